@@ -1,11 +1,16 @@
+use crate::escriptura;
+use crate::informe::{SyncJoc, SyncReport};
 use crate::pg_api::*;
+use crate::videojoc::conflicte::{self, PoliticaConflicte, Resolucio};
+use crate::videojoc::filtre::{self, Filtre};
+use crate::videojoc::partida_guardada::PartidaGuardada;
 use crate::videojoc::*;
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::fs;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Read;
 use std::path::PathBuf;
+use std::time::Instant;
 
 pub struct CliPG {
     pub api: Box<dyn PartidesGuardadesAPI>,
@@ -17,16 +22,28 @@ pub struct CliPG {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CliPgConfig {
     pub server: ServerConfig,
+    // Nom d'aquest dispositiu pels conflictes que mantenen totes dues partides. Per defecte el nom de la maquina.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispositiu: Option<String>,
     pub videojocs_habilitats: VideojocConfigList,
 }
 impl CliPgConfig {
+    pub fn nom_dispositiu(&self) -> String {
+        match &self.dispositiu {
+            Some(dispositiu) => conflicte::netejar_nom_dispositiu(dispositiu),
+            None => conflicte::nom_dispositiu(),
+        }
+    }
     fn default() -> Self {
         CliPgConfig {
             server: ServerConfig {
                 url: "http://localhost:8000".to_string(),
                 usuari: "admin".to_string(),
                 contrasenya: "admin".to_string(),
+                ca_bundle: None,
+                empremta: None,
             },
+            dispositiu: None,
             videojocs_habilitats: VideojocConfigList { list: Vec::new() },
         }
     }
@@ -36,16 +53,67 @@ pub struct ServerConfig {
     pub url: String,
     pub usuari: String,
     pub contrasenya: String,
+    // Fitxer PEM amb certificats de CA addicionals, per servidors amb certificats propis
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<String>,
+    // SHA-256 del certificat del servidor: si hi es, nomes s'accepta aquest certificat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub empremta: Option<String>,
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VideojocConfigList {
     pub list: Vec<VideojocConfig>,
 }
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct VideojocConfig {
     pub nom: String,
     pub path: String,
     pub partides_guardades: Vec<PartidaGuardadaConfig>,
+    // Que fer quan una partida s'ha modificat a local i al servidor (veure videojoc/conflicte.rs)
+    #[serde(default, skip_serializing_if = "PoliticaConflicte::es_defecte")]
+    pub conflict_policy: PoliticaConflicte,
+    // Patrons dels fitxers que son partides (veure videojoc/filtre.rs). Si son els per defecte no s'escriuen.
+    #[serde(default = "filtre::include_per_defecte", skip_serializing_if = "es_include_per_defecte")]
+    pub include: Vec<String>,
+    #[serde(default = "filtre::exclude_per_defecte", skip_serializing_if = "es_exclude_per_defecte")]
+    pub exclude: Vec<String>,
+}
+impl VideojocConfig {
+    pub fn filtre(&self) -> Filtre {
+        Filtre::new(&self.include, &self.exclude).unwrap_or_else(|err| {
+            // Nomes si s'ha editat la configuracio a ma: set_filtres no guarda patrons invalids
+            eprintln!("{}: {err}. Es fan servir els patrons per defecte.", self.nom);
+            Filtre::default()
+        })
+    }
+}
+fn es_include_per_defecte(include: &Vec<String>) -> bool {
+    *include == filtre::include_per_defecte()
+}
+fn es_exclude_per_defecte(exclude: &Vec<String>) -> bool {
+    *exclude == filtre::exclude_per_defecte()
+}
+// Una partida d'un joc amb la copia local i la del servidor (si n'hi ha)
+pub struct EstatPartida {
+    pub nom: String,
+    pub local: Option<PartidaGuardada>,
+    pub remot: Option<PartidaGuardada>,
+}
+impl EstatPartida {
+    pub fn estat(&self) -> &'static str {
+        match (&self.local, &self.remot) {
+            (Some(local), Some(remot)) if local.hash == remot.hash => "sincronitzada",
+            (Some(_), Some(_)) => "diferent",
+            (Some(_), None) => "nomes_local",
+            _ => "nomes_remot",
+        }
+    }
+}
+// Partida modificada a local i al servidor que espera que es resolgui a ma
+pub struct ConflictePendent {
+    pub joc: String,
+    pub local: PartidaGuardada,
+    pub remot: PartidaGuardada,
 }
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct PartidaGuardadaConfig {
@@ -61,8 +129,15 @@ impl CliPG {
         let config_path = config_path.unwrap_or_else(Self::get_config_path);
         let config = Self::load_or_create_config(Some(config_path.clone()));
         let credencials: (String, String, String) = Self::get_credentials(&config.server);
+        let mut api = PgAPI::new(credencials.0, credencials.1, credencials.2).with_dispositiu(config.nom_dispositiu());
+        if let Some(ca_bundle) = &config.server.ca_bundle {
+            api = api.with_ca_bundle(ca_bundle.clone());
+        }
+        if let Some(empremta) = &config.server.empremta {
+            api = api.with_empremta(empremta.clone());
+        }
         CliPG {
-            api: Box::new(PgAPI::new(credencials.0, credencials.1, credencials.2)),
+            api: Box::new(api),
             vjocs: Vec::new(),
             config,
             config_path: config_path.to_str().unwrap().to_string(),
@@ -71,20 +146,46 @@ impl CliPG {
     pub fn afegir_joc(&mut self, path: String, nom_joc: Option<String>) -> Result<(), String> {
         let pbuf = PathBuf::from(&path);
         if pbuf.exists() {
+            let nom = nom_joc.unwrap_or_else(|| pbuf.file_name().unwrap().to_str().unwrap().to_string());
+            // Els jocs es seleccionen pel nom (sync, GUI, servidor): no n'hi pot haver dos amb el mateix
+            if self.config.videojocs_habilitats.list.iter().any(|vc| vc.nom == nom) {
+                return Err(format!("Ja hi ha un joc habilitat amb el nom \"{}\".", nom));
+            }
             let v = VideojocConfig {
-                nom: nom_joc.unwrap_or_else(|| pbuf.file_name().unwrap().to_str().unwrap().to_string()),
+                nom,
                 path,
                 partides_guardades: Vec::new(),
+                conflict_policy: PoliticaConflicte::default(),
+                include: filtre::include_per_defecte(),
+                exclude: filtre::exclude_per_defecte(),
             };
-            if !self.config.videojocs_habilitats.list.contains(&v) {
-                self.config.videojocs_habilitats.list.push(v);
-                Self::save_config(&self.config, Some(PathBuf::from(self.config_path.clone())));
-            }
+            self.config.videojocs_habilitats.list.push(v);
+            Self::save_config(&self.config, Some(PathBuf::from(self.config_path.clone())));
             Ok(())
         } else {
             Err(format!("\"{}\" no existeix.", path))
         }
     }
+    pub fn actualitzar_joc(&mut self, nom_joc: &str, path: String, politica: PoliticaConflicte, include: Vec<String>, exclude: Vec<String>) -> Result<(), String> {
+        // Edita el joc al seu lloc de la llista: es conserva l'estat de les partides ja sincronitzades
+        if !PathBuf::from(&path).exists() {
+            return Err(format!("\"{}\" no existeix.", path));
+        }
+        Filtre::new(&include, &exclude)?;
+        let Some(vc) = self.config.videojocs_habilitats.list.iter_mut().find(|vc| vc.nom == nom_joc) else {
+            return Err(format!("\"{}\" no es un joc habilitat.", nom_joc));
+        };
+        // L'estat guardat es de les partides de l'altra carpeta
+        if vc.path != path {
+            vc.partides_guardades.clear();
+        }
+        vc.path = path;
+        vc.conflict_policy = politica;
+        vc.include = include;
+        vc.exclude = exclude;
+        Self::save_config(&self.config, Some(PathBuf::from(self.config_path.clone())));
+        Ok(())
+    }
     pub fn eliminar_joc(&mut self, videojoc_id: String) -> Result<(), String> {
         let mut i = 0;
         let mut trobat = false;
@@ -99,6 +200,119 @@ impl CliPG {
         }
         if trobat { Ok(()) } else { Err(format!("\"{}\" no era un joc habilitat.", videojoc_id)) }
     }
+    pub fn set_politica_conflictes(&mut self, nom_joc: &str, politica: PoliticaConflicte) -> Result<(), String> {
+        let Some(vc) = self.config.videojocs_habilitats.list.iter_mut().find(|vc| vc.nom == nom_joc) else {
+            return Err(format!("\"{}\" no es un joc habilitat.", nom_joc));
+        };
+        vc.conflict_policy = politica;
+        Self::save_config(&self.config, Some(PathBuf::from(self.config_path.clone())));
+        Ok(())
+    }
+    pub fn set_filtres(&mut self, nom_joc: &str, include: Vec<String>, exclude: Vec<String>) -> Result<(), String> {
+        // Nomes es guarden si tots els patrons son valids
+        Filtre::new(&include, &exclude)?;
+        let Some(vc) = self.config.videojocs_habilitats.list.iter_mut().find(|vc| vc.nom == nom_joc) else {
+            return Err(format!("\"{}\" no es un joc habilitat.", nom_joc));
+        };
+        vc.include = include;
+        vc.exclude = exclude;
+        Self::save_config(&self.config, Some(PathBuf::from(self.config_path.clone())));
+        Ok(())
+    }
+    pub fn reanomenar_joc(&mut self, nom_joc: &str, nou_nom: String) -> Result<(), String> {
+        if self.config.videojocs_habilitats.list.iter().any(|vc| vc.nom == nou_nom) {
+            return Err(format!("Ja hi ha un joc habilitat amb el nom \"{}\".", nou_nom));
+        }
+        let Some(vc) = self.config.videojocs_habilitats.list.iter_mut().find(|vc| vc.nom == nom_joc) else {
+            return Err(format!("\"{}\" no es un joc habilitat.", nom_joc));
+        };
+        // Al servidor les partides van pel nom del joc: amb el nom nou es tornen a pujar totes.
+        // Oblidem les ja sincronitzades perque no es tractin com a eliminades al servidor.
+        vc.nom = nou_nom;
+        vc.partides_guardades.clear();
+        Self::save_config(&self.config, Some(PathBuf::from(self.config_path.clone())));
+        Ok(())
+    }
+    pub fn partides_joc(&self, nom_joc: &str) -> Result<Vec<EstatPartida>, String> {
+        let Some(vc) = self.config.videojocs_habilitats.list.iter().find(|vc| vc.nom == nom_joc) else {
+            return Err(format!("\"{}\" no es un joc habilitat.", nom_joc));
+        };
+        let mut joc = self.videojoc_de_config(vc);
+        if joc.local_folder.exists() {
+            joc.load_partides_locals();
+        }
+        joc.fetch_partides_remotes(&self.api).map_err(|e| e.to_string())?;
+        let mut partides: Vec<EstatPartida> = joc
+            .partides_locals
+            .into_iter()
+            .map(|local| EstatPartida {
+                nom: local.nom.to_string_lossy().to_string(),
+                local: Some(local),
+                remot: None,
+            })
+            .collect();
+        for remot in joc.partides_remotes.into_iter() {
+            let nom = remot.nom.to_string_lossy().to_string();
+            match partides.iter_mut().find(|p| p.nom == nom) {
+                Some(p) => p.remot = Some(remot),
+                None => partides.push(EstatPartida { nom, local: None, remot: Some(remot) }),
+            }
+        }
+        partides.sort_by(|a, b| a.nom.cmp(&b.nom));
+        Ok(partides)
+    }
+    pub fn conflictes_pendents(&mut self) -> Result<Vec<ConflictePendent>, String> {
+        self.load_local_jocs();
+        let mut conflictes = Vec::new();
+        for joc in self.vjocs.iter_mut() {
+            let nom_joc = joc.nom.to_str().unwrap().to_string();
+            let parelles = joc.conflictes_pendents(&self.api).map_err(|e| format!("{nom_joc}: {e}"))?;
+            for (local, remot) in parelles {
+                conflictes.push(ConflictePendent { joc: nom_joc.clone(), local, remot });
+            }
+        }
+        Ok(conflictes)
+    }
+    pub fn resoldre_conflicte(&mut self, nom_joc: &str, partida: &str, resolucio: Resolucio) -> Result<(), String> {
+        // Despres de resoldre'l, la local i la remota son iguals: el proxim sync ho apunta a partides_guardades
+        let conflictes = self.conflictes_pendents()?;
+        let Some(conflicte) = conflictes.iter().find(|c| c.joc == nom_joc && c.local.nom == partida) else {
+            return Err(format!("La partida \"{}\" de \"{}\" no te cap conflicte pendent.", partida, nom_joc));
+        };
+        let joc = self.vjocs.iter().find(|v| v.nom == nom_joc).unwrap();
+        joc.resoldre_conflicte(&conflicte.local, &conflicte.remot, resolucio, &self.api).map_err(|e| e.to_string())
+    }
+    fn videojoc_de_config(&self, vc: &VideojocConfig) -> Videojoc {
+        Videojoc::new(vc.path.clone())
+            .with_nom(vc.nom.clone())
+            .with_partides_guardades_list(&vc.partides_guardades)
+            .with_politica_conflictes(vc.conflict_policy)
+            .with_filtre(vc.filtre())
+            .with_dispositiu(self.config.nom_dispositiu())
+    }
+    fn get_partida_joc(&self, nom_joc: &str, partida: &str) -> Result<PartidaGuardada, String> {
+        // `partida` es el nom relatiu de la partida dins la carpeta del joc (p.ex. "perfil 1/save1.sav")
+        let vc = self.config.videojocs_habilitats.list.iter().find(|vc| vc.nom == nom_joc);
+        let Some(vc) = vc else {
+            return Err(format!("\"{}\" no es un joc habilitat.", nom_joc));
+        };
+        let v = Videojoc::new(vc.path.clone()).with_nom(vc.nom.clone());
+        let path = PathBuf::from(&vc.path).join(partida);
+        Ok(PartidaGuardada::new(path.to_str().unwrap().to_string()).with_videojoc(&v))
+    }
+    pub fn historial_partida(&self, nom_joc: &str, partida: &str) -> Result<Vec<VersioPartidaGuardada>, String> {
+        let pg = self.get_partida_joc(nom_joc, partida)?;
+        self.api.get_versions_partida_guardada(&pg).map_err(|e| e.to_string())
+    }
+    pub fn restaurar_partida(&self, nom_joc: &str, partida: &str, versio_id: &str) -> Result<(), String> {
+        // Nomes es restaura al servidor: la seguent sincronitzacio descarrega la versio restaurada
+        let pg = self.get_partida_joc(nom_joc, partida)?;
+        let versions = self.api.get_versions_partida_guardada(&pg).map_err(|e| e.to_string())?;
+        if !versions.iter().any(|v| v.id == versio_id) {
+            return Err(format!("La partida \"{}\" de \"{}\" no te la versio \"{}\".", partida, nom_joc, versio_id));
+        }
+        self.api.restaurar_versio_partida_guardada(&pg, versio_id).map_err(|e| e.to_string())
+    }
     fn get_config_path() -> PathBuf {
         let proj_dirs = ProjectDirs::from("com", "bcedu", "CliPG").expect("No s'han pogut obtenir els directoris");
         proj_dirs.config_dir().join("config.toml")
@@ -135,69 +349,125 @@ impl CliPG {
         } else {
             cpath = path.unwrap();
         }
-        if let Some(dir) = cpath.parent() {
-            fs::create_dir_all(dir).unwrap();
-        }
         let toml = toml::to_string_pretty(config).unwrap();
-        let mut f = File::create(&cpath).unwrap();
-        f.write_all(toml.as_bytes()).unwrap();
-        f.sync_all().unwrap();
-        drop(f);
+        escriptura::escriure(&cpath, toml.as_bytes()).unwrap();
     }
     pub fn load_local_jocs(&mut self) -> Vec<VideojocConfig> {
-        self.vjocs = Vec::new();
+        let mut vjocs = Vec::new();
         let mut error_jocs = Vec::new();
         for v in self.config.videojocs_habilitats.list.iter() {
             let path = PathBuf::from(&v.path);
             if path.exists() {
-                self.vjocs.push(
-                    Videojoc::new(path.to_str().unwrap().to_string())
-                        .with_nom(v.nom.clone())
-                        .with_partides_guardades_list(&v.partides_guardades),
-                )
+                vjocs.push(self.videojoc_de_config(v))
             } else {
                 error_jocs.push(VideojocConfig {
                     nom: v.nom.clone(),
                     path: v.path.clone(),
                     partides_guardades: Vec::new(),
+                    conflict_policy: v.conflict_policy,
+                    include: v.include.clone(),
+                    exclude: v.exclude.clone(),
                 })
             }
         }
+        self.vjocs = vjocs;
         error_jocs
     }
-    pub fn sync_joc(&self, joc: &mut Videojoc, test_mode: bool) -> String {
-        let joc_res = joc.sync(&self.api, test_mode);
-        format!("* {}:\n{joc_res}", joc.nom.clone().to_str().unwrap())
+    pub fn sync_joc(&self, joc: &mut Videojoc, test_mode: bool) -> SyncJoc {
+        joc.sync(&self.api, test_mode)
     }
-    pub fn sync_all(&mut self, test_mode: bool) -> String {
-        let mut res = String::new();
-        let mut new_config = CliPgConfig {
-            server: self.config.server.clone(),
-            videojocs_habilitats: VideojocConfigList { list: Vec::new() },
-        };
+    pub fn sync_all(&mut self, test_mode: bool) -> SyncReport {
+        // Els jocs amb la carpeta que no existeix no es sincronitzen, pero es queden a la configuracio
         self.load_local_jocs();
-        for v in self.vjocs.iter() {
-            let mut updated_v = Videojoc::from(v);
-            let joc_res = self.sync_joc(&mut updated_v, test_mode);
-            res.push_str(&format!("\n{}", joc_res.as_str()));
-            new_config.videojocs_habilitats.list.push(VideojocConfig {
-                nom: updated_v.nom.to_str().unwrap().to_string().clone(),
-                path: updated_v.local_folder.to_str().unwrap().to_string().clone(),
-                partides_guardades: updated_v.get_partides_guardades_list(),
-            });
-        }
-        if !test_mode {
-            Self::save_config(&new_config, Some(PathBuf::from(self.config_path.clone())));
-        }
-        self.config = new_config;
-        res.to_string()
+        let noms: Vec<String> = self.vjocs.iter().map(|v| v.nom.to_str().unwrap().to_string()).collect();
+        self.sync_noms(&noms, test_mode)
+    }
+    pub fn sync_jocs(&mut self, patrons: &[String], test_mode: bool) -> Result<SyncReport, String> {
+        let noms = self.seleccionar_jocs(patrons)?;
+        Ok(self.sync_noms(&noms, test_mode))
+    }
+    pub fn seleccionar_jocs(&self, patrons: &[String]) -> Result<Vec<String>, String> {
+        // Cada patro es el nom d'un joc o un glob (p.ex. "Total War*"). Es retornen en l'ordre de la configuracio.
+        let mut seleccionats = vec![false; self.config.videojocs_habilitats.list.len()];
+        for patro in patrons.iter() {
+            // Un nom exacte no es tracta com a glob: els noms poden tenir "[" o "*"
+            if let Some(i) = self.config.videojocs_habilitats.list.iter().position(|vc| vc.nom == *patro) {
+                seleccionats[i] = true;
+                continue;
+            }
+            let glob = glob::Pattern::new(patro).map_err(|e| format!("\"{}\" no es un patro valid: {}", patro, e))?;
+            let mut trobat = false;
+            for (i, vc) in self.config.videojocs_habilitats.list.iter().enumerate() {
+                if glob.matches(&vc.nom) {
+                    seleccionats[i] = true;
+                    trobat = true;
+                }
+            }
+            if !trobat {
+                return Err(format!("\"{}\" no es cap joc habilitat.", patro));
+            }
+        }
+        Ok(self
+            .config
+            .videojocs_habilitats
+            .list
+            .iter()
+            .zip(seleccionats)
+            .filter(|(_, seleccionat)| *seleccionat)
+            .map(|(vc, _)| vc.nom.clone())
+            .collect())
+    }
+    fn sync_noms(&mut self, noms: &[String], test_mode: bool) -> SyncReport {
+        // Nomes es toquen les entrades de la configuracio dels jocs sincronitzats: la resta es queden igual
+        let inici = Instant::now();
+        let mut res = SyncReport::default();
+        let mut sincronitzats = Vec::new();
+        for nom in noms.iter() {
+            let i = self.config.videojocs_habilitats.list.iter().position(|vc| vc.nom == *nom).unwrap();
+            let vc = &self.config.videojocs_habilitats.list[i];
+            if !PathBuf::from(&vc.path).exists() {
+                res.jocs.push(SyncJoc {
+                    error: Some(format!("La carpeta \"{}\" no existeix.", vc.path)),
+                    ..SyncJoc::new(nom)
+                });
+                continue;
+            }
+            let path = vc.path.clone();
+            let mut joc = self.videojoc_de_config(vc);
+            res.jocs.push(self.sync_joc(&mut joc, test_mode));
+            if !test_mode {
+                self.config.videojocs_habilitats.list[i].partides_guardades = joc.get_partides_guardades_list();
+                sincronitzats.push((nom.clone(), path));
+            }
+        }
+        if !test_mode && !noms.is_empty() {
+            // La GUI, el dimoni o un altre `clipg` poden haver canviat la configuracio mentre sincronitzavem: es torna
+            // a llegir del disc i nomes s'hi posa l'estat de les partides dels jocs que encara hi son amb la mateixa carpeta
+            let conf_path = PathBuf::from(self.config_path.clone());
+            if conf_path.exists() {
+                let mut config = Self::load_or_create_config(Some(conf_path.clone()));
+                for (nom, path) in sincronitzats {
+                    let sincronitzat = self.config.videojocs_habilitats.list.iter().find(|vc| vc.nom == nom).unwrap();
+                    if let Some(vc) = config.videojocs_habilitats.list.iter_mut().find(|vc| vc.nom == nom && vc.path == path) {
+                        vc.partides_guardades = sincronitzat.partides_guardades.clone();
+                    }
+                }
+                self.config = config;
+            }
+            Self::save_config(&self.config, Some(conf_path));
+        }
+        res.durada = inici.elapsed();
+        res
     }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::cli_pg::{CliPG, CliPgConfig, Videojoc, VideojocConfig};
-    use crate::pg_api::{PartidesGuardadesAPI, PgAPI};
+    use crate::cli_pg::{CliPG, CliPgConfig, PartidaGuardadaConfig, Videojoc, VideojocConfig};
+    use crate::informe::SyncAction;
+    use crate::pg_api::{PartidesGuardadesAPI, PgAPI, PgApiError, VersioPartidaGuardada};
+    use crate::videojoc::conflicte::PoliticaConflicte;
+    use crate::videojoc::filtre;
     use crate::videojoc::partida_guardada::PartidaGuardada;
     use std::ffi::OsString;
     use std::fs;
@@ -206,13 +476,13 @@ pub mod tests {
     use std::path::PathBuf;
     pub struct FakeAPI_fase1;
     impl PartidesGuardadesAPI for FakeAPI_fase1 {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/test_sync/Joc/save1.txt");
             let p1 = PartidaGuardada {
@@ -220,28 +490,36 @@ pub mod tests {
                 nom: OsString::from("save1.txt"),
                 path: path,
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "8c2024bc30db3df9f66051705833d6672378d1696e9aa2bc22c1edaca23edc25".to_string(),
             };
             v.push(p1);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
             PgAPI::new("url".to_string(), "usuari".to_string(), "contrassenya".to_string())._post_partida_guardada(partida_guardada);
+            Ok(())
+        }
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
-            "Pastanaga bullida@".as_bytes().to_vec()
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("Pastanaga bullida@".as_bytes())?;
+            Ok(())
         }
     }
     pub struct FakeAPI_fase2;
     impl PartidesGuardadesAPI for FakeAPI_fase2 {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/test_sync/Joc/save1.txt");
             let p1 = PartidaGuardada {
@@ -249,28 +527,36 @@ pub mod tests {
                 nom: OsString::from("save1.txt"),
                 path: path,
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "791b6edec6ec1b075dbbe012a3cebf89b75541b5c32caf36230e0df1050f8639".to_string(),
             };
             v.push(p1);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
             PgAPI::new("url".to_string(), "usuari".to_string(), "contrassenya".to_string())._post_partida_guardada(partida_guardada);
+            Ok(())
         }
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
-            "Pastanaga bullida@2 la venganza".as_bytes().to_vec()
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("Pastanaga bullida@2 la venganza".as_bytes())?;
+            Ok(())
         }
     }
     pub struct FakeAPI_fase4;
     impl PartidesGuardadesAPI for FakeAPI_fase4 {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/test_sync/Joc/save3.txt");
             let p1 = PartidaGuardada {
@@ -278,28 +564,36 @@ pub mod tests {
                 nom: OsString::from("save3.txt"),
                 path: path,
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "fa7f7d6422a91afca0eedfc15dbb4f27286f14253624c5758314af03c786afc4".to_string(),
             };
             v.push(p1);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
             PgAPI::new("url".to_string(), "usuari".to_string(), "contrassenya".to_string())._post_partida_guardada(partida_guardada);
+            Ok(())
         }
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
-            "Pastanaga bullida@ 3 sl retrno".as_bytes().to_vec()
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("Pastanaga bullida@ 3 sl retrno".as_bytes())?;
+            Ok(())
         }
     }
     pub struct FakeAPI_fase5;
     impl PartidesGuardadesAPI for FakeAPI_fase5 {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/test_sync/Joc/save3.txt");
             let p1 = PartidaGuardada {
@@ -307,6 +601,10 @@ pub mod tests {
                 nom: OsString::from("save3.txt"),
                 path: path,
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "1befaf03995bb41eaa9dc50122342c92a85a28073663a7a55d2c82773422ab5e".to_string(),
             };
             v.push(p1);
@@ -316,30 +614,38 @@ pub mod tests {
                 nom: OsString::from("save4.txt"),
                 path: path,
                 timestamp: 0,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "12ee21760f19253fca62f5d0cdf480d1477c37300e56c2af141bcf35226a89b3".to_string(),
             };
             v.push(p1);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
             PgAPI::new("url".to_string(), "usuari".to_string(), "contrassenya".to_string())._post_partida_guardada(partida_guardada);
+            Ok(())
+        }
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
-            "save 4 alt 2".as_bytes().to_vec()
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("save 4 alt 2".as_bytes())?;
+            Ok(())
         }
     }
     pub struct FakeAPI_fase6 {
         pub bck_name: String,
     }
     impl PartidesGuardadesAPI for FakeAPI_fase6 {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/test_sync/Joc/save3.txt");
             let p1 = PartidaGuardada {
@@ -347,6 +653,10 @@ pub mod tests {
                 nom: OsString::from("save3.txt"),
                 path: path,
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "1befaf03995bb41eaa9dc50122342c92a85a28073663a7a55d2c82773422ab5e".to_string(),
             };
             v.push(p1);
@@ -355,7 +665,11 @@ pub mod tests {
                 videojoc: "Joc".to_string(),
                 nom: OsString::from("save4.txt"),
                 path: path,
-                timestamp: 999999999,
+                timestamp: 4000000000,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "0cc348fdba35a87c05b75232755f5e4d63e7316c2e9c0465841784e00d91aed7".to_string(),
             };
             v.push(p1);
@@ -364,31 +678,39 @@ pub mod tests {
                 videojoc: "Joc".to_string(),
                 nom: OsString::from(self.bck_name.clone()),
                 path: path,
-                timestamp: 999999999,
+                timestamp: 4000000000,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "6326239b272139f4aeba000178656f85ab10e7a74b0effbdcfdaf028241111a9".to_string(),
             };
             v.push(p1);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
             PgAPI::new("url".to_string(), "usuari".to_string(), "contrassenya".to_string())._post_partida_guardada(partida_guardada);
+            Ok(())
         }
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
-            "save 4 alt 2".as_bytes().to_vec()
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("save 4 alt 2".as_bytes())?;
+            Ok(())
         }
     }
     pub struct FakeAPI_fase7 {
         pub bck_name: String,
     }
     impl PartidesGuardadesAPI for FakeAPI_fase7 {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/test_sync/Joc/save3.txt");
             let p1 = PartidaGuardada {
@@ -396,6 +718,10 @@ pub mod tests {
                 nom: OsString::from("save3.txt"),
                 path: path,
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "1befaf03995bb41eaa9dc50122342c92a85a28073663a7a55d2c82773422ab5e".to_string(),
             };
             v.push(p1);
@@ -404,7 +730,11 @@ pub mod tests {
                 videojoc: "Joc".to_string(),
                 nom: OsString::from(self.bck_name.clone()),
                 path: path,
-                timestamp: 999999999,
+                timestamp: 4000000000,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "6326239b272139f4aeba000178656f85ab10e7a74b0effbdcfdaf028241111a9".to_string(),
             };
             v.push(p1);
@@ -413,18 +743,61 @@ pub mod tests {
                 videojoc: "Joc".to_string(),
                 nom: OsString::from("save4.txt"),
                 path: path,
-                timestamp: 999999999,
+                timestamp: 4000000000,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "patata".to_string(),
             };
             v.push(p1);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
             PgAPI::new("url".to_string(), "usuari".to_string(), "contrassenya".to_string())._post_partida_guardada(partida_guardada);
+            Ok(())
         }
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
-            "save 4 alt 22222222".as_bytes().to_vec()
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("save 4 alt 22222222".as_bytes())?;
+            Ok(())
+        }
+    }
+    pub struct FakeAPIHistorial;
+    impl PartidesGuardadesAPI for FakeAPIHistorial {
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
+        }
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
+            Ok(Vec::new())
+        }
+        fn post_partida_guardada(&self, _: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn delete_partida_guardada(&self, _: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, _: &PartidaGuardada, _: &mut dyn Write) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_versions_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<Vec<VersioPartidaGuardada>, PgApiError> {
+            assert_eq!(partida_guardada.videojoc, "Napoleon TW");
+            assert_eq!(partida_guardada.nom, "perfil 1/save1.sav");
+            Ok(vec![VersioPartidaGuardada {
+                id: "1700000000_000000000_aa".to_string(),
+                timestamp: 1700000000,
+                hash: "aa".to_string(),
+                mida: 7,
+            }])
+        }
+        fn restaurar_versio_partida_guardada(&self, _: &PartidaGuardada, versio_id: &str) -> Result<(), PgApiError> {
+            assert_eq!(versio_id, "1700000000_000000000_aa");
+            Ok(())
         }
     }
     fn get_dummy_cli_pg() -> CliPG {
@@ -437,11 +810,17 @@ pub mod tests {
             nom: "Napoleon TW".to_string(),
             path: "/home/patata/Napoleon TW".to_string(),
             partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::default(),
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
         });
         config.videojocs_habilitats.list.push(VideojocConfig {
             nom: "Space Marine 3".to_string(),
             path: "/home/patata/Space Marine 3".to_string(),
             partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::default(),
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
         });
         CliPG {
             api: Box::new(PgAPI::new(url.clone(), usuari.clone(), contrassenya.clone())),
@@ -461,16 +840,25 @@ pub mod tests {
             nom: "Mount & blade Warband 2".to_string(),
             path: format!("{}/Mount & blade Warband 2", test_path.to_str().unwrap().clone()),
             partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::default(),
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
         });
         config.videojocs_habilitats.list.push(VideojocConfig {
             nom: "Napoleón TW HD".to_string(),
             path: format!("{}/Napoleón TW HD", test_path.to_str().unwrap().clone()),
             partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::default(),
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
         });
         config.videojocs_habilitats.list.push(VideojocConfig {
             nom: "Total War 40k".to_string(),
             path: format!("{}/Total War 40k", test_path.to_str().unwrap().clone()),
             partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::default(),
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
         });
         CliPG {
             api: Box::new(PgAPI::new(url.clone(), usuari.clone(), contrassenya.clone())),
@@ -548,6 +936,150 @@ partides_guardades = []
         assert_eq!(cli.vjocs[2].nom, "Total War 40k");
     }
     #[test]
+    fn test_politica_conflictes() {
+        let mut cli = get_dummy_cli_pg();
+        let conf_path = std::env::temp_dir().join("clipg_test_politica_conflictes.toml");
+        cli.config_path = conf_path.to_str().unwrap().to_string();
+        cli.set_politica_conflictes("Space Marine 3", PoliticaConflicte::Manual).unwrap();
+        assert!(cli.set_politica_conflictes("Patata", PoliticaConflicte::Manual).is_err());
+        // Nomes es guarda si no es la de per defecte
+        let content = read_file_sync(cli.config_path.clone());
+        assert_eq!(content.matches("conflict_policy").count(), 1);
+        assert!(content.contains("conflict_policy = \"manual\""));
+        let config: CliPgConfig = toml::from_str(&content).unwrap();
+        assert_eq!(config.videojocs_habilitats.list[0].conflict_policy, PoliticaConflicte::MesRecentAmbCopia);
+        assert_eq!(config.videojocs_habilitats.list[1].conflict_policy, PoliticaConflicte::Manual);
+        std::fs::remove_file(conf_path).unwrap();
+    }
+    #[test]
+    fn test_set_filtres() {
+        let mut cli = get_dummy_cli_pg();
+        let conf_path = std::env::temp_dir().join("clipg_test_set_filtres.toml");
+        cli.config_path = conf_path.to_str().unwrap().to_string();
+        let exclude = vec!["bck_*".to_string(), "shadercache/**".to_string()];
+        cli.set_filtres("Space Marine 3", vec!["*.sav".to_string()], exclude.clone()).unwrap();
+        assert!(cli.set_filtres("Patata", Vec::new(), Vec::new()).is_err());
+        // Amb un patro invalid no es guarda res
+        assert!(cli.set_filtres("Space Marine 3", vec!["[sav".to_string()], Vec::new()).is_err());
+        // Nomes es guarden els que no son els per defecte
+        let content = read_file_sync(cli.config_path.clone());
+        assert_eq!(content.matches("include").count(), 1);
+        let config: CliPgConfig = toml::from_str(&content).unwrap();
+        assert_eq!(config.videojocs_habilitats.list[0].include, filtre::include_per_defecte());
+        assert_eq!(config.videojocs_habilitats.list[0].exclude, filtre::exclude_per_defecte());
+        assert_eq!(config.videojocs_habilitats.list[1].include, vec!["*.sav"]);
+        assert_eq!(config.videojocs_habilitats.list[1].exclude, exclude);
+        std::fs::remove_file(conf_path).unwrap();
+    }
+    #[test]
+    fn test_actualitzar_joc() {
+        let mut cli = get_dummy_cli_pg();
+        let conf_path = std::env::temp_dir().join("clipg_test_actualitzar_joc.toml");
+        cli.config_path = conf_path.to_str().unwrap().to_string();
+        let joc_path = env!("CARGO_MANIFEST_DIR").to_string();
+        let guardades = vec![PartidaGuardadaConfig {
+            path: "/home/patata/Napoleon TW/save1.sav".to_string(),
+            hash: "aa".to_string(),
+        }];
+        cli.config.videojocs_habilitats.list[0].partides_guardades = guardades.clone();
+        cli.config.videojocs_habilitats.list[0].path = joc_path.clone();
+        assert!(cli.actualitzar_joc("Patata", joc_path.clone(), PoliticaConflicte::Manual, Vec::new(), Vec::new()).is_err());
+        assert!(
+            cli.actualitzar_joc("Napoleon TW", "/home/patata/no existeix".to_string(), PoliticaConflicte::Manual, Vec::new(), Vec::new())
+                .is_err()
+        );
+        assert!(
+            cli.actualitzar_joc("Napoleon TW", joc_path.clone(), PoliticaConflicte::Manual, vec!["[sav".to_string()], Vec::new())
+                .is_err()
+        );
+        // Es modifica l'entrada existent sense duplicar-la i sense perdre les partides sincronitzades
+        cli.actualitzar_joc("Napoleon TW", joc_path.clone(), PoliticaConflicte::Manual, vec!["*.sav".to_string()], Vec::new())
+            .unwrap();
+        let config: CliPgConfig = toml::from_str(&read_file_sync(cli.config_path.clone())).unwrap();
+        assert_eq!(config.videojocs_habilitats.list.len(), 2);
+        let vc = &config.videojocs_habilitats.list[0];
+        assert_eq!(vc.nom, "Napoleon TW");
+        assert_eq!(vc.conflict_policy, PoliticaConflicte::Manual);
+        assert_eq!(vc.include, vec!["*.sav".to_string()]);
+        assert!(vc.exclude.is_empty());
+        assert_eq!(vc.partides_guardades, guardades);
+        // Amb una altra carpeta l'estat sincronitzat ja no serveix
+        let altre_path = std::env::temp_dir().to_str().unwrap().to_string();
+        cli.actualitzar_joc("Napoleon TW", altre_path.clone(), PoliticaConflicte::Manual, Vec::new(), Vec::new()).unwrap();
+        assert_eq!(cli.config.videojocs_habilitats.list[0].path, altre_path);
+        assert!(cli.config.videojocs_habilitats.list[0].partides_guardades.is_empty());
+        std::fs::remove_file(conf_path).unwrap();
+    }
+    #[test]
+    fn test_reanomenar_joc() {
+        let mut cli = get_dummy_cli_pg();
+        let conf_path = std::env::temp_dir().join("clipg_test_reanomenar_joc.toml");
+        cli.config_path = conf_path.to_str().unwrap().to_string();
+        cli.config.videojocs_habilitats.list[0].partides_guardades.push(PartidaGuardadaConfig {
+            path: "save1.sav".to_string(),
+            hash: "aa".to_string(),
+        });
+        assert!(cli.reanomenar_joc("Patata", "Napoleon".to_string()).is_err());
+        assert!(cli.reanomenar_joc("Napoleon TW", "Space Marine 3".to_string()).is_err());
+        cli.reanomenar_joc("Napoleon TW", "Napoleon".to_string()).unwrap();
+        // Amb el nom nou es tornen a pujar totes les partides
+        let config: CliPgConfig = toml::from_str(&read_file_sync(cli.config_path.clone())).unwrap();
+        assert_eq!(config.videojocs_habilitats.list[0].nom, "Napoleon");
+        assert_eq!(config.videojocs_habilitats.list[0].path, "/home/patata/Napoleon TW");
+        assert!(config.videojocs_habilitats.list[0].partides_guardades.is_empty());
+        std::fs::remove_file(conf_path).unwrap();
+    }
+    #[test]
+    fn test_sync_jocs() {
+        let mut cli = get_dummy_cli_pg();
+        let conf_path = std::env::temp_dir().join("clipg_test_sync_jocs.toml");
+        cli.config_path = conf_path.to_str().unwrap().to_string();
+        assert!(cli.sync_jocs(&["Patata".to_string()], true).is_err());
+        // Un joc sense carpeta queda com a error a l'informe i la resta de la configuracio no es toca
+        let res = cli.sync_jocs(&["Space Marine 3".to_string()], false).unwrap();
+        assert_eq!(res.jocs.len(), 1);
+        assert_eq!(res.jocs[0].nom, "Space Marine 3");
+        assert!(res.jocs[0].error.is_some());
+        let config: CliPgConfig = toml::from_str(&read_file_sync(cli.config_path.clone())).unwrap();
+        assert_eq!(config.videojocs_habilitats.list.len(), 2);
+        // Seleccio per nom o per glob, sense repetits i en l'ordre de la configuracio
+        assert_eq!(cli.seleccionar_jocs(&["Space*".to_string()]).unwrap(), vec!["Space Marine 3"]);
+        assert_eq!(cli.seleccionar_jocs(&["*".to_string(), "Napoleon TW".to_string()]).unwrap(), vec!["Napoleon TW", "Space Marine 3"]);
+        assert!(cli.seleccionar_jocs(&["Total War*".to_string()]).is_err());
+        assert!(cli.seleccionar_jocs(&["[".to_string()]).is_err());
+        // sync_all no treu de la configuracio els jocs que no s'han pogut sincronitzar
+        cli.sync_all(false);
+        assert_eq!(cli.config.videojocs_habilitats.list.len(), 2);
+        std::fs::remove_file(conf_path).unwrap();
+    }
+    #[test]
+    fn test_sync_conserva_canvis_de_config() {
+        let test_path = std::env::temp_dir().join("clipg_test_sync_conserva_config");
+        let _ = std::fs::remove_dir_all(&test_path);
+        let joc_path = test_path.join("Napoleon TW");
+        std::fs::create_dir_all(joc_path.join("perfil 1")).unwrap();
+        std::fs::write(joc_path.join("perfil 1/save1.sav"), "save1").unwrap();
+        let conf_path = test_path.join("conf.toml");
+        let mut cli = get_dummy_cli_pg();
+        cli.api = Box::new(FakeAPIHistorial);
+        cli.config_path = conf_path.to_str().unwrap().to_string();
+        cli.config.videojocs_habilitats.list[0].path = joc_path.to_str().unwrap().to_string();
+        CliPG::save_config(&cli.config, Some(conf_path.clone()));
+        // Mentre es sincronitza, un altre proces canvia la politica d'un joc i n'elimina un altre
+        let mut config_disc = CliPG::load_or_create_config(Some(conf_path.clone()));
+        config_disc.videojocs_habilitats.list[0].conflict_policy = PoliticaConflicte::PreferirLocal;
+        config_disc.videojocs_habilitats.list.pop();
+        CliPG::save_config(&config_disc, Some(conf_path.clone()));
+        let res = cli.sync_all(false);
+        assert_eq!(res.jocs.len(), 1);
+        let config: CliPgConfig = toml::from_str(&read_file_sync(cli.config_path.clone())).unwrap();
+        assert_eq!(config.videojocs_habilitats.list.len(), 1);
+        assert_eq!(config.videojocs_habilitats.list[0].conflict_policy, PoliticaConflicte::PreferirLocal);
+        assert_eq!(config.videojocs_habilitats.list[0].partides_guardades.len(), 1);
+        assert_eq!(cli.config.videojocs_habilitats.list, config.videojocs_habilitats.list);
+        std::fs::remove_dir_all(test_path).unwrap();
+    }
+    #[test]
     fn test_afegir_joc() {
         let mut cli = get_dummy_cli_pg();
         assert_eq!(cli.config.videojocs_habilitats.list.len(), 2);
@@ -603,9 +1135,11 @@ path = "/home/bcedu/Documents/Projectes/SincroPG/CliPG/tests/fixtures_cli_pg/pat
 partides_guardades = []
 "#
         );
-        // Un path repetit no fa res
+        // Un joc repetit, o un altre amb el mateix nom, no s'afegeix
         let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg/path a videojocs/Mount & blade Warband 2");
-        cli.afegir_joc(test_path.to_str().unwrap().to_string(), None);
+        assert!(cli.afegir_joc(test_path.to_str().unwrap().to_string(), None).is_err());
+        let altre_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_cli_pg");
+        assert!(cli.afegir_joc(altre_path.to_str().unwrap().to_string(), Some("Napoleon TW".to_string())).is_err());
         assert_eq!(cli.config.videojocs_habilitats.list.len(), 3);
         let res_cont = read_file_sync(cli.config_path.clone());
         assert_eq!(
@@ -633,6 +1167,18 @@ partides_guardades = []
         );
     }
     #[test]
+    fn test_historial_partida() {
+        let mut cli = get_dummy_cli_pg();
+        cli.api = Box::new(FakeAPIHistorial);
+        let versions = cli.historial_partida("Napoleon TW", "perfil 1/save1.sav").unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].hash, "aa");
+        assert!(cli.historial_partida("PATATA", "perfil 1/save1.sav").is_err());
+        // Nomes es poden restaurar versions que existeixen
+        assert!(cli.restaurar_partida("Napoleon TW", "perfil 1/save1.sav", "1700000000_000000000_aa").is_ok());
+        assert!(cli.restaurar_partida("Napoleon TW", "perfil 1/save1.sav", "1_000000000_bb").is_err());
+    }
+    #[test]
     fn test_eliminar_joc() {
         let mut cli = get_dummy_cli_pg();
         assert_eq!(cli.config.videojocs_habilitats.list.len(), 2);
@@ -755,9 +1301,12 @@ partides_guardades = []
          */
         // Fem sincronitzacio amb el fake_apiq ue ens diu que hi ha el save1.txt a remot
         clipg.api = Box::new(FakeAPI_fase1 {});
-        let result = clipg.sync_all(false);
+        let report = clipg.sync_all(false);
         // Revisem que el resum que ens retornen indica que s'ha descarregat el save1.txt
-        assert!(result.contains("⬇ Descarregar partida remota: save1.txt"));
+        assert!(report.to_string().contains("⬇ Descarregar partida remota: save1.txt"));
+        let partida = &report.jocs[0].partides[0];
+        assert_eq!((partida.nom.as_str(), &partida.accio), ("save1.txt", &SyncAction::Download { nova: true }));
+        assert!(!report.te_errors());
         // Revisem que el fitxer save1.txt existeix a local i el seu contingut
         let save_path = joc_path.join("save1.txt");
         assert!(save_path.exists());
@@ -796,7 +1345,7 @@ hash = "8c2024bc30db3df9f66051705833d6672378d1696e9aa2bc22c1edaca23edc25"
          */
         // NO ha canviat res
         clipg.api = Box::new(FakeAPI_fase1 {});
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             r#"
@@ -806,7 +1355,7 @@ hash = "8c2024bc30db3df9f66051705833d6672378d1696e9aa2bc22c1edaca23edc25"
         );
         clipg.api = Box::new(FakeAPI_fase2 {});
         // Ha canviat el save1.txt remot
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             r#"
@@ -850,7 +1399,7 @@ hash = "791b6edec6ec1b075dbbe012a3cebf89b75541b5c32caf36230e0df1050f8639"
         fs::remove_file(&joc_path.join("save1.txt")).unwrap();
         // Sincronitzem
         clipg.api = Box::new(FakeAPI_fase2 {});
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             r#"
@@ -891,7 +1440,7 @@ hash = "bdc0ff9c56ff25dc84cbc1b0217e4f55d9e0e58505dffb57dd6b9794a9fb1520"
          */
         // Sincronitzem. En remote s'ha eliminat save2 i creat save3
         clipg.api = Box::new(FakeAPI_fase4 {});
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             r#"
@@ -938,7 +1487,7 @@ hash = "1befaf03995bb41eaa9dc50122342c92a85a28073663a7a55d2c82773422ab5e"
         let save4_path = joc_path.join("save4.txt");
         std::fs::write(&save4_path, "save 4").unwrap();
         clipg.api = Box::new(FakeAPI_fase5 {});
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             r#"
@@ -983,16 +1532,18 @@ hash = "6326239b272139f4aeba000178656f85ab10e7a74b0effbdcfdaf028241111a9"
 "#,
             back_name.to_str().unwrap()
         );
-        assert!(config_content.contains(expect1.as_str()));
+        // Les copies bck_ no es sincronitzen (veure videojoc/filtre.rs): no es guarden a la configuracio
+        assert!(!config_content.contains(expect1.as_str()));
         // si ho has de retornar:
         back_name
     }
     fn test_full_process_fase_6(clipg: &mut CliPG, joc_path: &PathBuf, conf_path: &PathBuf, back_file: &PathBuf) {
         /*
          * PRE:
-         * El fitxer de configuracio te habilitat "Joc" amb 3 partides (save3.txt, save4.txt i "bck_save4.txt").
+         * El fitxer de configuracio te habilitat "Joc" amb 2 partides (save3.txt i save4.txt).
          * En local tenim el save3.txt, el save4.txt i el bck_save4.txt a "Joc".
          * En remot tenim el save3.txt i el save4.txt i el bck_save4.txt.
+         * El bck_save4.txt no es sincronitza: queda exclos pels patrons per defecte.
          * POST:
          * Fem una sincronitzacio que no fa res.
          * Verifiquem que efectivament no ha cambiat res en el conf.toml
@@ -1007,21 +1558,19 @@ hash = "6326239b272139f4aeba000178656f85ab10e7a74b0effbdcfdaf028241111a9"
         clipg.api = Box::new(FakeAPI_fase6 {
             bck_name: bck_name.clone().to_str().unwrap().to_string(),
         });
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             format!(
                 r#"
 * Joc:
-    ✔ Partida OK: {}
     ✔ Partida OK: save3.txt
     ✔ Partida OK: save4.txt
-"#,
-                bck_name.to_str().unwrap()
+"#
             ),
         );
         let config_content = read_file_sync(conf_path.to_str().unwrap().to_string());
-        // El back_save4 original
+        // El back_save4 original no es a la configuracio
         let expect1 = format!(
             r#"[[videojocs_habilitats.list.partides_guardades]]
 path = "/home/bcedu/Documents/Projectes/SincroPG/CliPG/tests/fixtures_cli_pg/test_sync/Joc/{}"
@@ -1029,7 +1578,7 @@ hash = "6326239b272139f4aeba000178656f85ab10e7a74b0effbdcfdaf028241111a9"
 "#,
             bck_name.to_str().unwrap()
         );
-        assert!(config_content.contains(expect1.as_str()));
+        assert!(!config_content.contains(expect1.as_str()));
         // El save3.txt original
         assert!(config_content.contains(
             r#"[[videojocs_habilitats.list.partides_guardades]]
@@ -1048,13 +1597,13 @@ hash = "0cc348fdba35a87c05b75232755f5e4d63e7316c2e9c0465841784e00d91aed7"
     fn test_full_process_fase_7(clipg: &mut CliPG, joc_path: &PathBuf, conf_path: &PathBuf, back_file: &PathBuf) {
         /*
          * PRE:
-         * El fitxer de configuracio te habilitat "Joc" amb 3 partides (save3.txt, save4.txt i "bck_save4.txt").
+         * El fitxer de configuracio te habilitat "Joc" amb 2 partides (save3.txt i save4.txt).
          * En local tenim el save3.txt, el save4.txt i el bck_save4.txt a "Joc".
          * En remot tenim el save3.txt i el save4.txt i el bck_save4.txt.
          * POST:
          * Modifiquem el save4.txt a local i remot. Sincronitzem:
          * EL save3.txt ha de seguir igual al tindre els amteixos hash.
-         * EL bck_save4.txt ha de seguir igual al tindre els amteixos hash.
+         * EL bck_save4.txt no es sincronitza: queda exclos pels patrons per defecte.
          * El save4.txt al tindre hash diferents en local i remot -> s'han de duplicar.
          * El que es renombra a "bck_" es el local ja que te un timestamp més petit.
          */
@@ -1066,17 +1615,15 @@ hash = "0cc348fdba35a87c05b75232755f5e4d63e7316c2e9c0465841784e00d91aed7"
         clipg.api = Box::new(FakeAPI_fase7 {
             bck_name: bck_name.clone().to_str().unwrap().to_string(),
         });
-        let result = clipg.sync_all(false);
+        let result = clipg.sync_all(false).to_string();
         assert_eq!(
             result,
             format!(
                 r#"
 * Joc:
-    ✔ Partida OK: {}
     ✔ Partida OK: save3.txt
     ⚠ Conflicte: save4.txt
-"#,
-                bck_name.to_str().unwrap()
+"#
             ),
         );
         // Verifiquem el contingut del conf.toml
@@ -1088,7 +1635,7 @@ path = "/home/bcedu/Documents/Projectes/SincroPG/CliPG/tests/fixtures_cli_pg/tes
 hash = "1befaf03995bb41eaa9dc50122342c92a85a28073663a7a55d2c82773422ab5e"
 "#
         ));
-        // El bck_save4 original
+        // El bck_save4 original no es a la configuracio
         let expect1 = format!(
             r#"[[videojocs_habilitats.list.partides_guardades]]
 path = "/home/bcedu/Documents/Projectes/SincroPG/CliPG/tests/fixtures_cli_pg/test_sync/Joc/{}"
@@ -1096,7 +1643,7 @@ hash = "6326239b272139f4aeba000178656f85ab10e7a74b0effbdcfdaf028241111a9"
 "#,
             bck_name.to_str().unwrap()
         );
-        assert!(config_content.contains(expect1.as_str()));
+        assert!(!config_content.contains(expect1.as_str()));
         // El save4.txt te un hash diferent, ja que es el nou
         let expect2 = format!(
             r#"[[videojocs_habilitats.list.partides_guardades]]
@@ -1105,8 +1652,8 @@ hash = "ab83203e18afd2f5cb7fbf83a2df2aa52ac399ea18dd5e3e27c6541d177a3417"
 "#,
         );
         assert!(config_content.contains(expect2.as_str()));
-        // El altre fitxer bck_ te un altre hash
+        // El bck_ nou tampoc
         let expect3 = format!(r#"hash = "ba0572e3c4ab94a90af51cb1938bffe0955c6f8b79f46591fa5b7bd165df2f28""#);
-        assert!(config_content.contains(expect3.as_str()));
+        assert!(!config_content.contains(expect3.as_str()));
     }
 }
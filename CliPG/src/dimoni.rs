@@ -0,0 +1,273 @@
+use crate::cli_pg::CliPG;
+use crate::informe::SyncReport;
+use crate::vigilant::{self, Vigilant};
+use interprocess::local_socket::prelude::*;
+use interprocess::local_socket::{GenericNamespaced, Listener, ListenerOptions};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Dimoni sense finestra: sincronitza periodicament i quan es desa una partida (veure vigilant.rs).
+// Es controla pel socket local SOCKET_DIMONI: cada connexio envia una Peticio en JSON en una linia
+// i rep una Resposta en una linia. La GUI i la linia de comandes en son clients.
+
+pub const SOCKET_DIMONI: &str = "clipg_dimoni";
+pub const INTERVAL_PER_DEFECTE: Duration = Duration::from_secs(30 * 60);
+// Temps maxim esperant l'altre costat del socket
+const TIMEOUT_SOCKET: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[serde(tag = "ordre")]
+pub enum Peticio {
+    #[serde(rename = "status")]
+    Estat,
+    #[serde(rename = "sync-now")]
+    SincronitzarAra,
+    #[serde(rename = "pause")]
+    Pausar,
+    // En reprendre es sincronitza tot: el que s'ha desat mentre estava pausat no s'ha pujat
+    #[serde(rename = "resume")]
+    Reprendre,
+    // Torna a llegir la configuracio per vigilar les carpetes dels jocs afegits o eliminats
+    #[serde(rename = "reload-config")]
+    RecarregarConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct EstatDimoni {
+    pub pausat: bool,
+    pub sincronitzant: bool,
+    // 0 si nomes es sincronitza quan es desa una partida
+    pub interval_minuts: u64,
+    pub jocs_vigilats: Vec<String>,
+    // "{joc}: {motiu}" dels jocs que no es poden vigilar
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jocs_no_vigilats: Vec<String>,
+    // Segons des de 1970
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ultima_sincronitzacio: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ultim_resum: Option<String>,
+    #[serde(default)]
+    pub ultim_te_errors: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Resposta {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub estat: EstatDimoni,
+}
+
+pub fn enviar(peticio: Peticio) -> Result<Resposta, String> {
+    enviar_a(SOCKET_DIMONI, peticio)
+}
+
+pub fn enviar_a(nom_socket: &str, peticio: Peticio) -> Result<Resposta, String> {
+    let nom = nom_socket.to_ns_name::<GenericNamespaced>().map_err(|e| e.to_string())?;
+    let conn = LocalSocketStream::connect(nom).map_err(|_| "El dimoni no esta en marxa.".to_string())?;
+    conn.set_recv_timeout(Some(TIMEOUT_SOCKET)).ok();
+    let mut conn = BufReader::new(conn);
+    let linia = serde_json::to_string(&peticio).unwrap() + "\n";
+    conn.get_mut().write_all(linia.as_bytes()).map_err(|e| format!("No s'ha pogut parlar amb el dimoni: {e}"))?;
+    let mut resposta = String::new();
+    conn.read_line(&mut resposta).map_err(|e| format!("El dimoni no ha respost: {e}"))?;
+    serde_json::from_str(&resposta).map_err(|e| format!("Resposta del dimoni no valida: {e}"))
+}
+
+pub fn escoltar(listener: Listener, estat: Arc<Mutex<EstatDimoni>>, tx: Sender<Peticio>) {
+    // Atén les connexions d'una en una: les peticions son curtes
+    for conn in listener.incoming() {
+        let conn = match conn {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("Error IPC: {e}.");
+                continue;
+            }
+        };
+        conn.set_recv_timeout(Some(TIMEOUT_SOCKET)).ok();
+        let mut conn = BufReader::new(conn);
+        let mut linia = String::new();
+        if conn.read_line(&mut linia).is_err() {
+            continue;
+        }
+        let resposta = match serde_json::from_str::<Peticio>(&linia) {
+            Ok(peticio) => atendre(peticio, &estat, &tx),
+            Err(e) => Resposta {
+                ok: false,
+                error: Some(format!("Peticio no valida: {e}")),
+                estat: estat.lock().unwrap().clone(),
+            },
+        };
+        let linia = serde_json::to_string(&resposta).unwrap() + "\n";
+        let _ = conn.get_mut().write_all(linia.as_bytes());
+    }
+}
+
+pub fn atendre(peticio: Peticio, estat: &Mutex<EstatDimoni>, tx: &Sender<Peticio>) -> Resposta {
+    // Pausar i reprendre tenen efecte de seguida; la resta les fa el bucle del dimoni
+    let mut estat = estat.lock().unwrap();
+    match peticio {
+        Peticio::Estat => {}
+        Peticio::Pausar => estat.pausat = true,
+        Peticio::Reprendre => {
+            estat.pausat = false;
+            tx.send(peticio).ok();
+        }
+        Peticio::SincronitzarAra | Peticio::RecarregarConfig => {
+            tx.send(peticio).ok();
+        }
+    }
+    Resposta {
+        ok: true,
+        error: None,
+        estat: estat.clone(),
+    }
+}
+
+pub struct Dimoni {
+    config_path: Option<PathBuf>,
+    interval: Duration,
+    espera: Duration,
+}
+impl Dimoni {
+    pub fn new(config_path: Option<PathBuf>) -> Self {
+        Dimoni {
+            config_path,
+            interval: INTERVAL_PER_DEFECTE,
+            espera: vigilant::ESPERA_PER_DEFECTE,
+        }
+    }
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        // Duration::ZERO desactiva la sincronitzacio periodica
+        self.interval = interval;
+        self
+    }
+    pub fn with_espera(mut self, espera: Duration) -> Self {
+        self.espera = espera;
+        self
+    }
+    pub fn executar(self) -> Result<(), String> {
+        let nom = SOCKET_DIMONI.to_ns_name::<GenericNamespaced>().map_err(|e| e.to_string())?;
+        let listener = ListenerOptions::new()
+            .name(nom)
+            .create_sync()
+            .map_err(|e| format!("No s'ha pogut obrir el socket de control (ja hi ha un dimoni en marxa?): {e}"))?;
+        let estat = Arc::new(Mutex::new(EstatDimoni {
+            interval_minuts: self.interval.as_secs() / 60,
+            ..Default::default()
+        }));
+        let (tx, rx): (Sender<Peticio>, Receiver<Peticio>) = mpsc::channel();
+        let estat2 = estat.clone();
+        std::thread::spawn(move || escoltar(listener, estat2, tx));
+        let mut vigilant = self.vigilant(&estat)?;
+        println!("Dimoni en marxa: vigilant {} jocs.", vigilant.jocs().len());
+        // En engegar se sincronitza tot
+        let mut sincronitzar_tots = true;
+        let mut ultima = Instant::now();
+        loop {
+            while let Ok(peticio) = rx.try_recv() {
+                match peticio {
+                    Peticio::SincronitzarAra | Peticio::Reprendre => sincronitzar_tots = true,
+                    Peticio::RecarregarConfig => match self.vigilant(&estat) {
+                        Ok(nou) => vigilant = nou,
+                        Err(err) => eprintln!("{err}"),
+                    },
+                    Peticio::Estat | Peticio::Pausar => {}
+                }
+            }
+            let jocs = vigilant.esperar(Duration::from_secs(1));
+            if estat.lock().unwrap().pausat {
+                continue;
+            }
+            if !self.interval.is_zero() && ultima.elapsed() >= self.interval {
+                sincronitzar_tots = true;
+            }
+            if sincronitzar_tots {
+                sincronitzar_tots = false;
+                ultima = Instant::now();
+                self.sincronitzar(&estat, |clipg| Ok(clipg.sync_all(false)));
+                let tots: Vec<String> = vigilant.jocs().iter().map(|joc| joc.to_string()).collect();
+                vigilant.ignorar_canvis(&tots);
+            } else if !jocs.is_empty() {
+                self.sincronitzar(&estat, |clipg| clipg.sync_jocs(&jocs, false));
+                vigilant.ignorar_canvis(&jocs);
+            }
+        }
+    }
+    fn vigilant(&self, estat: &Mutex<EstatDimoni>) -> Result<Vigilant, String> {
+        let clipg = CliPG::default(self.config_path.clone());
+        let vigilant = Vigilant::new(&clipg.config, self.espera)?;
+        let mut estat = estat.lock().unwrap();
+        estat.jocs_vigilats = vigilant.jocs().iter().map(|joc| joc.to_string()).collect();
+        estat.jocs_no_vigilats = vigilant.no_vigilats().iter().map(|(joc, motiu)| format!("{joc}: {motiu}")).collect();
+        Ok(vigilant)
+    }
+    fn sincronitzar(&self, estat: &Mutex<EstatDimoni>, sync: impl FnOnce(&mut CliPG) -> Result<SyncReport, String>) {
+        // Cada sincronitzacio llegeix la configuracio de nou: la poden haver canviat la GUI o la linia de comandes
+        estat.lock().unwrap().sincronitzant = true;
+        let mut clipg = CliPG::default(self.config_path.clone());
+        let res = sync(&mut clipg);
+        let mut estat = estat.lock().unwrap();
+        estat.sincronitzant = false;
+        match res {
+            Ok(res) => {
+                println!("{res}");
+                println!("{}", res.resum());
+                estat.ultima_sincronitzacio = Some(chrono::Local::now().timestamp());
+                estat.ultim_resum = Some(res.resum());
+                estat.ultim_te_errors = res.te_errors();
+            }
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_protocol_dimoni() {
+        // Els noms de les ordres son estables: els fan servir altres clients
+        assert_eq!(serde_json::to_string(&Peticio::SincronitzarAra).unwrap(), r#"{"ordre":"sync-now"}"#);
+        assert_eq!(serde_json::from_str::<Peticio>(r#"{"ordre":"reload-config"}"#).unwrap(), Peticio::RecarregarConfig);
+        assert!(serde_json::from_str::<Peticio>(r#"{"ordre":"patata"}"#).is_err());
+        let estat = Mutex::new(EstatDimoni::default());
+        let (tx, rx) = mpsc::channel();
+        // Pausar no passa pel bucle del dimoni, reprendre si (sincronitza el que s'ha desat mentrestant)
+        assert!(atendre(Peticio::Pausar, &estat, &tx).estat.pausat);
+        assert!(rx.try_recv().is_err());
+        assert!(!atendre(Peticio::Reprendre, &estat, &tx).estat.pausat);
+        assert_eq!(rx.try_recv(), Ok(Peticio::Reprendre));
+        atendre(Peticio::SincronitzarAra, &estat, &tx);
+        assert_eq!(rx.try_recv(), Ok(Peticio::SincronitzarAra));
+    }
+    #[test]
+    fn test_socket_dimoni() {
+        let nom_socket = format!("clipg_dimoni_test_{}", std::process::id());
+        assert!(enviar_a(&nom_socket, Peticio::Estat).is_err());
+        let nom = nom_socket.as_str().to_ns_name::<GenericNamespaced>().unwrap();
+        let listener = ListenerOptions::new().name(nom).create_sync().unwrap();
+        let estat = Arc::new(Mutex::new(EstatDimoni {
+            interval_minuts: 30,
+            jocs_vigilats: vec!["Joc".to_string()],
+            jocs_no_vigilats: vec!["Altre: Permission denied".to_string()],
+            ..Default::default()
+        }));
+        let (tx, rx) = mpsc::channel();
+        let estat2 = estat.clone();
+        std::thread::spawn(move || escoltar(listener, estat2, tx));
+        let resposta = enviar_a(&nom_socket, Peticio::Estat).unwrap();
+        assert!(resposta.ok);
+        assert_eq!(resposta.estat.jocs_vigilats, vec!["Joc"]);
+        assert_eq!(resposta.estat.jocs_no_vigilats, vec!["Altre: Permission denied"]);
+        assert!(enviar_a(&nom_socket, Peticio::Pausar).unwrap().estat.pausat);
+        assert!(estat.lock().unwrap().pausat);
+        enviar_a(&nom_socket, Peticio::SincronitzarAra).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(Peticio::SincronitzarAra));
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Escriptures atomiques: el contingut nou s'escriu a un fitxer temporal al mateix directori, es fa
+// fsync, es renombra sobre el desti i es fa fsync del directori. Si el proces s'atura a mig cami
+// el desti es o la versio anterior o la nova, mai una barreja.
+
+// Prefix dels fitxers temporals de les escriptures en curs. No son partides.
+pub const PREFIX_TEMPORAL: &str = ".clipg-tmp-";
+
+pub fn path_temporal(desti: &Path) -> PathBuf {
+    // Un nom diferent per cada escriptura (la GUI i la CLI poden sincronitzar alhora)
+    static COMPTADOR: AtomicU64 = AtomicU64::new(0);
+    let n = COMPTADOR.fetch_add(1, Ordering::Relaxed);
+    let nom = desti.file_name().unwrap_or_default().to_string_lossy();
+    desti.with_file_name(format!("{PREFIX_TEMPORAL}{}-{n}-{nom}", std::process::id()))
+}
+
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Perque el rename sobrevisqui a una aturada cal persistir tambe l'entrada del directori
+    fs::File::open(dir)?.sync_all()
+}
+#[cfg(not(unix))]
+pub fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn escriure_amb<E: From<io::Error>>(desti: &Path, escriure: impl FnOnce(&mut fs::File) -> Result<(), E>) -> Result<(), E> {
+    // `escriure` omple el temporal; el desti nomes es substitueix si acaba be
+    if let Some(dir) = desti.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporal = path_temporal(desti);
+    let resultat = (|| {
+        let mut f = fs::File::create(&temporal)?;
+        escriure(&mut f)?;
+        f.sync_all()?;
+        drop(f);
+        Ok(fs::rename(&temporal, desti)?)
+    })();
+    if resultat.is_err() {
+        let _ = fs::remove_file(&temporal);
+        return resultat;
+    }
+    Ok(sync_dir(desti.parent().unwrap_or(Path::new(".")))?)
+}
+
+pub fn escriure(desti: &Path, contingut: &[u8]) -> io::Result<()> {
+    escriure_amb(desti, |f| f.write_all(contingut))
+}
+
+pub fn copiar(origen: &Path, desti: &Path) -> io::Result<()> {
+    let mut origen = fs::File::open(origen)?;
+    escriure_amb(desti, |f| io::copy(&mut origen, f).map(|_| ()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_escriure() {
+        let dir = std::env::temp_dir().join("clipg_test_escriptura");
+        let _ = fs::remove_dir_all(&dir);
+        let desti = dir.join("perfil/save.sav");
+        escriure(&desti, b"v1").unwrap();
+        escriure(&desti, b"v2").unwrap();
+        assert_eq!(fs::read(&desti).unwrap(), b"v2");
+        let copia = dir.join("perfil/copia.sav");
+        copiar(&desti, &copia).unwrap();
+        assert_eq!(fs::read(&copia).unwrap(), b"v2");
+        // Si falla a mitges el desti es queda com estava i no queda el temporal
+        let error = escriure_amb(&desti, |f| -> io::Result<()> {
+            f.write_all(b"v3 a mitg")?;
+            Err(io::Error::other("disc ple"))
+        });
+        assert!(error.is_err());
+        assert_eq!(fs::read(&desti).unwrap(), b"v2");
+        let noms: Vec<_> = fs::read_dir(dir.join("perfil")).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(noms.len(), 2);
+        assert!(noms.iter().all(|n| !n.to_string_lossy().starts_with(PREFIX_TEMPORAL)));
+        assert_ne!(path_temporal(&desti), path_temporal(&desti));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
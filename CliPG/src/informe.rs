@@ -0,0 +1,214 @@
+use crate::videojoc::conflicte::Resolucio;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::time::Duration;
+
+// Resultat d'una sincronitzacio. El text de la consola, el JSON i la GUI es generen d'aqui.
+
+// Que s'ha fet amb una partida
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "accio", rename_all = "snake_case")]
+pub enum SyncAction {
+    // `nova`: la partida no existia a l'altre costat
+    Upload { nova: bool },
+    Download { nova: bool },
+    DeleteLocal,
+    DeleteRemote,
+    // `resolucio` es None si el conflicte ha quedat pendent de resoldre a ma
+    Conflict { resolucio: Option<Resolucio> },
+    Unchanged,
+    Error { error: String },
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SyncPartida {
+    pub nom: String,
+    #[serde(flatten)]
+    pub accio: SyncAction,
+    // Bytes pujats o descarregats (0 si no s'ha transferit res)
+    pub bytes: u64,
+    #[serde(rename = "durada_ms", serialize_with = "serialitzar_ms")]
+    pub durada: Duration,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+pub struct SyncJoc {
+    pub nom: String,
+    pub partides: Vec<SyncPartida>,
+    // Error que ha impedit sincronitzar el joc (p.ex. no s'ha pogut obtenir la llista del servidor)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(rename = "durada_ms", serialize_with = "serialitzar_ms")]
+    pub durada: Duration,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+pub struct SyncReport {
+    pub jocs: Vec<SyncJoc>,
+    #[serde(rename = "durada_ms", serialize_with = "serialitzar_ms")]
+    pub durada: Duration,
+}
+
+fn serialitzar_ms<S: Serializer>(durada: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(durada.as_millis() as u64)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{b} B"),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+impl SyncAction {
+    pub fn icona(&self) -> &'static str {
+        match self {
+            SyncAction::Upload { .. } => "⬆",
+            SyncAction::Download { .. } => "⬇",
+            SyncAction::DeleteLocal | SyncAction::DeleteRemote => "❌",
+            SyncAction::Conflict { .. } => "⚠",
+            SyncAction::Unchanged => "✔",
+            SyncAction::Error { .. } => "⛔",
+        }
+    }
+    pub fn es_error(&self) -> bool {
+        matches!(self, SyncAction::Error { .. })
+    }
+}
+
+impl fmt::Display for SyncPartida {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icona = self.accio.icona();
+        let nom = &self.nom;
+        match &self.accio {
+            SyncAction::Upload { nova: true } => write!(f, "{icona} Pujar partida local: {nom}"),
+            SyncAction::Upload { nova: false } => write!(f, "{icona} Pujar partida local (local modificat): {nom}"),
+            SyncAction::Download { nova: true } => write!(f, "{icona} Descarregar partida remota: {nom}"),
+            SyncAction::Download { nova: false } => write!(f, "{icona} Descarregar (remot modificat): {nom}"),
+            SyncAction::DeleteLocal => write!(f, "{icona} Eliminar local: {nom}"),
+            SyncAction::DeleteRemote => write!(f, "{icona} Eliminar remot: {nom}"),
+            SyncAction::Conflict { resolucio: Some(_) } => write!(f, "{icona} Conflicte: {nom}"),
+            SyncAction::Conflict { resolucio: None } => write!(f, "{icona} Conflicte pendent de resoldre: {nom}"),
+            SyncAction::Unchanged => write!(f, "{icona} Partida OK: {nom}"),
+            SyncAction::Error { error } => write!(f, "{icona} Error sincronitzant {nom}: {error}"),
+        }
+    }
+}
+
+impl SyncJoc {
+    pub fn new(nom: &str) -> Self {
+        SyncJoc {
+            nom: nom.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn te_errors(&self) -> bool {
+        self.error.is_some() || self.partides.iter().any(|p| p.accio.es_error())
+    }
+    pub fn resum_partides(&self) -> String {
+        // Una linia per partida, sense la capcalera del joc
+        if let Some(error) = &self.error {
+            return format!("    ⛔ Error obtenint les partides del servidor: {error}\n");
+        }
+        self.partides.iter().map(|p| format!("    {p}\n")).collect()
+    }
+}
+impl fmt::Display for SyncJoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "* {}:\n{}", self.nom, self.resum_partides())
+    }
+}
+
+impl SyncReport {
+    pub fn te_errors(&self) -> bool {
+        self.jocs.iter().any(SyncJoc::te_errors)
+    }
+    pub fn partides(&self) -> impl Iterator<Item = &SyncPartida> {
+        self.jocs.iter().flat_map(|j| j.partides.iter())
+    }
+    pub fn bytes(&self) -> u64 {
+        self.partides().map(|p| p.bytes).sum()
+    }
+    pub fn resum(&self) -> String {
+        // Una linia amb el total de cada accio
+        let compta = |f: fn(&SyncAction) -> bool| self.partides().filter(|p| f(&p.accio)).count();
+        let errors = compta(SyncAction::es_error) + self.jocs.iter().filter(|j| j.error.is_some()).count();
+        format!(
+            "{} pujades, {} descarregades, {} eliminades, {} conflictes, {} errors ({} en {:.1} s)",
+            compta(|a| matches!(a, SyncAction::Upload { .. })),
+            compta(|a| matches!(a, SyncAction::Download { .. })),
+            compta(|a| matches!(a, SyncAction::DeleteLocal | SyncAction::DeleteRemote)),
+            compta(|a| matches!(a, SyncAction::Conflict { .. })),
+            errors,
+            format_bytes(self.bytes()),
+            self.durada.as_secs_f64(),
+        )
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for joc in self.jocs.iter() {
+            write!(f, "\n{joc}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_sync_report() {
+        let partida = |nom: &str, accio, bytes| SyncPartida {
+            nom: nom.to_string(),
+            accio,
+            bytes,
+            durada: Duration::from_millis(5),
+        };
+        let report = SyncReport {
+            jocs: vec![
+                SyncJoc {
+                    nom: "Joc".to_string(),
+                    partides: vec![
+                        partida("save1.txt", SyncAction::Upload { nova: false }, 2048),
+                        partida("save2.txt", SyncAction::Conflict { resolucio: None }, 0),
+                        partida(
+                            "save3.txt",
+                            SyncAction::Error {
+                                error: "connexio tallada".to_string(),
+                            },
+                            0,
+                        ),
+                    ],
+                    error: None,
+                    durada: Duration::from_millis(15),
+                },
+                SyncJoc {
+                    error: Some("Servidor apagat".to_string()),
+                    ..SyncJoc::new("Altre")
+                },
+            ],
+            durada: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            report.to_string(),
+            "\n* Joc:\n    ⬆ Pujar partida local (local modificat): save1.txt\n    ⚠ Conflicte pendent de resoldre: save2.txt\n    ⛔ Error sincronitzant save3.txt: connexio tallada\n\n* Altre:\n    ⛔ Error obtenint les partides del servidor: Servidor apagat\n"
+        );
+        assert_eq!(report.resum(), "1 pujades, 0 descarregades, 0 eliminades, 1 conflictes, 2 errors (2.0 KB en 1.5 s)");
+        assert!(report.te_errors());
+        assert!(!SyncReport::default().te_errors());
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["durada_ms"], 1500);
+        assert_eq!(
+            json["jocs"][0]["partides"][0],
+            serde_json::json!({"nom": "save1.txt", "accio": "upload", "nova": false, "bytes": 2048, "durada_ms": 5})
+        );
+        assert_eq!(json["jocs"][0]["partides"][1]["resolucio"], serde_json::Value::Null);
+        assert_eq!(json["jocs"][0]["partides"][2]["error"], "connexio tallada");
+        assert_eq!(json["jocs"][1]["error"], "Servidor apagat");
+    }
+}
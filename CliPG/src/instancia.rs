@@ -0,0 +1,169 @@
+use interprocess::local_socket::prelude::*;
+use interprocess::local_socket::{GenericNamespaced, Listener};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
+
+// Protocol entre instancies de la GUI: una segona `clipg gui` no obre una altra finestra, envia una Ordre
+// a la que ja esta en marxa pel socket local SOCKET_NAME. Cada connexio envia un Missatge en JSON en una
+// linia i rep una Resposta en una linia. Aixi els llancadors i els scripts d'abans i despres de jugar
+// fan servir l'aplicacio oberta en lloc d'engegar una altra sincronitzacio.
+
+pub const SOCKET_NAME: &str = "clipg_socket";
+// S'incrementa quan s'afegeixen ordres. Una instancia accepta els missatges de la seva versio o anteriors.
+pub const VERSIO_PROTOCOL: u32 = 1;
+// Temps maxim esperant l'altre costat del socket
+const TIMEOUT_SOCKET: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "ordre")]
+pub enum Ordre {
+    #[serde(rename = "activate")]
+    Activar,
+    #[serde(rename = "sync-game")]
+    SincronitzarJoc { joc: String },
+    #[serde(rename = "sync-all")]
+    SincronitzarTots,
+    // Obre la pantalla d'afegir joc amb la carpeta (i el nom) ja posats: l'usuari ho confirma
+    #[serde(rename = "add-game")]
+    AfegirJoc {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nom: Option<String>,
+    },
+    #[serde(rename = "open-game")]
+    ObrirJoc { joc: String },
+}
+impl Ordre {
+    pub fn activa_finestra(&self) -> bool {
+        // Les sincronitzacions es fan en segon pla: un script abans de jugar no ha de treure el focus al joc
+        !matches!(self, Ordre::SincronitzarJoc { .. } | Ordre::SincronitzarTots)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Missatge {
+    pub versio: u32,
+    #[serde(flatten)]
+    pub ordre: Ordre,
+}
+impl Missatge {
+    pub fn new(ordre: Ordre) -> Self {
+        Missatge { versio: VERSIO_PROTOCOL, ordre }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Resposta {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn llegir_missatge(linia: &str) -> Result<Ordre, String> {
+    // Les versions anteriors del protocol nomes enviaven "activate", sense salt de linia
+    if linia.trim() == "activate" {
+        return Ok(Ordre::Activar);
+    }
+    let valor: Value = serde_json::from_str(linia).map_err(|e| format!("Missatge no valid: {e}"))?;
+    let versio = valor["versio"].as_u64().ok_or("Missatge sense versio del protocol")?;
+    if versio > VERSIO_PROTOCOL as u64 {
+        return Err(format!("Versio del protocol {versio} no suportada (aquesta instancia fa servir la {VERSIO_PROTOCOL})"));
+    }
+    serde_json::from_value::<Missatge>(valor).map(|missatge| missatge.ordre).map_err(|e| format!("Ordre no valida: {e}"))
+}
+
+pub fn enviar(ordre: Ordre) -> Result<(), String> {
+    enviar_a(SOCKET_NAME, ordre)
+}
+
+pub fn enviar_a(nom_socket: &str, ordre: Ordre) -> Result<(), String> {
+    let nom = nom_socket.to_ns_name::<GenericNamespaced>().map_err(|e| e.to_string())?;
+    let conn = LocalSocketStream::connect(nom).map_err(|_| "L'aplicacio no esta en marxa.".to_string())?;
+    conn.set_recv_timeout(Some(TIMEOUT_SOCKET)).ok();
+    let mut conn = BufReader::new(conn);
+    let linia = serde_json::to_string(&Missatge::new(ordre)).unwrap() + "\n";
+    conn.get_mut().write_all(linia.as_bytes()).map_err(|e| format!("No s'ha pogut parlar amb l'aplicacio: {e}"))?;
+    let mut resposta = String::new();
+    conn.read_line(&mut resposta).map_err(|e| format!("L'aplicacio no ha respost: {e}"))?;
+    // Les versions anteriors tanquen la connexio sense respondre despres d'activar la finestra
+    if resposta.is_empty() {
+        return Err("L'aplicacio en marxa es d'una versio anterior: nomes s'ha activat la finestra.".to_string());
+    }
+    let resposta: Resposta = serde_json::from_str(&resposta).map_err(|e| format!("Resposta de l'aplicacio no valida: {e}"))?;
+    match resposta.error {
+        Some(error) if !resposta.ok => Err(error),
+        _ => Ok(()),
+    }
+}
+
+pub fn escoltar(listener: Listener, mut en_ordre: impl FnMut(Ordre)) {
+    // Atén les connexions d'una en una. La resposta nomes diu si l'ordre s'ha entes: la fa la GUI.
+    for conn in listener.incoming() {
+        let conn = match conn {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("Error IPC: {e}.");
+                continue;
+            }
+        };
+        conn.set_recv_timeout(Some(TIMEOUT_SOCKET)).ok();
+        let mut conn = BufReader::new(conn);
+        let mut linia = String::new();
+        if conn.read_line(&mut linia).is_err() {
+            continue;
+        }
+        let resposta = match llegir_missatge(&linia) {
+            Ok(ordre) => {
+                en_ordre(ordre);
+                Resposta { ok: true, error: None }
+            }
+            Err(error) => Resposta { ok: false, error: Some(error) },
+        };
+        let linia = serde_json::to_string(&resposta).unwrap() + "\n";
+        let _ = conn.get_mut().write_all(linia.as_bytes());
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use interprocess::local_socket::ListenerOptions;
+    use std::sync::mpsc;
+    #[test]
+    fn test_protocol_instancia() {
+        // Els noms dels camps i de les ordres son estables: els fan servir llancadors i scripts
+        assert_eq!(
+            serde_json::to_string(&Missatge::new(Ordre::SincronitzarJoc { joc: "Celeste".to_string() })).unwrap(),
+            r#"{"versio":1,"ordre":"sync-game","joc":"Celeste"}"#
+        );
+        assert_eq!(
+            llegir_missatge(r#"{"versio":1,"ordre":"add-game","path":"/jocs/Celeste"}"#),
+            Ok(Ordre::AfegirJoc {
+                path: "/jocs/Celeste".to_string(),
+                nom: None
+            })
+        );
+        // Les instancies antigues envien "activate" tal qual
+        assert_eq!(llegir_missatge("activate"), Ok(Ordre::Activar));
+        assert!(llegir_missatge(r#"{"versio":2,"ordre":"sync-all"}"#).unwrap_err().contains("Versio del protocol 2"));
+        assert!(llegir_missatge(r#"{"ordre":"sync-all"}"#).is_err());
+        assert!(llegir_missatge(r#"{"versio":1,"ordre":"patata"}"#).is_err());
+        assert!(!Ordre::SincronitzarTots.activa_finestra());
+        assert!(Ordre::ObrirJoc { joc: "Celeste".to_string() }.activa_finestra());
+    }
+    #[test]
+    fn test_socket_instancia() {
+        let nom_socket = format!("clipg_socket_test_{}", std::process::id());
+        assert!(enviar_a(&nom_socket, Ordre::Activar).is_err());
+        let nom = nom_socket.as_str().to_ns_name::<GenericNamespaced>().unwrap();
+        let listener = ListenerOptions::new().name(nom).create_sync().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || escoltar(listener, |ordre| tx.send(ordre).unwrap()));
+        enviar_a(&nom_socket, Ordre::ObrirJoc { joc: "Celeste".to_string() }).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(Ordre::ObrirJoc { joc: "Celeste".to_string() }));
+        enviar_a(&nom_socket, Ordre::SincronitzarTots).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(Ordre::SincronitzarTots));
+    }
+}
@@ -1,4 +1,12 @@
 pub mod cli_pg;
+pub mod dimoni;
+pub mod escriptura;
+pub mod informe;
+pub mod instancia;
+pub mod llancament;
 pub mod pg_api;
 pub mod pg_gui;
+pub mod sortida;
+pub mod tls;
 pub mod videojoc;
+pub mod vigilant;
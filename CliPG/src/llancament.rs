@@ -0,0 +1,130 @@
+use crate::cli_pg::CliPG;
+use crate::informe::{SyncAction, SyncReport};
+use crate::sortida;
+use std::path::PathBuf;
+use std::process::Command;
+
+// `clipg run`: sincronitza un joc abans d'obrir-lo i quan es tanca. Es pot posar davant de l'ordre del joc
+// a Steam (opcions de llancament: `clipg run --game "Celeste" -- %command%`) o a Lutris.
+
+pub struct Llancament {
+    pub baixada: Result<SyncReport, String>,
+    // Per que no s'executa el joc. Amb `forcar`, el motiu pel qual s'hauria bloquejat.
+    pub bloqueig: Option<String>,
+    // None si no s'ha executat el joc, Err si no s'ha pogut executar
+    pub codi_joc: Option<Result<i32, String>>,
+    pub pujada: Option<Result<SyncReport, String>>,
+}
+impl Llancament {
+    pub fn codi(&self) -> i32 {
+        // El codi del joc si ha fallat; si no, el de la sincronitzacio de despres
+        match (&self.codi_joc, &self.pujada) {
+            (Some(Ok(codi)), _) if *codi != sortida::CODI_OK => *codi,
+            (Some(Ok(_)), Some(Ok(pujada))) => sortida::codi_informe(pujada),
+            _ => sortida::CODI_ERROR,
+        }
+    }
+}
+
+pub fn motiu_bloqueig(informe: &SyncReport) -> Option<String> {
+    // No es juga amb una partida que potser no es l'ultima: si el servidor no respon o hi ha conflictes pendents
+    if let Some(joc) = informe.jocs.iter().find(|joc| joc.error.is_some()) {
+        return Some(format!("No s'han pogut obtenir les partides de {} del servidor: {}.", joc.nom, joc.error.as_ref().unwrap()));
+    }
+    let errors = informe.partides().filter(|p| p.accio.es_error()).count();
+    if errors > 0 {
+        return Some(format!("{errors} partides no s'han pogut sincronitzar."));
+    }
+    let conflictes = informe.partides().filter(|p| matches!(p.accio, SyncAction::Conflict { resolucio: None })).count();
+    if conflictes > 0 {
+        return Some(format!("{conflictes} partides tenen un conflicte pendent de resoldre (clipg conflicts list)."));
+    }
+    None
+}
+
+pub fn executar_ordre(ordre: &[String]) -> Result<i32, String> {
+    let (programa, args) = ordre.split_first().ok_or("No s'ha donat cap ordre per executar")?;
+    let estat = Command::new(programa).args(args).status().map_err(|e| format!("No s'ha pogut executar \"{programa}\": {e}"))?;
+    // Sense codi si l'ha aturat un senyal
+    Ok(estat.code().unwrap_or(sortida::CODI_ERROR))
+}
+
+pub fn jugar(config_path: Option<PathBuf>, joc: &str, ordre: &[String], forcar: bool, abans_de_jugar: impl FnOnce(&Llancament)) -> Llancament {
+    let noms = [joc.to_string()];
+    let mut clipg = CliPG::default(config_path.clone());
+    let baixada = clipg.sync_jocs(&noms, false);
+    let bloqueig = match &baixada {
+        Ok(informe) => motiu_bloqueig(informe),
+        Err(err) => Some(err.clone()),
+    };
+    let mut llancament = Llancament {
+        baixada,
+        bloqueig,
+        codi_joc: None,
+        pujada: None,
+    };
+    abans_de_jugar(&llancament);
+    if llancament.bloqueig.is_some() && !forcar {
+        return llancament;
+    }
+    let codi_joc = executar_ordre(ordre);
+    let executat = codi_joc.is_ok();
+    llancament.codi_joc = Some(codi_joc);
+    if !executat {
+        return llancament;
+    }
+    // Es torna a llegir la configuracio: mentre es jugava la pot haver canviat la GUI o el dimoni
+    let mut clipg = CliPG::default(config_path);
+    llancament.pujada = Some(clipg.sync_jocs(&noms, false));
+    llancament
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::informe::{SyncJoc, SyncPartida};
+    use std::time::Duration;
+    #[test]
+    fn test_llancament() {
+        let partida = |accio| SyncPartida {
+            nom: "save1.sav".to_string(),
+            accio,
+            bytes: 0,
+            durada: Duration::ZERO,
+        };
+        let mut informe = SyncReport {
+            jocs: vec![SyncJoc {
+                partides: vec![partida(SyncAction::Download { nova: false }), partida(SyncAction::Conflict { resolucio: None })],
+                ..SyncJoc::new("Celeste")
+            }],
+            durada: Duration::ZERO,
+        };
+        assert!(motiu_bloqueig(&informe).unwrap().contains("conflicte pendent"));
+        informe.jocs[0].partides.pop();
+        assert_eq!(motiu_bloqueig(&informe), None);
+        informe.jocs[0].error = Some("Servidor apagat".to_string());
+        assert!(motiu_bloqueig(&informe).unwrap().contains("Servidor apagat"));
+        // El codi del joc es manté si falla
+        let mut llancament = Llancament {
+            baixada: Ok(SyncReport::default()),
+            bloqueig: None,
+            codi_joc: Some(Ok(3)),
+            pujada: Some(Ok(SyncReport::default())),
+        };
+        assert_eq!(llancament.codi(), 3);
+        llancament.codi_joc = Some(Ok(0));
+        assert_eq!(llancament.codi(), sortida::CODI_OK);
+        llancament.pujada = Some(Err("Servidor apagat".to_string()));
+        assert_eq!(llancament.codi(), sortida::CODI_ERROR);
+        llancament.codi_joc = Some(Err("No s'ha pogut executar".to_string()));
+        assert_eq!(llancament.codi(), sortida::CODI_ERROR);
+        llancament.codi_joc = None;
+        assert_eq!(llancament.codi(), sortida::CODI_ERROR);
+        assert!(executar_ordre(&[]).is_err());
+        assert!(executar_ordre(&["clipg_no_existeix".to_string()]).is_err());
+        if cfg!(unix) {
+            let ordre: Vec<String> = ["sh", "-c", "exit 3"].iter().map(|s| s.to_string()).collect();
+            assert_eq!(executar_ordre(&ordre), Ok(3));
+        }
+    }
+}
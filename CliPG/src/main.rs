@@ -1,81 +1,627 @@
 use CliPG::cli_pg::CliPG as CliPG_class;
+use CliPG::dimoni::{self, Dimoni, Peticio};
+use CliPG::instancia::Ordre;
+use CliPG::llancament::{self, Llancament};
 use CliPG::pg_gui::PgGUI;
-use clap::{Arg, Command};
+use CliPG::sortida::{self, Sortida};
+use CliPG::videojoc::conflicte::{PoliticaConflicte, Resolucio};
+use CliPG::videojoc::partida_guardada::PartidaGuardada;
+use CliPG::vigilant;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use std::path::PathBuf;
+use std::time::Duration;
 
 fn main() {
     let matches = Command::new("CliPG")
         .version("1.0")
         .author("Bcedu")
         .about("Pastanaga Bullida")
+        .subcommand_required(true)
         .arg_required_else_help(true) // Mostra ajuda si no hi ha arguments
         .arg(
-            Arg::new("list")
-                .short('l')
-                .long("list")
-                .help("Mostra tots els videojocs habilitats per sincornitzar-se")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("gui")
-                .short('g')
-                .long("gui")
-                .help("Inicia l'aplicacio amb interfície gràfica")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("add")
-                .short('a')
-                .long("add")
-                .help("Afegeix un videojoc amb la ruta donada")
+            Arg::new("config")
+                .long("config")
+                .help("Fitxer de configuracio a fer servir en lloc del de per defecte")
+                .global(true)
                 .num_args(1)
-                .value_name("videojoc_path"),
+                .value_name("path")
+                .value_parser(clap::value_parser!(PathBuf)),
         )
         .arg(
-            Arg::new("remove")
-                .short('r')
-                .long("remove")
-                .help("Elimina un videojoc pel seu ID")
+            Arg::new("output")
+                .long("output")
+                .help("Format de la sortida: text o json (per scripts)")
+                .global(true)
                 .num_args(1)
-                .value_name("videojoc_id"),
+                .value_name("format")
+                .value_parser(["text", "json"])
+                .default_value("text"),
         )
-        .arg(
-            Arg::new("sync_all")
-                .short('s')
-                .long("sync_all")
-                .help("Sincronitza tots els videojocs habilitats")
-                .action(clap::ArgAction::SetTrue),
+        .subcommand(
+            Command::new("games")
+                .about("Gestiona els videojocs habilitats per sincronitzar-se")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("Mostra tots els videojocs habilitats per sincronitzar-se"))
+                .subcommand(
+                    Command::new("add")
+                        .about("Afegeix un videojoc amb la ruta donada")
+                        .arg(Arg::new("path").required(true).value_name("videojoc_path"))
+                        .arg(Arg::new("name").long("name").num_args(1).value_name("nom").help("Nom del videojoc (per defecte el de la carpeta)")),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Elimina un videojoc pel seu nom")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id")),
+                )
+                .subcommand(
+                    Command::new("rename")
+                        .about("Canvia el nom d'un videojoc (al servidor es tornen a pujar totes les partides amb el nom nou)")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id"))
+                        .arg(Arg::new("new_name").required(true).value_name("nou_nom")),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Mostra la configuracio d'un videojoc")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id")),
+                )
+                .subcommand(
+                    Command::new("policy")
+                        .about("Canvia la politica de conflictes d'un videojoc (mes_recent_amb_copia, preferir_local, preferir_remot, mantenir_ambdues, manual)")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id"))
+                        .arg(Arg::new("policy").required(true).value_name("politica")),
+                ),
         )
-        .arg(
-            Arg::new("sync_all_test")
-                .short('t')
-                .long("sync_all_test")
-                .help("Sincronitza tots els videojocs habilitats (test mode)")
-                .action(clap::ArgAction::SetTrue),
+        .subcommand(
+            Command::new("sync")
+                .about("Sincronitza els videojocs donats, o tots els habilitats si no se'n dona cap")
+                .arg(
+                    Arg::new("games")
+                        .num_args(0..)
+                        .value_name("videojoc_id")
+                        .help("Nom d'un videojoc o un glob entre cometes (p.ex. \"Total War*\")"),
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .long("dry-run")
+                        .help("Mostra que es faria sense pujar, descarregar ni eliminar res")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("server")
+                .about("Configuracio del servidor")
+                .subcommand_required(true)
+                .subcommand(Command::new("test").about("Comprova la connexio i les credencials amb el servidor"))
+                .subcommand(
+                    Command::new("set")
+                        .about("Canvia la url o les credencials del servidor")
+                        .arg(Arg::new("url").long("url").num_args(1).value_name("url"))
+                        .arg(Arg::new("user").long("user").num_args(1).value_name("usuari"))
+                        .arg(Arg::new("password").long("password").num_args(1).value_name("contrasenya"))
+                        .group(ArgGroup::new("camps").args(["url", "user", "password"]).required(true).multiple(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("saves")
+                .about("Partides guardades d'un videojoc")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("Mostra les partides d'un videojoc a local i al servidor")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id")),
+                )
+                .subcommand(
+                    Command::new("history")
+                        .about("Mostra les versions anteriors d'una partida guardades al servidor")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id"))
+                        .arg(Arg::new("save").required(true).value_name("partida")),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Restaura al servidor una versio anterior d'una partida")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id"))
+                        .arg(Arg::new("save").required(true).value_name("partida"))
+                        .arg(Arg::new("version").required(true).value_name("versio_id")),
+                ),
+        )
+        .subcommand(
+            Command::new("conflicts")
+                .about("Conflictes pendents de resoldre")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("Mostra les partides amb un conflicte pendent de resoldre"))
+                .subcommand(
+                    Command::new("resolve")
+                        .about("Resol un conflicte pendent quedant-se amb la partida local, la remota o totes dues (local, remot, ambdues)")
+                        .arg(Arg::new("game").required(true).value_name("videojoc_id"))
+                        .arg(Arg::new("save").required(true).value_name("partida"))
+                        .arg(Arg::new("resolution").required(true).value_name("resolucio")),
+                ),
+        )
+        .subcommand(
+            Command::new("watch").about("Vigila les carpetes dels videojocs i sincronitza cada joc quan se'n desa una partida").arg(
+                Arg::new("debounce")
+                    .long("debounce")
+                    .help("Segons sense canvis a la carpeta abans de sincronitzar")
+                    .num_args(1)
+                    .value_name("segons")
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("5"),
+            ),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Sincronitza un videojoc, executa l'ordre donada i el torna a sincronitzar quan acaba (a Steam: clipg run --game NOM -- %command%)")
+                .arg(Arg::new("game").long("game").required(true).num_args(1).value_name("videojoc_id"))
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Executa l'ordre encara que la sincronitzacio d'abans falli o deixi conflictes pendents")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(Arg::new("command").required(true).num_args(1..).last(true).value_name("ordre")),
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("Sincronitza en segon pla sense finestra. Amb una ordre, la envia al dimoni que ja esta en marxa.")
+                .args_conflicts_with_subcommands(true)
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .help("Minuts entre sincronitzacions de tots els jocs (0 per nomes sincronitzar quan es desa una partida)")
+                        .num_args(1)
+                        .value_name("minuts")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("30"),
+                )
+                .arg(
+                    Arg::new("debounce")
+                        .long("debounce")
+                        .help("Segons sense canvis a la carpeta abans de sincronitzar")
+                        .num_args(1)
+                        .value_name("segons")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("5"),
+                )
+                .subcommand(Command::new("status").about("Mostra l'estat del dimoni"))
+                .subcommand(Command::new("sync-now").about("Sincronitza tots els jocs ara"))
+                .subcommand(Command::new("pause").about("Atura les sincronitzacions fins que es reprenguin"))
+                .subcommand(Command::new("resume").about("Repren les sincronitzacions (i sincronitza tots els jocs)"))
+                .subcommand(Command::new("reload-config").about("Torna a llegir la configuracio (jocs afegits o eliminats)")),
+        )
+        .subcommand(
+            Command::new("gui")
+                .about("Inicia l'aplicacio amb interfície gràfica. Si ja esta en marxa, li envia l'ordre donada (o l'activa).")
+                .arg(Arg::new("sync_game").long("sync-game").num_args(1).value_name("videojoc_id").help("Sincronitza un videojoc"))
+                .arg(Arg::new("sync_all").long("sync-all").action(clap::ArgAction::SetTrue).help("Sincronitza tots els videojocs"))
+                .arg(
+                    Arg::new("add_game")
+                        .long("add-game")
+                        .num_args(1)
+                        .value_name("videojoc_path")
+                        .help("Obre la pantalla d'afegir un videojoc amb la ruta donada"),
+                )
+                .arg(Arg::new("name").long("name").num_args(1).value_name("nom").requires("add_game").help("Nom del videojoc a afegir"))
+                .arg(Arg::new("open_game").long("open-game").num_args(1).value_name("videojoc_id").help("Obre la pantalla d'un videojoc"))
+                .group(ArgGroup::new("ordre").args(["sync_game", "sync_all", "add_game", "open_game"])),
         )
         .get_matches();
 
-    if matches.get_flag("gui") {
-        PgGUI::start_pg_gui(None);
+    let sortida: Sortida = matches.get_one::<String>("output").unwrap().parse().unwrap();
+    let config_path = matches.get_one::<PathBuf>("config").cloned();
+    // Codi de sortida: 0 tot correcte, 1 error, 2 nomes una part ha anat be
+    let codi = match matches.subcommand() {
+        Some(("gui", args)) => {
+            PgGUI::start_pg_gui(config_path, ordre_gui(args)).ok();
+            sortida::CODI_OK
+        }
+        Some(("watch", args)) => vigilar(args, sortida, config_path),
+        Some(("daemon", args)) => dimoni(args, sortida, config_path),
+        Some(("run", args)) => jugar(args, sortida, config_path),
+        Some((ordre, args)) => {
+            let mut clipg = CliPG_class::default(config_path.clone());
+            match ordre {
+                "games" => games(&mut clipg, args, sortida),
+                "sync" => sincronitzar(&mut clipg, args, sortida),
+                "server" => servidor(&mut clipg, args, sortida, config_path),
+                "saves" => partides(&clipg, args, sortida),
+                "conflicts" => conflictes(&mut clipg, args, sortida),
+                _ => unreachable!(),
+            }
+        }
+        None => unreachable!(),
+    };
+    std::process::exit(codi);
+}
+
+fn arg<'a>(args: &'a ArgMatches, nom: &str) -> &'a str {
+    args.get_one::<String>(nom).unwrap()
+}
+
+fn error(err: String, sortida: Sortida) -> i32 {
+    if sortida == Sortida::Json {
+        println!("{:#}", sortida::error_json(&err));
+    } else {
+        println!("{err}");
+    }
+    sortida::CODI_ERROR
+}
+
+fn missatge(missatge: String, sortida: Sortida) -> i32 {
+    // Resultat de les comandes que nomes canvien alguna cosa
+    if sortida == Sortida::Json {
+        println!("{:#}", sortida::missatge_json(&missatge));
     } else {
-        let mut clipg = CliPG_class::default(None);
-        if matches.get_flag("list") {
-            for v in clipg.config.videojocs_habilitats.list.iter() {
-                println!("* {}\n    -> {}\n", v.nom, v.path);
-            }
-        } else if let Some(path) = matches.get_one::<String>("add") {
-            let res = clipg.afegir_joc(path.to_string(), None);
-            res.unwrap_or_else(|err| println!("{err}"));
-        } else if let Some(videojoc) = matches.get_one::<String>("remove") {
-            let res = clipg.eliminar_joc(videojoc.to_string());
-            res.unwrap_or_else(|err| println!("{err}"));
-        } else if matches.get_flag("sync_all") {
+        println!("{missatge}");
+    }
+    sortida::CODI_OK
+}
+
+fn format_data(timestamp: i64, timestamp_nanos: u32) -> String {
+    chrono::DateTime::from_timestamp(timestamp, timestamp_nanos)
+        .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn games(clipg: &mut CliPG_class, args: &ArgMatches, sortida: Sortida) -> i32 {
+    let (ordre, args) = args.subcommand().unwrap();
+    match ordre {
+        "list" => {
+            if sortida == Sortida::Json {
+                println!("{:#}", sortida::videojocs_json(&clipg.config));
+            } else {
+                for v in clipg.config.videojocs_habilitats.list.iter() {
+                    println!("* {}\n    -> {}\n", v.nom, v.path);
+                }
+            }
+            sortida::CODI_OK
+        }
+        "add" => {
+            let path = arg(args, "path");
+            let res = clipg.afegir_joc(path.to_string(), args.get_one::<String>("name").cloned());
+            match res {
+                Err(err) => error(err, sortida),
+                Ok(()) if sortida == Sortida::Json => {
+                    // El videojoc afegit es l'ultim amb aquesta ruta
+                    let afegit = clipg.config.videojocs_habilitats.list.iter().rfind(|vc| vc.path == path).unwrap();
+                    println!("{:#}", sortida::videojoc_json(Ok(afegit)));
+                    sortida::CODI_OK
+                }
+                Ok(()) => sortida::CODI_OK,
+            }
+        }
+        "remove" => {
+            let videojoc = arg(args, "game");
+            let eliminat = clipg.config.videojocs_habilitats.list.iter().find(|vc| vc.nom == videojoc).cloned();
+            match clipg.eliminar_joc(videojoc.to_string()) {
+                Err(err) => error(err, sortida),
+                Ok(()) => {
+                    if sortida == Sortida::Json {
+                        println!("{:#}", sortida::videojoc_json(Ok(eliminat.as_ref().unwrap())));
+                    }
+                    sortida::CODI_OK
+                }
+            }
+        }
+        "rename" => {
+            let nou_nom = arg(args, "new_name");
+            match clipg.reanomenar_joc(arg(args, "game"), nou_nom.to_string()) {
+                Err(err) => error(err, sortida),
+                Ok(()) => {
+                    let vc = clipg.config.videojocs_habilitats.list.iter().find(|vc| vc.nom == nou_nom).unwrap();
+                    if sortida == Sortida::Json {
+                        println!("{:#}", sortida::videojoc_json(Ok(vc)));
+                    } else {
+                        println!("Videojoc reanomenat: {}", vc.nom);
+                    }
+                    sortida::CODI_OK
+                }
+            }
+        }
+        "show" => {
+            let videojoc = arg(args, "game");
+            let Some(vc) = clipg.config.videojocs_habilitats.list.iter().find(|vc| vc.nom == videojoc) else {
+                return error(format!("\"{}\" no es un joc habilitat.", videojoc), sortida);
+            };
+            if sortida == Sortida::Json {
+                println!("{:#}", sortida::videojoc_json(Ok(vc)));
+            } else {
+                println!(
+                    "* {}\n    -> ruta: {}\n    -> politica de conflictes: {}\n    -> partides sincronitzades: {}\n    -> include: {}\n    -> exclude: {}",
+                    vc.nom,
+                    vc.path,
+                    vc.conflict_policy,
+                    vc.partides_guardades.len(),
+                    vc.include.join(" "),
+                    vc.exclude.join(" ")
+                );
+            }
+            sortida::CODI_OK
+        }
+        "policy" => {
+            let videojoc = arg(args, "game");
+            match arg(args, "policy").parse::<PoliticaConflicte>().and_then(|politica| clipg.set_politica_conflictes(videojoc, politica)) {
+                Ok(()) => missatge(format!("Politica de conflictes de \"{}\": {}", videojoc, arg(args, "policy")), sortida),
+                Err(err) => error(err, sortida),
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn sincronitzar(clipg: &mut CliPG_class, args: &ArgMatches, sortida: Sortida) -> i32 {
+    let test_mode = args.get_flag("dry_run");
+    let noms: Vec<String> = args.get_many::<String>("games").map(|noms| noms.cloned().collect()).unwrap_or_default();
+    if sortida == Sortida::Text {
+        if noms.is_empty() {
             println!("Sincronitzant tots els videojocs...");
-            let res = clipg.sync_all(false);
-            println!("{res}");
-        } else if matches.get_flag("sync_all_test") {
-            println!("Sincronitzant tots els videojocs (test mode)...");
-            let res = clipg.sync_all(true);
+        } else {
+            println!("Sincronitzant {}...", noms.join(", "));
+        }
+    }
+    let res = if noms.is_empty() { Ok(clipg.sync_all(test_mode)) } else { clipg.sync_jocs(&noms, test_mode) };
+    let res = match res {
+        Ok(res) => res,
+        Err(err) => return error(err, sortida),
+    };
+    if sortida == Sortida::Json {
+        println!("{:#}", sortida::informe_json(&res, test_mode));
+    } else {
+        println!("{res}");
+        println!("{}", res.resum());
+    }
+    sortida::codi_informe(&res)
+}
+
+fn ordre_gui(args: &ArgMatches) -> Option<Ordre> {
+    // Ordre per la instancia de la GUI (la que ja esta en marxa o la que s'engega)
+    if let Some(joc) = args.get_one::<String>("sync_game") {
+        Some(Ordre::SincronitzarJoc { joc: joc.clone() })
+    } else if args.get_flag("sync_all") {
+        Some(Ordre::SincronitzarTots)
+    } else if let Some(path) = args.get_one::<String>("add_game") {
+        Some(Ordre::AfegirJoc {
+            path: path.clone(),
+            nom: args.get_one::<String>("name").cloned(),
+        })
+    } else {
+        args.get_one::<String>("open_game").map(|joc| Ordre::ObrirJoc { joc: joc.clone() })
+    }
+}
+
+fn vigilar(args: &ArgMatches, sortida: Sortida, config_path: Option<PathBuf>) -> i32 {
+    let espera = Duration::from_secs(*args.get_one::<u64>("debounce").unwrap());
+    if sortida == Sortida::Text {
+        println!("Vigilant les carpetes dels videojocs (Ctrl+C per aturar)...");
+    }
+    // Amb json, un informe per linia a mesura que es sincronitza
+    let res = vigilant::vigilar(
+        config_path,
+        espera,
+        || true,
+        |res| {
+            if sortida == Sortida::Json {
+                println!("{}", sortida::informe_json(&res, false));
+            } else {
+                println!("{res}");
+                println!("{}", res.resum());
+            }
+        },
+    );
+    match res {
+        Ok(()) => sortida::CODI_OK,
+        Err(err) => error(err, sortida),
+    }
+}
+
+fn jugar(args: &ArgMatches, sortida: Sortida, config_path: Option<PathBuf>) -> i32 {
+    let joc = arg(args, "game");
+    let ordre: Vec<String> = args.get_many::<String>("command").unwrap().cloned().collect();
+    let forcar = args.get_flag("force");
+    if sortida == Sortida::Text {
+        println!("Sincronitzant {joc} abans de jugar...");
+    }
+    let mostrar_baixada = |llancament: &Llancament| {
+        if sortida == Sortida::Json {
+            return;
+        }
+        if let Ok(res) = &llancament.baixada {
             println!("{res}");
+            println!("{}", res.resum());
+        }
+        match &llancament.bloqueig {
+            Some(err) if forcar => println!("{err}\nS'executa igualment (--force)."),
+            Some(err) => println!("{err}\nNo s'executa el joc (--force per executar-lo igualment)."),
+            None => {}
+        }
+    };
+    let llancament = llancament::jugar(config_path, joc, &ordre, forcar, mostrar_baixada);
+    if sortida == Sortida::Json {
+        println!("{:#}", sortida::llancament_json(&llancament));
+    } else {
+        match &llancament.codi_joc {
+            Some(Ok(codi)) => println!("El joc ha acabat (codi {codi}). Sincronitzant {joc}..."),
+            Some(Err(err)) => println!("{err}"),
+            None => {}
+        }
+        match &llancament.pujada {
+            Some(Ok(res)) => {
+                println!("{res}");
+                println!("{}", res.resum());
+            }
+            Some(Err(err)) => println!("{err}"),
+            None => {}
+        }
+    }
+    llancament.codi()
+}
+
+fn dimoni(args: &ArgMatches, sortida: Sortida, config_path: Option<PathBuf>) -> i32 {
+    let peticio = match args.subcommand() {
+        None => {
+            let res = Dimoni::new(config_path)
+                .with_interval(Duration::from_secs(*args.get_one::<u64>("interval").unwrap() * 60))
+                .with_espera(Duration::from_secs(*args.get_one::<u64>("debounce").unwrap()))
+                .executar();
+            return match res {
+                Ok(()) => sortida::CODI_OK,
+                Err(err) => error(err, sortida),
+            };
+        }
+        Some(("status", _)) => Peticio::Estat,
+        Some(("sync-now", _)) => Peticio::SincronitzarAra,
+        Some(("pause", _)) => Peticio::Pausar,
+        Some(("resume", _)) => Peticio::Reprendre,
+        Some(("reload-config", _)) => Peticio::RecarregarConfig,
+        _ => unreachable!(),
+    };
+    let resposta = match dimoni::enviar(peticio) {
+        Ok(resposta) => resposta,
+        Err(err) => return error(err, sortida),
+    };
+    if sortida == Sortida::Json {
+        println!("{:#}", serde_json::to_value(&resposta).unwrap());
+    } else if let Some(err) = &resposta.error {
+        println!("{err}");
+    } else {
+        let estat = &resposta.estat;
+        println!(
+            "Dimoni en marxa{}",
+            if estat.pausat {
+                " (pausat)"
+            } else if estat.sincronitzant {
+                " (sincronitzant)"
+            } else {
+                ""
+            }
+        );
+        println!("    -> jocs vigilats: {}", estat.jocs_vigilats.join(", "));
+        for joc in estat.jocs_no_vigilats.iter() {
+            println!("    -> no vigilat: {joc}");
+        }
+        if estat.interval_minuts > 0 {
+            println!("    -> sincronitzacio periodica: cada {} minuts", estat.interval_minuts);
+        }
+        if let (Some(data), Some(resum)) = (estat.ultima_sincronitzacio, &estat.ultim_resum) {
+            println!("    -> ultima sincronitzacio: {} ({resum})", format_data(data, 0));
+        }
+    }
+    if resposta.ok { sortida::CODI_OK } else { sortida::CODI_ERROR }
+}
+
+fn servidor(clipg: &mut CliPG_class, args: &ArgMatches, sortida: Sortida, config_path: Option<PathBuf>) -> i32 {
+    let (ordre, args) = args.subcommand().unwrap();
+    match ordre {
+        "test" => {
+            let url = clipg.config.server.url.clone();
+            let res = clipg.api.probar_connexio().map_err(|e| e.to_string());
+            let codi = if res.is_ok() { sortida::CODI_OK } else { sortida::CODI_ERROR };
+            if sortida == Sortida::Json {
+                println!("{:#}", sortida::servidor_json(&url, res));
+            } else {
+                match res {
+                    Ok(()) => println!("✔ Conectat ({url})"),
+                    Err(err) => println!("❌ {err} ({url})"),
+                }
+            }
+            codi
+        }
+        "set" => {
+            if let Some(url) = args.get_one::<String>("url") {
+                clipg.config.server.url = url.clone();
+            }
+            if let Some(usuari) = args.get_one::<String>("user") {
+                clipg.config.server.usuari = usuari.clone();
+            }
+            if let Some(contrasenya) = args.get_one::<String>("password") {
+                clipg.config.server.contrasenya = contrasenya.clone();
+            }
+            CliPG_class::save_config(&clipg.config, config_path);
+            missatge(format!("Servidor: {} ({})", clipg.config.server.url, clipg.config.server.usuari), sortida)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn partides(clipg: &CliPG_class, args: &ArgMatches, sortida: Sortida) -> i32 {
+    let (ordre, args) = args.subcommand().unwrap();
+    match ordre {
+        "list" => match clipg.partides_joc(arg(args, "game")) {
+            Ok(partides) => {
+                if sortida == Sortida::Json {
+                    println!("{:#}", sortida::partides_json(&partides));
+                } else {
+                    for p in partides.iter() {
+                        let icona = match p.estat() {
+                            "sincronitzada" => "✔",
+                            "diferent" => "⚠",
+                            "nomes_local" => "⬆",
+                            _ => "⬇",
+                        };
+                        println!("{icona} {}  ({})", p.nom, p.estat().replace('_', " "));
+                    }
+                }
+                sortida::CODI_OK
+            }
+            Err(err) => error(err, sortida),
+        },
+        "history" => match clipg.historial_partida(arg(args, "game"), arg(args, "save")) {
+            Ok(versions) if sortida == Sortida::Json => {
+                println!("{:#}", sortida::versions_json(&versions));
+                sortida::CODI_OK
+            }
+            Ok(versions) => {
+                for v in versions.iter() {
+                    println!("* {}\n    -> {} ({} bytes, {})\n", v.id, format_data(v.timestamp, 0), v.mida, v.hash);
+                }
+                sortida::CODI_OK
+            }
+            Err(err) => error(err, sortida),
+        },
+        "restore" => match clipg.restaurar_partida(arg(args, "game"), arg(args, "save"), arg(args, "version")) {
+            Ok(()) => missatge("Versio restaurada. Es descarregara a la propera sincronitzacio.".to_string(), sortida),
+            Err(err) => error(err, sortida),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn conflictes(clipg: &mut CliPG_class, args: &ArgMatches, sortida: Sortida) -> i32 {
+    let (ordre, args) = args.subcommand().unwrap();
+    match ordre {
+        "list" => match clipg.conflictes_pendents() {
+            Ok(conflictes) if sortida == Sortida::Json => {
+                println!("{:#}", sortida::conflictes_json(&conflictes));
+                sortida::CODI_OK
+            }
+            Ok(conflictes) if conflictes.is_empty() => {
+                println!("No hi ha cap conflicte pendent.");
+                sortida::CODI_OK
+            }
+            Ok(conflictes) => {
+                let descripcio = |p: &PartidaGuardada| {
+                    let mida = p.mida.map(|m| format!("{m} bytes")).unwrap_or_else(|| "-".to_string());
+                    format!("{}  {mida}  {}  ({})", format_data(p.timestamp, p.timestamp_nanos), p.hash, p.dispositiu.as_deref().unwrap_or("-"))
+                };
+                for c in conflictes.iter() {
+                    println!(
+                        "* {}: {}\n    -> local {}\n    -> remot {}\n",
+                        c.joc,
+                        c.local.nom.to_string_lossy(),
+                        descripcio(&c.local),
+                        descripcio(&c.remot)
+                    );
+                }
+                sortida::CODI_OK
+            }
+            Err(err) => error(err, sortida),
+        },
+        "resolve" => {
+            let resolucio = arg(args, "resolution").parse::<Resolucio>();
+            match resolucio.and_then(|resolucio| clipg.resoldre_conflicte(arg(args, "game"), arg(args, "save"), resolucio)) {
+                Ok(()) => missatge("Conflicte resolt.".to_string(), sortida),
+                Err(err) => error(err, sortida),
+            }
         }
+        _ => unreachable!(),
     }
 }
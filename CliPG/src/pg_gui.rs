@@ -1,5 +1,13 @@
-use crate::cli_pg::CliPG;
+use crate::cli_pg::{CliPG, ConflictePendent};
+use crate::dimoni::{self, EstatDimoni, Peticio};
+use crate::informe::{self, SyncAction, SyncReport};
+use crate::instancia::{self, Ordre, SOCKET_NAME};
+use crate::pg_api::PgApiError;
 use crate::videojoc::Videojoc;
+use crate::videojoc::conflicte::{PoliticaConflicte, Resolucio};
+use crate::videojoc::filtre::{self, Filtre};
+use crate::videojoc::partida_guardada::PartidaGuardada;
+use crate::vigilant;
 use eframe::App;
 use eframe::egui::{self, CornerRadius, RichText};
 use interprocess::local_socket::prelude::*;
@@ -7,15 +15,11 @@ use interprocess::local_socket::{GenericNamespaced, ListenerOptions};
 use rfd::FileDialog;
 use serde::{Deserialize, Serialize};
 use single_instance::SingleInstance;
-use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::sync::mpsc::{self, Receiver, Sender};
-
-const SOCKET_NAME: &str = "clipg_socket";
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 
 /*
  * TODO:
- * - Si li dones a sincronitzar pero no estas conectat al servidor peta. S'hauria de controlar
  * - S'hauria de poder visualitzar eks videojocs que estan al servidor per afegirlos al client
  */
 
@@ -24,6 +28,7 @@ enum AppMode {
     Dashboard,
     EditarJoc,
     Configuracio,
+    Conflictes,
 }
 impl Default for AppMode {
     fn default() -> Self {
@@ -55,20 +60,49 @@ pub struct PgGUI {
     estat_servidor: String,
     #[serde(skip)]
     activitat: String,
+    // Resultat de l'ultima sincronitzacio. Es mostra si no hi ha cap missatge a `activitat`.
+    #[serde(skip)]
+    informe: Option<SyncReport>,
     #[serde(skip)]
     joc_afegit: String,
     #[serde(skip)]
     joc_afegit_nom: String,
     #[serde(skip)]
+    joc_afegit_politica: PoliticaConflicte,
+    // Patrons include/exclude del joc, un per linia (veure videojoc/filtre.rs)
+    #[serde(skip)]
+    joc_afegit_include: String,
+    #[serde(skip)]
+    joc_afegit_exclude: String,
+    // Nom del joc que s'esta editant. Si es None, la pantalla afegeix un joc nou.
+    #[serde(skip)]
+    joc_editat: Option<String>,
+    // Conflictes pendents de resoldre a ma
+    #[serde(skip)]
+    conflictes: Vec<ConflictePendent>,
+    #[serde(skip)]
     quit_app: bool,
     #[serde(skip)]
     single_instance_thread_started: bool,
+    // Ordres d'altres `clipg gui` (veure instancia.rs). La de la linia de comandes s'atén en engegar.
+    #[serde(skip)]
+    ordre_inicial: Option<Ordre>,
+    #[serde(skip)]
+    instancia_rx: Option<Receiver<Ordre>>,
     #[serde(skip)]
     sync_tx: Option<Sender<SyncCommand>>,
     #[serde(skip)]
     sync_thread_started: bool,
     #[serde(skip)]
-    sync_result_rx: Option<Receiver<String>>,
+    sync_result_rx: Option<Receiver<SyncReport>>,
+    // Thread que vigila les carpetes dels jocs: es para deixant anar (o enviant per) `vigilant_tx`
+    #[serde(skip)]
+    vigilant_tx: Option<Sender<()>>,
+    #[serde(skip)]
+    vigilant_rx: Option<Receiver<SyncReport>>,
+    // Estat del dimoni (`clipg daemon`) si n'hi ha un en marxa: llavors la GUI no sincronitza pel seu compte
+    #[serde(skip)]
+    dimoni: Option<EstatDimoni>,
     // Coses que si que es guarden al tancar la app
     config_url: String,
     config_usuari: String,
@@ -76,6 +110,8 @@ pub struct PgGUI {
     config_tancar_aplicacio: ConfigTancarAplicacio,
     config_sincronitzar_al_tancar: bool,
     config_sincronitzar_cada_x_minuts: u64,
+    #[serde(default)]
+    config_vigilar_carpetes: bool,
 }
 impl Default for PgGUI {
     fn default() -> Self {
@@ -84,96 +120,135 @@ impl Default for PgGUI {
             current_mode: AppMode::Dashboard,
             estat_servidor: String::new(),
             activitat: String::new(),
+            informe: None,
             joc_afegit: String::new(),
             joc_afegit_nom: String::new(),
+            joc_afegit_politica: PoliticaConflicte::default(),
+            joc_afegit_include: filtre::include_per_defecte().join("\n"),
+            joc_afegit_exclude: filtre::exclude_per_defecte().join("\n"),
+            joc_editat: None,
+            conflictes: Vec::new(),
             config_url: String::new(),
             config_usuari: String::new(),
             config_contrasenya: String::new(),
             config_tancar_aplicacio: ConfigTancarAplicacio::BackgroundApp,
             config_sincronitzar_al_tancar: false,
             config_sincronitzar_cada_x_minuts: 0,
+            config_vigilar_carpetes: false,
             quit_app: false,
             single_instance_thread_started: false,
+            ordre_inicial: None,
+            instancia_rx: None,
             sync_thread_started: false,
             sync_tx: None,
             sync_result_rx: None,
+            vigilant_tx: None,
+            vigilant_rx: None,
+            dimoni: None,
         }
     }
 }
 // Metodes per inicialitxar i configurar comportaments la UI
 impl PgGUI {
-    pub fn start_pg_gui(clipg_config_path: Option<PathBuf>) -> Result<(), eframe::Error> {
+    pub fn start_pg_gui(clipg_config_path: Option<PathBuf>, ordre: Option<Ordre>) -> Result<(), eframe::Error> {
         let instance = SingleInstance::new("clipg").unwrap();
         if !instance.is_single() {
-            PgGUI::notify_activate_to_existing_instance();
+            PgGUI::notify_to_existing_instance(ordre.unwrap_or(Ordre::Activar));
             return Ok(());
         }
         println!("Instancia principal: iniciant UI.");
-        let options = PgGUI::get_default_egui_options(&clipg_config_path);
+        let options = PgGUI::get_default_egui_options();
         eframe::run_native(
             "CliPG: Sincronitzacio de partides guardades",
             options,
             Box::new(|cc| {
-                let app: PgGUI = if let Some(storage) = cc.storage {
+                let mut app: PgGUI = if let Some(storage) = cc.storage {
                     eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
                 } else {
                     PgGUI::default()
                 };
+                // La configuracio del CliPG (`--config`). La de la finestra la guarda eframe al seu directori.
+                app.clipg_config_path = clipg_config_path;
+                app.ordre_inicial = ordre;
+                app.actualitzar_dimoni();
 
                 Ok(Box::new(app))
             }),
         )
     }
-    fn get_default_egui_options(clipg_config_path: &Option<PathBuf>) -> eframe::NativeOptions {
+    fn get_default_egui_options() -> eframe::NativeOptions {
         let mut res = eframe::NativeOptions::default();
         res.persist_window = true;
-        res.persistence_path = clipg_config_path.clone();
         res
     }
     fn setup_signals(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.setup_signal_close(ctx, _frame);
         self.setup_single_instance_activate(ctx, _frame);
         self.setup_sync_thread();
-        if let Some(rx) = &self.sync_result_rx {
+        self.setup_vigilant_thread(ctx);
+        for rx in [&self.sync_result_rx, &self.vigilant_rx].into_iter().flatten() {
             while let Ok(res) = rx.try_recv() {
-                self.activitat = res;
+                self.activitat = String::new();
+                self.informe = Some(res);
             }
         }
+        let ordres: Vec<Ordre> = self.instancia_rx.iter().flat_map(|rx| rx.try_iter()).collect();
+        for ordre in ordres {
+            self.atendre_ordre(ordre);
+        }
     }
-    fn notify_activate_to_existing_instance() {
-        println!("Instancia secundaria: intentant activar instancia principal.");
-        let name = SOCKET_NAME.to_ns_name::<GenericNamespaced>().unwrap();
-        if let Ok(mut conn) = LocalSocketStream::connect(name) {
-            let _ = conn.write_all(b"activate");
+    fn notify_to_existing_instance(ordre: Ordre) {
+        println!("Instancia secundaria: enviant {ordre:?} a la instancia principal.");
+        if let Err(err) = instancia::enviar(ordre) {
+            eprintln!("{err}");
         }
     }
     fn start_single_instance_thread(&mut self, ctx: &egui::Context) {
         self.single_instance_thread_started = true;
+        let (tx, rx) = mpsc::channel::<Ordre>();
+        self.instancia_rx = Some(rx);
+        if let Some(ordre) = self.ordre_inicial.take() {
+            tx.send(ordre).ok();
+        }
         let ctx2 = ctx.clone();
         std::thread::spawn(move || {
             let name = SOCKET_NAME.to_ns_name::<GenericNamespaced>().unwrap();
             let listener = ListenerOptions::new().name(name).create_sync().expect("No es pot crear el socket IPC.");
             println!("Instancia principal: IPC listener iniciat.");
-            for conn in listener.incoming() {
-                match conn {
-                    Ok(mut conn) => {
-                        let mut buf = [0; 16];
-                        let _ = conn.read(&mut buf);
-                        println!("Instancia principal: activant finestra.");
-                        PgGUI::activate_window(&ctx2);
-                    }
-                    Err(e) => {
-                        eprintln!("Error IPC: {e}.");
-                    }
+            instancia::escoltar(listener, |ordre| {
+                if ordre.activa_finestra() {
+                    println!("Instancia principal: activant finestra.");
+                    PgGUI::activate_window(&ctx2);
                 }
-            }
+                let _ = tx.send(ordre);
+                ctx2.request_repaint();
+            });
         });
     }
+    fn atendre_ordre(&mut self, ordre: Ordre) {
+        println!("Instancia principal: atenent {ordre:?}.");
+        match ordre {
+            Ordre::Activar => {}
+            Ordre::SincronitzarJoc { joc } => self.sincronitzar_joc(joc),
+            Ordre::SincronitzarTots => self.sincronitzar_tots(),
+            Ordre::AfegirJoc { path, nom } => {
+                let v = Videojoc::new(path);
+                self.joc_afegit = v.local_folder.display().to_string();
+                self.joc_afegit_nom = nom.unwrap_or_else(|| v.nom.to_string_lossy().to_string());
+                self.joc_afegit_politica = PoliticaConflicte::default();
+                self.joc_afegit_include = filtre::include_per_defecte().join("\n");
+                self.joc_afegit_exclude = filtre::exclude_per_defecte().join("\n");
+                self.joc_editat = None;
+                self.current_mode = AppMode::EditarJoc;
+            }
+            Ordre::ObrirJoc { joc } => self.obrir_joc(&joc),
+        }
+    }
     fn start_sync_thread(&mut self) {
         println!("Iniciant thread de sincronitzacio en segon pla...");
         self.sync_thread_started = true;
         let (tx_cmd, rx_cmd): (Sender<SyncCommand>, Receiver<SyncCommand>) = mpsc::channel();
-        let (tx_res, rx_res) = mpsc::channel::<String>();
+        let (tx_res, rx_res) = mpsc::channel::<SyncReport>();
         self.sync_tx = Some(tx_cmd);
         self.sync_result_rx = Some(rx_res);
         std::thread::spawn(move || {
@@ -221,6 +296,46 @@ impl PgGUI {
             }
         });
     }
+    fn start_vigilant_thread(&mut self, ctx: &egui::Context) {
+        println!("Iniciant thread de vigilancia de carpetes...");
+        let (tx_stop, rx_stop) = mpsc::channel::<()>();
+        let (tx_res, rx_res) = mpsc::channel::<SyncReport>();
+        self.vigilant_tx = Some(tx_stop);
+        self.vigilant_rx = Some(rx_res);
+        let clipg_config_path = self.clipg_config_path.clone();
+        let ctx2 = ctx.clone();
+        std::thread::spawn(move || {
+            let continuar = || matches!(rx_stop.try_recv(), Err(TryRecvError::Empty));
+            let res = vigilant::vigilar(clipg_config_path, vigilant::ESPERA_PER_DEFECTE, continuar, |res| {
+                println!("Sincronitzacio per canvis a les carpetes completada:");
+                println!("{res}");
+                let _ = tx_res.send(res);
+                ctx2.request_repaint();
+            });
+            match res {
+                Ok(()) => println!("Thread de vigilancia de carpetes aturat."),
+                Err(err) => eprintln!("{err}"),
+            }
+        });
+    }
+    fn setup_vigilant_thread(&mut self, ctx: &egui::Context) {
+        // S'engega o s'atura segons la configuracio
+        if self.dimoni.is_some() {
+            return;
+        }
+        if self.config_vigilar_carpetes && self.vigilant_tx.is_none() {
+            self.start_vigilant_thread(ctx);
+        } else if !self.config_vigilar_carpetes && self.vigilant_tx.is_some() {
+            self.reiniciar_vigilant_thread();
+        }
+    }
+    fn reiniciar_vigilant_thread(&mut self) {
+        // El thread s'atura i, si cal, setup_vigilant_thread el torna a engegar amb les carpetes noves
+        if let Some(tx) = self.vigilant_tx.take() {
+            tx.send(()).ok();
+        }
+        self.vigilant_rx = None;
+    }
     fn setup_single_instance_activate(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         if !self.single_instance_thread_started {
             println!("Instancia principal: iniciant thread d'instancia única.");
@@ -228,6 +343,10 @@ impl PgGUI {
         }
     }
     fn setup_sync_thread(&mut self) {
+        // Amb el dimoni en marxa ja sincronitza ell
+        if self.dimoni.is_some() {
+            return;
+        }
         if !self.sync_thread_started && self.config_sincronitzar_cada_x_minuts > 0 {
             self.start_sync_thread();
             self.config_sync_thread();
@@ -284,7 +403,11 @@ impl PgGUI {
     fn get_estat_servidor(&mut self) -> String {
         if self.estat_servidor.is_empty() {
             let clipg = CliPG::default(self.clipg_config_path.clone());
-            let conectat = if clipg.api.probar_connexio() { "✔ Conectat" } else { "❌ Desconectat" };
+            let conectat = match clipg.api.probar_connexio() {
+                Ok(()) => "✔ Conectat",
+                Err(PgApiError::Autenticacio) => "❌ Usuari o contrasenya incorrectes",
+                Err(_) => "❌ Desconectat",
+            };
             self.estat_servidor = format!("{} ({})", conectat, clipg.config.server.url,);
             self.config_url = clipg.config.server.url;
             self.config_usuari = clipg.config.server.usuari;
@@ -296,30 +419,137 @@ impl PgGUI {
         self.activitat.clone()
     }
     fn sincronitzar_tots(&mut self) {
-        self.activitat = Self::static_sincronitzar_tots(self.clipg_config_path.clone());
+        if self.dimoni.is_some() {
+            self.enviar_dimoni(Peticio::SincronitzarAra);
+            self.activitat = "Sincronització demanada al dimoni".to_string();
+            return;
+        }
+        self.activitat = String::new();
+        self.informe = Some(Self::static_sincronitzar_tots(self.clipg_config_path.clone()));
+        self.actualitzar_conflictes();
     }
-    fn static_sincronitzar_tots(clipg_config_path: Option<PathBuf>) -> String {
+    fn static_sincronitzar_tots(clipg_config_path: Option<PathBuf>) -> SyncReport {
         println!("Sincronitzant jocs...");
         let mut clipg = CliPG::default(clipg_config_path);
         let res = clipg.sync_all(false);
         println!("Sincronitzant jocs... Fet!");
-        res.trim().to_string()
+        res
     }
-    fn sincronitzar_joc(&mut self, joc: &mut Videojoc) {
+    fn sincronitzar_joc(&mut self, nom: String) {
+        // Amb sync_jocs es guarden a la configuracio les partides sincronitzades d'aquest joc
         let mut clipg = CliPG::default(self.clipg_config_path.clone());
-        let res = clipg.sync_joc(joc, false);
-        self.activitat = res.trim().to_string();
+        match clipg.sync_jocs(&[nom], false) {
+            Ok(res) => {
+                self.activitat = String::new();
+                self.informe = Some(res);
+            }
+            Err(err) => self.activitat = err,
+        }
+        self.actualitzar_conflictes();
+    }
+    fn editar_joc(&mut self, joc: &Videojoc) {
+        self.joc_afegit = joc.local_folder.clone().display().to_string();
+        self.joc_afegit_nom = joc.nom.clone().into_string().unwrap();
+        self.joc_afegit_politica = joc.politica_conflictes;
+        let clipg = CliPG::default(self.clipg_config_path.clone());
+        if let Some(vc) = clipg.config.videojocs_habilitats.list.iter().find(|vc| joc.nom == vc.nom.as_str()) {
+            self.joc_afegit_include = vc.include.join("\n");
+            self.joc_afegit_exclude = vc.exclude.join("\n");
+        }
+        self.joc_editat = Some(self.joc_afegit_nom.clone());
+        self.current_mode = AppMode::EditarJoc;
+    }
+    fn obrir_joc(&mut self, nom: &str) {
+        let mut clipg = CliPG::default(self.clipg_config_path.clone());
+        clipg.load_local_jocs();
+        match clipg.vjocs.iter().find(|joc| joc.nom.to_string_lossy() == nom) {
+            Some(joc) => self.editar_joc(joc),
+            None => self.activitat = format!("El joc \"{nom}\" no existeix"),
+        }
+    }
+    fn actualitzar_conflictes(&mut self) {
+        let mut clipg = CliPG::default(self.clipg_config_path.clone());
+        self.conflictes = clipg.conflictes_pendents().unwrap_or_default();
+    }
+    fn resoldre_conflicte(&mut self, nom_joc: &str, partida: &str, resolucio: Resolucio) {
+        let mut clipg = CliPG::default(self.clipg_config_path.clone());
+        self.activitat = match clipg.resoldre_conflicte(nom_joc, partida, resolucio) {
+            Ok(()) => format!("Conflicte de '{partida}' ({nom_joc}) resolt correctament"),
+            Err(err) => err,
+        };
+        self.actualitzar_conflictes();
+        if self.conflictes.is_empty() {
+            self.current_mode = AppMode::Dashboard;
+        }
+    }
+    fn format_mida(mida: Option<u64>) -> String {
+        match mida {
+            None => "-".to_string(),
+            Some(mida) if mida < 1024 => format!("{mida} B"),
+            Some(mida) if mida < 1024 * 1024 => format!("{:.1} KB", mida as f64 / 1024.0),
+            Some(mida) => format!("{:.1} MB", mida as f64 / (1024.0 * 1024.0)),
+        }
+    }
+    fn format_data(partida: &PartidaGuardada) -> String {
+        // Els servidors antics no envien la data de modificacio
+        if partida.timestamp == 0 && partida.timestamp_nanos == 0 {
+            return "-".to_string();
+        }
+        chrono::DateTime::from_timestamp(partida.timestamp, partida.timestamp_nanos)
+            .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "-".to_string())
     }
     fn eliminar_joc(&mut self, joc: &mut Videojoc) {
         let mut clipg = CliPG::default(self.clipg_config_path.clone());
         let nom_joc = joc.nom.to_str().unwrap().to_string();
         clipg.eliminar_joc(nom_joc.clone());
         self.activitat = format!("'{nom_joc}' eliminat correctament");
+        self.reiniciar_vigilant_thread();
+        self.enviar_dimoni(Peticio::RecarregarConfig);
     }
-    fn afegir_joc(&mut self, path_joc: String, nom_joc: String) {
+    fn afegir_joc(&mut self, path_joc: String, nom_joc: String, politica: PoliticaConflicte, include: Vec<String>, exclude: Vec<String>) {
         let mut clipg = CliPG::default(self.clipg_config_path.clone());
-        clipg.afegir_joc(path_joc, Some(nom_joc.clone()));
-        self.activitat = format!("'{nom_joc}' afegit correctament");
+        // Si no s'ha pogut afegir no es toca res: hi pot haver un altre joc amb aquest nom
+        let res = clipg
+            .afegir_joc(path_joc, Some(nom_joc.clone()))
+            .and_then(|()| clipg.set_politica_conflictes(&nom_joc, politica))
+            .and_then(|()| clipg.set_filtres(&nom_joc, include, exclude));
+        self.activitat = match res {
+            Ok(()) => format!("'{nom_joc}' afegit correctament"),
+            Err(err) => err,
+        };
+        self.reiniciar_vigilant_thread();
+        self.enviar_dimoni(Peticio::RecarregarConfig);
+    }
+    fn actualitzar_joc(&mut self, nom_original: String, path_joc: String, nom_joc: String, politica: PoliticaConflicte, include: Vec<String>, exclude: Vec<String>) {
+        let mut clipg = CliPG::default(self.clipg_config_path.clone());
+        let reanomenat = if nom_joc != nom_original { clipg.reanomenar_joc(&nom_original, nom_joc.clone()) } else { Ok(()) };
+        self.activitat = match reanomenat.and_then(|()| clipg.actualitzar_joc(&nom_joc, path_joc, politica, include, exclude)) {
+            Ok(()) => format!("'{nom_joc}' actualitzat correctament"),
+            Err(err) => err,
+        };
+        self.reiniciar_vigilant_thread();
+        self.enviar_dimoni(Peticio::RecarregarConfig);
+    }
+    fn netejar_joc_afegit(&mut self) {
+        self.joc_afegit = String::new();
+        self.joc_afegit_nom = String::new();
+        self.joc_afegit_politica = PoliticaConflicte::default();
+        self.joc_afegit_include = filtre::include_per_defecte().join("\n");
+        self.joc_afegit_exclude = filtre::exclude_per_defecte().join("\n");
+        self.joc_editat = None;
+    }
+    fn patrons(text: &str) -> Vec<String> {
+        text.lines().map(str::trim).filter(|p| !p.is_empty()).map(str::to_string).collect()
+    }
+    fn actualitzar_dimoni(&mut self) {
+        self.dimoni = dimoni::enviar(Peticio::Estat).ok().map(|resposta| resposta.estat);
+    }
+    fn enviar_dimoni(&mut self, peticio: Peticio) {
+        // Nomes si hi ha un dimoni en marxa. Si ha deixat de respondre, la GUI torna a sincronitzar pel seu compte.
+        if self.dimoni.is_some() {
+            self.dimoni = dimoni::enviar(peticio).ok().map(|resposta| resposta.estat);
+        }
     }
     fn guardar_configuracio(&mut self, url: String, usuari: String, contrasenya: String, storage: &mut dyn eframe::Storage) {
         let mut clipg = CliPG::default(self.clipg_config_path.clone());
@@ -334,6 +564,7 @@ impl PgGUI {
             self.config_tancar_aplicacio = loaded.config_tancar_aplicacio;
             self.config_sincronitzar_al_tancar = loaded.config_sincronitzar_al_tancar;
             self.config_sincronitzar_cada_x_minuts = loaded.config_sincronitzar_cada_x_minuts;
+            self.config_vigilar_carpetes = loaded.config_vigilar_carpetes;
         }
     }
 }
@@ -393,10 +624,40 @@ impl PgGUI {
                 .corner_radius(6.0),
         )
     }
+    fn ui_informe(ui: &mut egui::Ui, informe: &SyncReport) {
+        for joc in informe.jocs.iter() {
+            ui.strong(&joc.nom);
+            if let Some(error) = &joc.error {
+                ui.colored_label(egui::Color32::DARK_RED, format!("    ⛔ Error obtenint les partides del servidor: {error}"));
+            }
+            for partida in joc.partides.iter() {
+                let color = match partida.accio {
+                    SyncAction::Error { .. } => egui::Color32::DARK_RED,
+                    SyncAction::Conflict { .. } => egui::Color32::from_rgb(200, 120, 0),
+                    SyncAction::Unchanged => egui::Color32::GRAY,
+                    _ => ui.visuals().text_color(),
+                };
+                ui.horizontal(|row_ui| {
+                    row_ui.colored_label(color, format!("    {partida}"));
+                    if partida.bytes > 0 {
+                        row_ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |right_ui| {
+                            right_ui.weak(format!("{} · {} ms", informe::format_bytes(partida.bytes), partida.durada.as_millis()));
+                        });
+                    }
+                });
+            }
+        }
+        ui.separator();
+        ui.weak(informe.resum());
+    }
     fn setup_top_panel(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         egui::menu::bar(ui, |ui| {
             ui.menu_button("Fitxer", |ui| {
                 if ui.button("Afegir joc").clicked() {
+                    // Si s'havia deixat un joc a mig editar, es comenca un de nou
+                    if self.joc_editat.is_some() {
+                        self.netejar_joc_afegit();
+                    }
                     self.current_mode = AppMode::EditarJoc;
                 }
                 if ui.button("Tancar i sortir").clicked() {
@@ -408,12 +669,22 @@ impl PgGUI {
                 if ui.button("Preferències").clicked() {
                     self.current_mode = AppMode::Configuracio;
                 }
+                if ui.button("Conflictes").clicked() {
+                    self.actualitzar_conflictes();
+                    self.current_mode = AppMode::Conflictes;
+                }
             });
         });
     }
     fn setup_dashboard(&mut self, centered_ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
         self.setup_dashboard_videojocs_habilitats(centered_ui);
+        if !self.conflictes.is_empty() {
+            self.setup_dashboard_conflictes(centered_ui);
+        }
         self.setup_dashboard_servidor_status(centered_ui);
+        if self.dimoni.is_some() {
+            self.setup_dashboard_dimoni(centered_ui);
+        }
         self.setup_dashboard_activitat(centered_ui);
     }
     fn setup_dashboard_videojocs_habilitats(&mut self, centered_ui: &mut egui::Ui) {
@@ -422,6 +693,9 @@ impl PgGUI {
             egui::ScrollArea::vertical().show(group_ui, |scroll_ui| {
                 scroll_ui.horizontal(|row_ui| {
                     if Self::ui_button(row_ui, "+ Afegir joc").clicked() {
+                        if self.joc_editat.is_some() {
+                            self.netejar_joc_afegit();
+                        }
                         self.current_mode = AppMode::EditarJoc;
                     }
                     row_ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |right_ui| {
@@ -441,12 +715,10 @@ impl PgGUI {
                                 self.eliminar_joc(joc);
                             }
                             if Self::ui_secondary_button(right_ui, "🛠").clicked() {
-                                self.joc_afegit = joc.local_folder.clone().display().to_string();
-                                self.joc_afegit_nom = joc.nom.clone().into_string().unwrap();
-                                self.current_mode = AppMode::EditarJoc;
+                                self.editar_joc(joc);
                             }
                             if Self::ui_primary_secondary_button(right_ui, "🔄").clicked() {
-                                self.sincronitzar_joc(joc);
+                                self.sincronitzar_joc(joc.nom.to_string_lossy().to_string());
                             }
                         });
                     });
@@ -455,6 +727,19 @@ impl PgGUI {
             });
         });
     }
+    fn setup_dashboard_conflictes(&mut self, centered_ui: &mut egui::Ui) {
+        centered_ui.add_space(10.0);
+        Self::ui_card(centered_ui, None, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::DARK_RED, format!("⚠ {} conflictes pendents de resoldre", self.conflictes.len()));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                    if Self::ui_button(ui, "Revisar").clicked() {
+                        self.current_mode = AppMode::Conflictes;
+                    }
+                });
+            });
+        });
+    }
     fn setup_dashboard_servidor_status(&mut self, centered_ui: &mut egui::Ui) {
         centered_ui.add_space(10.0);
         Self::ui_card(centered_ui, None, |ui| {
@@ -476,6 +761,40 @@ impl PgGUI {
             });
         });
     }
+    fn setup_dashboard_dimoni(&mut self, centered_ui: &mut egui::Ui) {
+        let Some(estat) = self.dimoni.clone() else {
+            return;
+        };
+        centered_ui.add_space(10.0);
+        Self::ui_card(centered_ui, None, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Dimoni:");
+                if estat.pausat {
+                    ui.colored_label(egui::Color32::DARK_RED, "⏸ Pausat");
+                } else {
+                    ui.colored_label(egui::Color32::DARK_GREEN, "✔ En marxa");
+                }
+                if let (Some(data), Some(resum)) = (estat.ultima_sincronitzacio, &estat.ultim_resum) {
+                    let data = chrono::DateTime::from_timestamp(data, 0)
+                        .map(|d| d.with_timezone(&chrono::Local).format("%H:%M").to_string())
+                        .unwrap_or_default();
+                    ui.label(format!("(última sincronització a les {data}: {resum})"));
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                    let peticio = if estat.pausat { Peticio::Reprendre } else { Peticio::Pausar };
+                    if Self::ui_button(ui, if estat.pausat { "▶ Reprendre" } else { "⏸ Pausar" }).clicked() {
+                        self.enviar_dimoni(peticio);
+                    }
+                    if Self::ui_secondary_button(ui, "🔄").clicked() {
+                        self.actualitzar_dimoni();
+                    }
+                });
+            });
+            for joc in estat.jocs_no_vigilats.iter() {
+                ui.colored_label(egui::Color32::DARK_RED, format!("⚠ No es vigila {joc}"));
+            }
+        });
+    }
     fn setup_dashboard_activitat(&mut self, centered_ui: &mut egui::Ui) {
         centered_ui.add_space(10.0);
         Self::ui_card(centered_ui, None, |group_ui| {
@@ -483,8 +802,13 @@ impl PgGUI {
                 vertical_ui.vertical(|ui| {
                     egui::ScrollArea::vertical().auto_shrink([false, true]).show(ui, |scroll_ui| {
                         let res = self.get_activitat();
-                        for line in res.split("\n") {
-                            scroll_ui.label(line);
+                        match &self.informe {
+                            Some(informe) if res.is_empty() => Self::ui_informe(scroll_ui, informe),
+                            _ => {
+                                for line in res.split("\n") {
+                                    scroll_ui.label(line);
+                                }
+                            }
                         }
                     });
                 });
@@ -525,6 +849,13 @@ impl PgGUI {
                 vui.horizontal(|ui| {
                     ui.label("(deixar a 0 per desactivar la sincornització periòdica)");
                 });
+                vui.add_space(4.0);
+                vui.horizontal(|ui| {
+                    ui.label("Sincronitzar un joc quan s'hi desa una partida:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.config_vigilar_carpetes, "");
+                    });
+                });
                 vui.add_space(10.0);
                 vui.horizontal(|hui| {
                     hui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -582,7 +913,8 @@ impl PgGUI {
     }
     fn setup_editar_joc(&mut self, centered_ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
         centered_ui.add_space(10.0);
-        Self::ui_card(centered_ui, Some("🎮 Afegir Joc"), |group_ui| {
+        let titol = if self.joc_editat.is_some() { "🎮 Editar Joc" } else { "🎮 Afegir Joc" };
+        Self::ui_card(centered_ui, Some(titol), |group_ui| {
             group_ui.vertical_centered_justified(|vui| {
                 vui.add_space(4.0);
                 vui.horizontal(|hui| {
@@ -613,18 +945,57 @@ impl PgGUI {
                     ui.label("Nom:");
                     ui.add(egui::TextEdit::singleline(&mut self.joc_afegit_nom).desired_width(f32::INFINITY));
                 });
+                vui.add_space(4.0);
+                vui.horizontal_top(|ui| {
+                    ui.label("En cas de conflicte:");
+                    egui::ComboBox::from_id_salt("politica_conflictes")
+                        .selected_text(self.joc_afegit_politica.descripcio())
+                        .show_ui(ui, |ui| {
+                            for politica in PoliticaConflicte::TOTES {
+                                ui.selectable_value(&mut self.joc_afegit_politica, politica, politica.descripcio());
+                            }
+                        });
+                });
+                vui.add_space(4.0);
+                vui.horizontal_top(|ui| {
+                    ui.label("Sincronitzar:");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.joc_afegit_include)
+                            .desired_rows(2)
+                            .desired_width(f32::INFINITY)
+                            .hint_text("Un patró per línia (p.ex. *.sav). Buit: tots els fitxers"),
+                    );
+                });
+                vui.add_space(4.0);
+                vui.horizontal_top(|ui| {
+                    ui.label("No sincronitzar:");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.joc_afegit_exclude)
+                            .desired_rows(3)
+                            .desired_width(f32::INFINITY)
+                            .hint_text("Un patró per línia (p.ex. *.log, shadercache/**)"),
+                    );
+                });
+                let include = Self::patrons(&self.joc_afegit_include);
+                let exclude = Self::patrons(&self.joc_afegit_exclude);
+                let filtre = Filtre::new(&include, &exclude);
+                if let Err(err) = &filtre {
+                    vui.colored_label(egui::Color32::DARK_RED, err);
+                }
                 vui.add_space(10.0);
                 vui.horizontal(|hui| {
                     hui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if Self::ui_primary_button(ui, "Afegir").clicked() {
-                            self.afegir_joc(self.joc_afegit.clone(), self.joc_afegit_nom.clone());
-                            self.joc_afegit = String::new();
-                            self.joc_afegit_nom = String::new();
+                        let boto = if self.joc_editat.is_some() { "Desar" } else { "Afegir" };
+                        if ui.add_enabled_ui(filtre.is_ok(), |ui| Self::ui_primary_button(ui, boto)).inner.clicked() {
+                            match self.joc_editat.clone() {
+                                Some(nom_original) => self.actualitzar_joc(nom_original, self.joc_afegit.clone(), self.joc_afegit_nom.clone(), self.joc_afegit_politica, include, exclude),
+                                None => self.afegir_joc(self.joc_afegit.clone(), self.joc_afegit_nom.clone(), self.joc_afegit_politica, include, exclude),
+                            }
+                            self.netejar_joc_afegit();
                             self.current_mode = AppMode::Dashboard;
                         }
                         if Self::ui_danger_button(ui, "Cancel·lar").clicked() {
-                            self.joc_afegit = String::new();
-                            self.joc_afegit_nom = String::new();
+                            self.netejar_joc_afegit();
                             self.current_mode = AppMode::Dashboard;
                         }
                     });
@@ -633,6 +1004,74 @@ impl PgGUI {
             group_ui.add_space(10.0);
         });
     }
+    fn setup_conflictes(&mut self, centered_ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        centered_ui.add_space(10.0);
+        centered_ui.horizontal(|hui| {
+            if Self::ui_button(hui, "← Tornar").clicked() {
+                self.current_mode = AppMode::Dashboard;
+            }
+            hui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if Self::ui_primary_secondary_button(ui, "🔄").clicked() {
+                    self.actualitzar_conflictes();
+                }
+            });
+        });
+        if self.conflictes.is_empty() {
+            centered_ui.add_space(10.0);
+            Self::ui_card(centered_ui, None, |ui| {
+                ui.label("No hi ha cap conflicte pendent.");
+            });
+            return;
+        }
+        let mut resolt = None;
+        egui::ScrollArea::vertical().show(centered_ui, |scroll_ui| {
+            for (i, conflicte) in self.conflictes.iter().enumerate() {
+                let partida = conflicte.local.nom.to_string_lossy().to_string();
+                scroll_ui.add_space(10.0);
+                Self::ui_card(scroll_ui, Some(&format!("⚠ {}: {partida}", conflicte.joc)), |group_ui| {
+                    egui::Grid::new(("conflicte", i)).num_columns(3).spacing([30.0, 6.0]).striped(true).show(group_ui, |grid| {
+                        grid.label("");
+                        grid.strong("Local");
+                        grid.strong("Servidor");
+                        grid.end_row();
+                        grid.label("Mida:");
+                        grid.label(Self::format_mida(conflicte.local.mida));
+                        grid.label(Self::format_mida(conflicte.remot.mida));
+                        grid.end_row();
+                        grid.label("Modificada:");
+                        grid.label(Self::format_data(&conflicte.local));
+                        grid.label(Self::format_data(&conflicte.remot));
+                        grid.end_row();
+                        grid.label("Hash:");
+                        grid.monospace(&conflicte.local.hash[..conflicte.local.hash.len().min(16)]).on_hover_text(&conflicte.local.hash);
+                        grid.monospace(&conflicte.remot.hash[..conflicte.remot.hash.len().min(16)]).on_hover_text(&conflicte.remot.hash);
+                        grid.end_row();
+                        grid.label("Dispositiu:");
+                        grid.label(format!("{} (aquest)", conflicte.local.dispositiu.as_deref().unwrap_or("-")));
+                        grid.label(conflicte.remot.dispositiu.as_deref().unwrap_or("-"));
+                        grid.end_row();
+                    });
+                    group_ui.add_space(10.0);
+                    group_ui.horizontal(|hui| {
+                        hui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if Self::ui_secondary_button(ui, "Quedar-me totes dues").clicked() {
+                                resolt = Some((conflicte.joc.clone(), partida.clone(), Resolucio::Ambdues));
+                            }
+                            if Self::ui_primary_secondary_button(ui, "Quedar-me la del servidor").clicked() {
+                                resolt = Some((conflicte.joc.clone(), partida.clone(), Resolucio::Remot));
+                            }
+                            if Self::ui_primary_secondary_button(ui, "Quedar-me la local").clicked() {
+                                resolt = Some((conflicte.joc.clone(), partida.clone(), Resolucio::Local));
+                            }
+                        });
+                    });
+                });
+            }
+        });
+        if let Some((nom_joc, partida, resolucio)) = resolt {
+            self.resoldre_conflicte(&nom_joc, &partida, resolucio);
+        }
+    }
 }
 // Bucle principal de egui
 impl eframe::App for PgGUI {
@@ -658,6 +1097,9 @@ impl eframe::App for PgGUI {
                         AppMode::EditarJoc => {
                             self.setup_editar_joc(centered_ui, _frame);
                         }
+                        AppMode::Conflictes => {
+                            self.setup_conflictes(centered_ui, _frame);
+                        }
                     };
                 });
             });
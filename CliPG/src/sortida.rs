@@ -0,0 +1,243 @@
+use crate::cli_pg::{CliPgConfig, ConflictePendent, EstatPartida, VideojocConfig};
+use crate::informe::SyncReport;
+use crate::llancament::Llancament;
+use crate::pg_api::VersioPartidaGuardada;
+use crate::videojoc::conflicte::PoliticaConflicte;
+use crate::videojoc::partida_guardada::PartidaGuardada;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::str::FromStr;
+
+// Format de la sortida de la linia de comandes (`--output`) i codis de sortida.
+// Els noms dels camps del JSON son estables: els fan servir scripts i widgets.
+
+pub const CODI_OK: i32 = 0;
+// No s'ha pogut fer res
+pub const CODI_ERROR: i32 = 1;
+// Una part s'ha fet i una altra ha fallat (p.ex. algunes partides no s'han pogut sincronitzar)
+pub const CODI_PARCIAL: i32 = 2;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Sortida {
+    Text,
+    Json,
+}
+impl FromStr for Sortida {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Sortida::Text),
+            "json" => Ok(Sortida::Json),
+            _ => Err(format!("\"{s}\" no es un format de sortida valid (text, json)")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VideojocJson<'a> {
+    nom: &'a str,
+    path: &'a str,
+    conflict_policy: PoliticaConflicte,
+    include: &'a [String],
+    exclude: &'a [String],
+    partides_guardades: usize,
+}
+impl<'a> From<&'a VideojocConfig> for VideojocJson<'a> {
+    fn from(vc: &'a VideojocConfig) -> Self {
+        VideojocJson {
+            nom: &vc.nom,
+            path: &vc.path,
+            conflict_policy: vc.conflict_policy,
+            include: &vc.include,
+            exclude: &vc.exclude,
+            partides_guardades: vc.partides_guardades.len(),
+        }
+    }
+}
+
+pub fn videojocs_json(config: &CliPgConfig) -> Value {
+    let videojocs: Vec<VideojocJson> = config.videojocs_habilitats.list.iter().map(VideojocJson::from).collect();
+    json!({ "videojocs": videojocs })
+}
+
+pub fn videojoc_json(resultat: Result<&VideojocConfig, String>) -> Value {
+    // Resultat d'afegir o eliminar un videojoc
+    match resultat {
+        Ok(vc) => json!({ "ok": true, "videojoc": VideojocJson::from(vc) }),
+        Err(error) => error_json(&error),
+    }
+}
+
+pub fn partides_json(partides: &[EstatPartida]) -> Value {
+    let copia = |p: &Option<PartidaGuardada>| match p {
+        Some(p) => json!({ "hash": p.hash, "mida": p.mida, "timestamp": p.timestamp }),
+        None => Value::Null,
+    };
+    let partides: Vec<Value> = partides
+        .iter()
+        .map(|p| json!({ "nom": p.nom, "estat": p.estat(), "local": copia(&p.local), "remot": copia(&p.remot) }))
+        .collect();
+    json!({ "partides": partides })
+}
+
+pub fn versions_json(versions: &[VersioPartidaGuardada]) -> Value {
+    // De la mes recent a la mes antiga, com les retorna el servidor
+    json!({ "versions": versions })
+}
+
+pub fn conflictes_json(conflictes: &[ConflictePendent]) -> Value {
+    let copia = |p: &PartidaGuardada| json!({ "hash": p.hash, "mida": p.mida, "timestamp": p.timestamp, "dispositiu": p.dispositiu });
+    let conflictes: Vec<Value> = conflictes
+        .iter()
+        .map(|c| json!({ "joc": c.joc, "partida": c.local.nom.to_string_lossy(), "local": copia(&c.local), "remot": copia(&c.remot) }))
+        .collect();
+    json!({ "conflictes": conflictes })
+}
+
+pub fn missatge_json(missatge: &str) -> Value {
+    // Resultat de les comandes que nomes canvien alguna cosa (p.ex. `games policy`, `server set`)
+    json!({ "ok": true, "missatge": missatge })
+}
+
+pub fn servidor_json(url: &str, resultat: Result<(), String>) -> Value {
+    match resultat {
+        Ok(()) => json!({ "ok": true, "url": url }),
+        Err(error) => json!({ "ok": false, "url": url, "error": error }),
+    }
+}
+
+pub fn error_json(error: &str) -> Value {
+    json!({ "ok": false, "error": error })
+}
+
+pub fn informe_json(informe: &SyncReport, dry_run: bool) -> Value {
+    let mut valor = serde_json::to_value(informe).unwrap();
+    valor["ok"] = json!(!informe.te_errors());
+    valor["dry_run"] = json!(dry_run);
+    valor["bytes"] = json!(informe.bytes());
+    valor
+}
+
+pub fn llancament_json(llancament: &Llancament) -> Value {
+    let informe = |res: &Result<SyncReport, String>| match res {
+        Ok(informe) => informe_json(informe, false),
+        Err(error) => error_json(error),
+    };
+    // `codi_joc` es null si el joc no s'ha executat; `error` diu per que (o per que s'hauria bloquejat, amb --force)
+    let codi_joc = llancament.codi_joc.as_ref().and_then(|codi| codi.as_ref().ok());
+    let mut valor = json!({ "ok": llancament.codi() == CODI_OK, "baixada": informe(&llancament.baixada), "codi_joc": codi_joc });
+    if let Some(Err(error)) = &llancament.codi_joc {
+        valor["error"] = json!(error);
+    } else if let Some(error) = &llancament.bloqueig {
+        valor["error"] = json!(error);
+    }
+    if let Some(pujada) = &llancament.pujada {
+        valor["pujada"] = informe(pujada);
+    }
+    valor
+}
+
+pub fn codi_informe(informe: &SyncReport) -> i32 {
+    if !informe.te_errors() {
+        return CODI_OK;
+    }
+    // Parcial si almenys un joc s'ha pogut sincronitzar, encara que sigui en part
+    let algun_correcte = informe
+        .jocs
+        .iter()
+        .any(|j| j.error.is_none() && (j.partides.is_empty() || j.partides.iter().any(|p| !p.accio.es_error())));
+    if algun_correcte { CODI_PARCIAL } else { CODI_ERROR }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::cli_pg::{ServerConfig, VideojocConfigList};
+    use crate::informe::{SyncAction, SyncJoc, SyncPartida};
+    use crate::videojoc::filtre;
+    use std::time::Duration;
+    #[test]
+    fn test_sortida_json() {
+        assert_eq!("json".parse::<Sortida>(), Ok(Sortida::Json));
+        assert!("yaml".parse::<Sortida>().is_err());
+        let vc = VideojocConfig {
+            nom: "Joc".to_string(),
+            path: "/jocs/Joc".to_string(),
+            partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::Manual,
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
+        };
+        let config = CliPgConfig {
+            server: ServerConfig {
+                url: "http://localhost:8000".to_string(),
+                usuari: "admin".to_string(),
+                contrasenya: "admin".to_string(),
+                ca_bundle: None,
+                empremta: None,
+            },
+            dispositiu: None,
+            videojocs_habilitats: VideojocConfigList { list: vec![vc.clone()] },
+        };
+        assert_eq!(
+            videojocs_json(&config),
+            json!({"videojocs": [{
+                "nom": "Joc",
+                "path": "/jocs/Joc",
+                "conflict_policy": "manual",
+                "include": ["*"],
+                "exclude": ["bck_*", "desktop.ini", "Thumbs.db", ".DS_Store", "*.log"],
+                "partides_guardades": 0
+            }]})
+        );
+        assert_eq!(videojoc_json(Ok(&vc))["videojoc"]["nom"], "Joc");
+        assert_eq!(videojoc_json(Err("no existeix".to_string())), json!({"ok": false, "error": "no existeix"}));
+        assert_eq!(servidor_json("http://pg", Ok(())), json!({"ok": true, "url": "http://pg"}));
+        let partides = partides_json(&[EstatPartida {
+            nom: "save1.sav".to_string(),
+            local: None,
+            remot: Some(PartidaGuardada::new("/jocs/Joc/save1.sav".to_string()).with_hash("aa".to_string())),
+        }]);
+        assert_eq!(partides["partides"][0]["estat"], "nomes_remot");
+        assert_eq!(partides["partides"][0]["local"], Value::Null);
+        assert_eq!(partides["partides"][0]["remot"]["hash"], "aa");
+        // Codis de sortida segons el que hagi fallat
+        let partida = |accio| SyncPartida {
+            nom: "save".to_string(),
+            accio,
+            bytes: 3,
+            durada: Duration::ZERO,
+        };
+        let joc = |partides| SyncJoc { partides, ..SyncJoc::new("Joc") };
+        let error = || SyncAction::Error { error: "tallat".to_string() };
+        let mut informe = SyncReport {
+            jocs: vec![joc(vec![partida(SyncAction::Upload { nova: true })])],
+            durada: Duration::ZERO,
+        };
+        assert_eq!(codi_informe(&informe), CODI_OK);
+        assert_eq!(informe_json(&informe, true)["ok"], true);
+        assert_eq!(informe_json(&informe, true)["dry_run"], true);
+        assert_eq!(informe_json(&informe, true)["bytes"], 3);
+        informe.jocs.push(joc(vec![partida(error())]));
+        assert_eq!(codi_informe(&informe), CODI_PARCIAL);
+        assert_eq!(informe_json(&informe, false)["ok"], false);
+        informe.jocs.remove(0);
+        assert_eq!(codi_informe(&informe), CODI_ERROR);
+        informe.jocs = vec![SyncJoc {
+            error: Some("apagat".to_string()),
+            ..SyncJoc::new("Joc")
+        }];
+        assert_eq!(codi_informe(&informe), CODI_ERROR);
+        // `clipg run` bloquejat abans d'executar el joc
+        let llancament = Llancament {
+            baixada: Err("\"Joc\" no es cap joc habilitat.".to_string()),
+            bloqueig: Some("\"Joc\" no es cap joc habilitat.".to_string()),
+            codi_joc: None,
+            pujada: None,
+        };
+        assert_eq!(
+            llancament_json(&llancament),
+            json!({"ok": false, "baixada": {"ok": false, "error": "\"Joc\" no es cap joc habilitat."}, "codi_joc": null, "error": "\"Joc\" no es cap joc habilitat."})
+        );
+    }
+}
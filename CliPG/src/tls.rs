@@ -0,0 +1,104 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::sync::Arc;
+
+// Opcions TLS del client per servidors amb certificats propis (p.ex. un SerPG a casa amb un certificat autosignat):
+//  - ca_bundle: fitxer PEM amb certificats de CA addicionals, a mes dels del sistema.
+//  - empremta: SHA-256 del certificat del servidor (el que mostra SerPG en arrencar, "AB:CD:..." o en hex).
+//    Si hi ha empremta nomes s'accepta aquest certificat: no es comprova la CA ni el nom del servidor.
+// Les dues opcions no es poden fer servir alhora, i l'empremta nomes te sentit amb una URL https://.
+
+pub fn parse_empremta(empremta: &str) -> Option<Vec<u8>> {
+    let hex: String = empremta.chars().filter(|c| *c != ':' && !c.is_whitespace()).collect();
+    hex::decode(hex).ok().filter(|bytes| bytes.len() == 32)
+}
+
+pub fn client(url: &str, ca_bundle: Option<&str>, empremta: Option<&str>) -> Result<reqwest::blocking::Client, String> {
+    let mut builder = reqwest::blocking::Client::builder();
+    if ca_bundle.is_some() && empremta.is_some() {
+        return Err("No es pot donar alhora un fitxer de CA i l'empremta del certificat: l'empremta no comprova la CA".to_string());
+    }
+    if empremta.is_some() && !url.to_ascii_lowercase().starts_with("https://") {
+        return Err(format!("L'empremta del certificat nomes es pot fer servir amb una URL https:// (\"{url}\" no ho es)"));
+    }
+    if let Some(empremta) = empremta {
+        let empremta = parse_empremta(empremta).ok_or_else(|| format!("L'empremta \"{empremta}\" no es un SHA-256 valid"))?;
+        builder = builder.tls_backend_preconfigured(config_amb_empremta(empremta)?);
+    } else if let Some(ca_bundle) = ca_bundle {
+        let pem = fs::read(ca_bundle).map_err(|e| format!("No s'ha pogut llegir el fitxer de CA {ca_bundle}: {e}"))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| format!("Fitxer de CA {ca_bundle} no valid: {e}"))?;
+        builder = builder.tls_certs_merge(certs);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn config_amb_empremta(empremta: Vec<u8>) -> Result<ClientConfig, String> {
+    let proveidor = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let verificador = VerificadorEmpremta {
+        empremta,
+        proveidor: proveidor.clone(),
+    };
+    Ok(ClientConfig::builder_with_provider(proveidor)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verificador))
+        .with_no_client_auth())
+}
+
+#[derive(Debug)]
+struct VerificadorEmpremta {
+    empremta: Vec<u8>,
+    proveidor: Arc<CryptoProvider>,
+}
+impl ServerCertVerifier for VerificadorEmpremta {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if Sha256::digest(end_entity.as_ref()).as_slice() == self.empremta.as_slice() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+        }
+    }
+    // Les signatures del handshake es comproven igual que sense empremta: demostren que el servidor te la clau del certificat
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.proveidor.signature_verification_algorithms)
+    }
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.proveidor.signature_verification_algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.proveidor.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_empremta() {
+        let hex = "ab".repeat(32);
+        let amb_dos_punts = vec!["AB"; 32].join(":");
+        assert_eq!(parse_empremta(&hex), Some(vec![0xab; 32]));
+        assert_eq!(parse_empremta(&amb_dos_punts), Some(vec![0xab; 32]));
+        assert_eq!(parse_empremta("AB:CD"), None);
+        assert_eq!(parse_empremta(&"zz".repeat(32)), None);
+        let url = "https://localhost:3000";
+        assert!(client(url, None, Some("AB:CD")).is_err());
+        assert!(client(url, Some("/no/existeix/ca.pem"), None).is_err());
+        assert!(client(url, None, Some(&hex)).is_ok());
+        // Opcions que no tindrien efecte
+        assert!(client(url, Some("/no/existeix/ca.pem"), Some(&hex)).unwrap_err().contains("alhora"));
+        assert!(client("http://localhost:3000", None, Some(&hex)).unwrap_err().contains("https://"));
+    }
+}
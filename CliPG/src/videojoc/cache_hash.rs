@@ -0,0 +1,176 @@
+use crate::escriptura;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Cache persistent dels hash de les partides locals. Una entrada es valida mentre la mida i la data
+// de modificacio del fitxer no canviin, aixi carregar un joc nomes fa un stat de cada partida.
+
+// Els fitxers modificats fa menys d'aquest temps no es guarden a la cache: en sistemes de fitxers amb
+// poca precisio a la data de modificacio, una escriptura posterior podria no canviar-la.
+const MARGE_MTIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct EntradaCache {
+    mida: u64,
+    mtime: i64,
+    mtime_nanos: u32,
+    hash: String,
+}
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CacheHash {
+    // Clau: ruta completa de la partida
+    partides: HashMap<String, EntradaCache>,
+    #[serde(skip)]
+    modificat: bool,
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    hash_reader(&mut fs::File::open(path)?)
+}
+
+pub fn hash_reader(f: &mut impl Read) -> io::Result<String> {
+    // Llegeix el contingut a trossos, sense carregar-lo sencer a memoria
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+impl CacheHash {
+    pub fn load(path: &Path) -> Self {
+        // Si la cache no existeix o no es pot llegir es torna a omplir
+        fs::read_to_string(path).ok().and_then(|content| serde_json::from_str(&content).ok()).unwrap_or_default()
+    }
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        // De pas es treuen les partides que ja no existeixen
+        self.partides.retain(|p, _| Path::new(p).is_file());
+        escriptura::escriure(path, serde_json::to_string(&self)?.as_bytes())?;
+        self.modificat = false;
+        Ok(())
+    }
+    pub fn hash(&mut self, path: &Path) -> io::Result<String> {
+        let metadata = fs::metadata(path)?;
+        if let Some(hash) = self.consultar(path, &metadata)? {
+            return Ok(hash);
+        }
+        let hash = hash_file(path)?;
+        self.apuntar(path, &metadata, &hash)?;
+        Ok(hash)
+    }
+    fn consultar(&self, path: &Path, metadata: &fs::Metadata) -> io::Result<Option<String>> {
+        // El hash de la cache si el fitxer no ha canviat des que es va calcular
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(self
+            .partides
+            .get(path.to_string_lossy().as_ref())
+            .filter(|e| e.mida == metadata.len() && e.mtime == mtime.as_secs() as i64 && e.mtime_nanos == mtime.subsec_nanos())
+            .map(|e| e.hash.clone()))
+    }
+    fn apuntar(&mut self, path: &Path, metadata: &fs::Metadata, hash: &str) -> io::Result<()> {
+        let modified = metadata.modified()?;
+        let mtime = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let clau = path.to_string_lossy().to_string();
+        let recent = SystemTime::now().duration_since(modified).map(|d| d < MARGE_MTIME).unwrap_or(true);
+        if recent {
+            self.partides.remove(&clau);
+        } else {
+            self.partides.insert(
+                clau,
+                EntradaCache {
+                    mida: metadata.len(),
+                    mtime: mtime.as_secs() as i64,
+                    mtime_nanos: mtime.subsec_nanos(),
+                    hash: hash.to_string(),
+                },
+            );
+            self.modificat = true;
+        }
+        Ok(())
+    }
+}
+
+fn get_cache_path() -> Option<PathBuf> {
+    // Els tests no fan servir la cache de l'usuari
+    if cfg!(test) {
+        return None;
+    }
+    Some(ProjectDirs::from("com", "bcedu", "CliPG")?.cache_dir().join("hashes.json"))
+}
+
+fn cache() -> &'static Mutex<CacheHash> {
+    static CACHE: OnceLock<Mutex<CacheHash>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(get_cache_path().map(|p| CacheHash::load(&p)).unwrap_or_default()))
+}
+
+pub fn hash(path: &Path) -> io::Result<String> {
+    // La cache es compartida per tots els threads (GUI, sincronitzacio, vigilant): no es bloqueja mentre
+    // es llegeix el fitxer, que en una partida gran pot trigar
+    let metadata = fs::metadata(path)?;
+    if let Some(hash) = cache().lock().unwrap().consultar(path, &metadata)? {
+        return Ok(hash);
+    }
+    let hash = hash_file(path)?;
+    cache().lock().unwrap().apuntar(path, &metadata, &hash)?;
+    Ok(hash)
+}
+
+pub fn guardar() {
+    // Es crida despres de carregar les partides d'un joc, no per cada hash nou
+    let mut cache = cache().lock().unwrap();
+    if !cache.modificat {
+        return;
+    }
+    if let Some(path) = get_cache_path()
+        && let Err(e) = cache.save(&path)
+    {
+        eprintln!("No s'ha pogut guardar la cache de hash {}: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use filetime::{FileTime, set_file_mtime};
+    #[test]
+    fn test_cache_hash() {
+        let dir = std::env::temp_dir().join("clipg_test_cache_hash");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let save = dir.join("save1.sav");
+        let cache_path = dir.join("cache/hashes.json");
+        fs::write(&save, "v1").unwrap();
+        let mut cache = CacheHash::default();
+        // Un fitxer acabat de modificar no es guarda a la cache
+        assert_eq!(cache.hash(&save).unwrap(), hash_file(&save).unwrap());
+        assert!(cache.partides.is_empty());
+        set_file_mtime(&save, FileTime::from_unix_time(1_700_000_000, 5)).unwrap();
+        let hash = cache.hash(&save).unwrap();
+        assert_eq!(cache.partides.len(), 1);
+        cache.save(&cache_path).unwrap();
+        // Mentre la mida i la data no canvien es fa servir el hash de la cache sense llegir el fitxer
+        let mut cache = CacheHash::load(&cache_path);
+        cache.partides.get_mut(save.to_str().unwrap()).unwrap().hash = "fals".to_string();
+        assert_eq!(cache.hash(&save).unwrap(), "fals");
+        // Si canvia la data es torna a calcular
+        set_file_mtime(&save, FileTime::from_unix_time(1_700_000_001, 5)).unwrap();
+        assert_eq!(cache.hash(&save).unwrap(), hash);
+        // Les partides eliminades surten de la cache en guardar-la
+        fs::remove_file(&save).unwrap();
+        cache.save(&cache_path).unwrap();
+        assert!(CacheHash::load(&cache_path).partides.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// Que fer quan una partida s'ha modificat a local i al servidor des de l'ultima sincronitzacio.
+// Es configura per cada videojoc (`conflict_policy` a la configuracio).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PoliticaConflicte {
+    // Es queda la mes recent i l'altra es guarda com a "bck_{data}_{nom}"
+    #[default]
+    MesRecentAmbCopia,
+    PreferirLocal,
+    PreferirRemot,
+    // Es queden totes dues: la local passa a dir-se "{nom}_{dispositiu}"
+    MantenirAmbdues,
+    // No es toca res: el conflicte queda pendent fins que es resol a ma
+    Manual,
+}
+impl PoliticaConflicte {
+    pub const TOTES: [PoliticaConflicte; 5] = [
+        PoliticaConflicte::MesRecentAmbCopia,
+        PoliticaConflicte::PreferirLocal,
+        PoliticaConflicte::PreferirRemot,
+        PoliticaConflicte::MantenirAmbdues,
+        PoliticaConflicte::Manual,
+    ];
+    pub fn es_defecte(&self) -> bool {
+        *self == PoliticaConflicte::default()
+    }
+    pub fn id(&self) -> &'static str {
+        match self {
+            PoliticaConflicte::MesRecentAmbCopia => "mes_recent_amb_copia",
+            PoliticaConflicte::PreferirLocal => "preferir_local",
+            PoliticaConflicte::PreferirRemot => "preferir_remot",
+            PoliticaConflicte::MantenirAmbdues => "mantenir_ambdues",
+            PoliticaConflicte::Manual => "manual",
+        }
+    }
+    pub fn descripcio(&self) -> &'static str {
+        match self {
+            PoliticaConflicte::MesRecentAmbCopia => "La més recent, amb còpia de l'altra",
+            PoliticaConflicte::PreferirLocal => "Sempre la local",
+            PoliticaConflicte::PreferirRemot => "Sempre la del servidor",
+            PoliticaConflicte::MantenirAmbdues => "Totes dues (la local amb el nom del dispositiu)",
+            PoliticaConflicte::Manual => "Deixar-lo pendent i resoldre'l a mà",
+        }
+    }
+}
+impl fmt::Display for PoliticaConflicte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+impl FromStr for PoliticaConflicte {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PoliticaConflicte::TOTES.into_iter().find(|p| p.id() == s).ok_or_else(|| {
+            let valides: Vec<&str> = PoliticaConflicte::TOTES.iter().map(|p| p.id()).collect();
+            format!("\"{s}\" no es una politica de conflictes valida ({})", valides.join(", "))
+        })
+    }
+}
+
+// Com es resol un conflicte concret
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolucio {
+    // Es queda la local i la remota es guarda com a copia "bck_*" (a local i al servidor)
+    LocalAmbCopia,
+    // Es queda la remota i la local es guarda com a copia "bck_*" (a local i al servidor)
+    RemotAmbCopia,
+    Local,
+    Remot,
+    // La remota es queda amb el nom original i la local passa a "{nom}_{dispositiu}"
+    Ambdues,
+}
+impl FromStr for Resolucio {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Les opcions per resoldre un conflicte a ma
+        match s {
+            "local" => Ok(Resolucio::Local),
+            "remot" => Ok(Resolucio::Remot),
+            "ambdues" => Ok(Resolucio::Ambdues),
+            _ => Err(format!("\"{s}\" no es una resolucio valida (local, remot, ambdues)")),
+        }
+    }
+}
+
+pub fn nom_dispositiu() -> String {
+    // El nom de la maquina: a linux /etc/hostname, a windows COMPUTERNAME
+    let nom = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .unwrap_or_default();
+    netejar_nom_dispositiu(&nom)
+}
+
+pub fn netejar_nom_dispositiu(nom: &str) -> String {
+    // Nomes caracters que es puguin posar a un nom de fitxer a qualsevol sistema (i a una capcalera HTTP)
+    let nom: String = nom.trim().chars().take(64).map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect();
+    if nom.is_empty() { "dispositiu".to_string() } else { nom }
+}
+
+pub fn nom_amb_dispositiu(nom_fitxer: &str, dispositiu: &str) -> String {
+    // El sufix va abans de l'extensio: "save1.sav" -> "save1_portatil.sav"
+    match nom_fitxer.rfind('.').filter(|i| *i > 0) {
+        Some(i) => format!("{}_{dispositiu}{}", &nom_fitxer[..i], &nom_fitxer[i..]),
+        None => format!("{nom_fitxer}_{dispositiu}"),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_politica_conflicte() {
+        assert_eq!(PoliticaConflicte::default(), PoliticaConflicte::MesRecentAmbCopia);
+        for p in PoliticaConflicte::TOTES {
+            assert_eq!(p.to_string().parse::<PoliticaConflicte>(), Ok(p));
+        }
+        assert!("patata".parse::<PoliticaConflicte>().is_err());
+        assert_eq!("ambdues".parse::<Resolucio>(), Ok(Resolucio::Ambdues));
+        assert!("les dues".parse::<Resolucio>().is_err());
+        assert_eq!(nom_amb_dispositiu("save1.sav", "portatil"), "save1_portatil.sav");
+        assert_eq!(nom_amb_dispositiu("save", "portatil"), "save_portatil");
+        assert_eq!(nom_amb_dispositiu(".config", "portatil"), ".config_portatil");
+        assert_eq!(netejar_nom_dispositiu(" El meu PC/2\n"), "El-meu-PC-2");
+        assert_eq!(netejar_nom_dispositiu(""), "dispositiu");
+        assert_eq!(netejar_nom_dispositiu("Portàtil"), "Port-til");
+    }
+}
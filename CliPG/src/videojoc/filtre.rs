@@ -0,0 +1,89 @@
+use glob::{MatchOptions, Pattern};
+
+// Quins fitxers de la carpeta d'un joc son partides. S'aplica igual a les partides locals i a les del servidor:
+// el que no passa el filtre no es puja, ni es descarrega, ni s'elimina.
+// Un patro sense "/" es compara amb el nom del fitxer, a qualsevol subdirectori (p.ex. "*.log"). Un patro amb "/"
+// es compara amb la ruta relativa a la carpeta del joc (p.ex. "shadercache/**"). Es sincronitza el que coincideix
+// amb algun `include` (tot, si no n'hi ha cap) i amb cap `exclude`.
+
+pub const INCLUDE_PER_DEFECTE: [&str; 1] = ["*"];
+// Fitxers dels sistemes operatius, registres i les copies "bck_*" que deixen els conflictes
+pub const EXCLUDE_PER_DEFECTE: [&str; 5] = ["bck_*", "desktop.ini", "Thumbs.db", ".DS_Store", "*.log"];
+
+pub fn include_per_defecte() -> Vec<String> {
+    INCLUDE_PER_DEFECTE.iter().map(|p| p.to_string()).collect()
+}
+pub fn exclude_per_defecte() -> Vec<String> {
+    EXCLUDE_PER_DEFECTE.iter().map(|p| p.to_string()).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Filtre {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+impl Default for Filtre {
+    fn default() -> Self {
+        Filtre::new(&include_per_defecte(), &exclude_per_defecte()).unwrap()
+    }
+}
+impl Filtre {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        let compilar = |patrons: &[String]| -> Result<Vec<Pattern>, String> {
+            patrons
+                .iter()
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| Pattern::new(p.trim()).map_err(|e| format!("\"{}\" no es un patro valid: {}", p, e)))
+                .collect()
+        };
+        Ok(Filtre {
+            include: compilar(include)?,
+            exclude: compilar(exclude)?,
+        })
+    }
+    pub fn sincronitza(&self, nom: &str) -> bool {
+        // `nom` es el nom relatiu de la partida, separat per "/" (p.ex. "perfil 1/save1.sav")
+        (self.include.is_empty() || self.include.iter().any(|p| Self::coincideix(p, nom))) && !self.exclude.iter().any(|p| Self::coincideix(p, nom))
+    }
+    fn coincideix(patro: &Pattern, nom: &str) -> bool {
+        // "*" no passa d'un subdirectori a l'altre; "**" si
+        let opcions = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        if patro.as_str().contains('/') {
+            patro.matches_with(nom, opcions)
+        } else {
+            patro.matches_with(nom.rsplit('/').next().unwrap_or(nom), opcions)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_filtre() {
+        let filtre = Filtre::default();
+        assert!(filtre.sincronitza("save1.sav"));
+        assert!(filtre.sincronitza("perfil 1/slot 2/save2.sav"));
+        assert!(!filtre.sincronitza("bck_20240329182000000000000_save1.sav"));
+        assert!(!filtre.sincronitza("perfil 1/desktop.ini"));
+        assert!(!filtre.sincronitza("logs/joc.log"));
+        let patrons = |p: &[&str]| p.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+        let filtre = Filtre::new(&patrons(&["*.sav", "perfil/**"]), &patrons(&["shadercache/**", "perfil/cache/*"])).unwrap();
+        assert!(filtre.sincronitza("save1.sav"));
+        assert!(filtre.sincronitza("slot 2/save2.sav"));
+        assert!(filtre.sincronitza("perfil/opcions.ini"));
+        assert!(!filtre.sincronitza("opcions.ini"));
+        assert!(!filtre.sincronitza("shadercache/a/b.sav"));
+        assert!(!filtre.sincronitza("perfil/cache/c.bin"));
+        // Amb un sol "*" no es baixa de subdirectori
+        assert!(filtre.sincronitza("perfil/cache/mes/c.bin"));
+        // Sense include es sincronitza tot el que no s'exclou
+        let filtre = Filtre::new(&[], &patrons(&["*.tmp", " "])).unwrap();
+        assert!(filtre.sincronitza("perfil/save.dat"));
+        assert!(!filtre.sincronitza("perfil/save.tmp"));
+        assert!(Filtre::new(&patrons(&["[save"]), &[]).unwrap_err().contains("[save"));
+    }
+}
@@ -1,19 +1,42 @@
+pub mod cache_hash;
+pub mod conflicte;
+pub mod filtre;
 pub mod partida_guardada;
 use crate::cli_pg::PartidaGuardadaConfig;
-use crate::pg_api::{PartidesGuardadesAPI, PgAPI};
+use crate::escriptura::PREFIX_TEMPORAL;
+use crate::informe::{SyncAction, SyncJoc, SyncPartida};
+use crate::pg_api::{PartidesGuardadesAPI, PgAPI, PgApiError};
 use chrono::Local;
+use conflicte::{PoliticaConflicte, Resolucio};
+use filtre::Filtre;
 use partida_guardada::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Partida que no es sincronitza: el nom del servidor no es una ruta valida dins la carpeta del joc
+// o la partida local no es pot llegir. No es toca a cap costat i surt com a error a l'informe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartidaNoValida {
+    pub nom: String,
+    pub error: String,
+    pub local: bool,
+}
 
 pub struct Videojoc {
     pub nom: OsString,
     pub local_folder: PathBuf,
     pub partides_locals: Vec<PartidaGuardada>,
     pub partides_remotes: Vec<PartidaGuardada>,
+    pub partides_no_valides: Vec<PartidaNoValida>,
     pub partides_guardades: HashMap<String, PartidaGuardadaConfig>,
+    pub politica_conflictes: PoliticaConflicte,
+    // Fitxers de la carpeta que son partides (veure filtre.rs)
+    pub filtre: Filtre,
+    // Nom d'aquest dispositiu, pels conflictes que es resolen mantenint totes dues partides
+    pub dispositiu: String,
 }
 impl Videojoc {
     pub fn new(path: String) -> Self {
@@ -24,13 +47,20 @@ impl Videojoc {
             local_folder,
             partides_locals: Vec::new(),
             partides_remotes: Vec::new(),
+            partides_no_valides: Vec::new(),
             partides_guardades: HashMap::new(),
+            politica_conflictes: PoliticaConflicte::default(),
+            filtre: Filtre::default(),
+            dispositiu: conflicte::nom_dispositiu(),
         }
     }
     pub fn from(videojoc: &Videojoc) -> Self {
         Videojoc::new(videojoc.local_folder.to_str().unwrap().to_string())
             .with_nom(videojoc.nom.clone().into_string().unwrap())
             .with_partides_guardades(videojoc.partides_guardades.clone())
+            .with_politica_conflictes(videojoc.politica_conflictes)
+            .with_filtre(videojoc.filtre.clone())
+            .with_dispositiu(videojoc.dispositiu.clone())
     }
     pub fn with_nom(mut self, nom: String) -> Self {
         self.nom = OsString::from(nom);
@@ -40,6 +70,18 @@ impl Videojoc {
         self.partides_guardades = partides_guardades;
         self
     }
+    pub fn with_politica_conflictes(mut self, politica_conflictes: PoliticaConflicte) -> Self {
+        self.politica_conflictes = politica_conflictes;
+        self
+    }
+    pub fn with_filtre(mut self, filtre: Filtre) -> Self {
+        self.filtre = filtre;
+        self
+    }
+    pub fn with_dispositiu(mut self, dispositiu: String) -> Self {
+        self.dispositiu = dispositiu;
+        self
+    }
     pub fn with_partides_guardades_list(mut self, partides_guardades: &Vec<PartidaGuardadaConfig>) -> Self {
         self.partides_guardades.clear();
         for p in partides_guardades.iter() {
@@ -49,99 +91,206 @@ impl Videojoc {
     }
     pub fn load_partides_locals(&mut self) {
         self.partides_locals.clear();
-
-        let Ok(entries) = self.local_folder.read_dir() else {
+        self.partides_no_valides.retain(|p| !p.local);
+        let local_folder = self.local_folder.clone();
+        self.load_partides_locals_dir(&local_folder);
+        cache_hash::guardar();
+    }
+    fn load_partides_locals_dir(&mut self, dir: &Path) {
+        let Ok(entries) = dir.read_dir() else {
             return;
         };
 
         for entry in entries.flatten() {
             let path = entry.path();
-            if path.is_file() {
-                self.partides_locals.push(PartidaGuardada::new(path.to_str().unwrap().to_string()).with_videojoc(self));
+            // No seguim enllaços simbolics a directoris per evitar recorreguts infinits
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                self.load_partides_locals_dir(&path);
+            } else if entry.file_name().to_string_lossy().starts_with(PREFIX_TEMPORAL) {
+                // Escriptura que no va acabar
+                continue;
+            } else if !self.nom_relatiu(&path).is_some_and(|nom| self.filtre.sincronitza(&nom.to_string_lossy())) {
+                // Fitxers que no son partides: ni se'n calcula el hash
+                continue;
+            } else if path.is_file() {
+                match PartidaGuardada::llegir(path.to_str().unwrap().to_string()) {
+                    Ok(partida) => self.partides_locals.push(partida.with_videojoc(self)),
+                    Err(e) => self.partides_no_valides.push(PartidaNoValida {
+                        nom: self.nom_relatiu(&path).unwrap().to_string_lossy().to_string(),
+                        error: format!("No s'ha pogut llegir la partida local: {e}"),
+                        local: true,
+                    }),
+                }
             }
         }
     }
-    pub fn fetch_partides_remotes(&mut self, api: &Box<dyn PartidesGuardadesAPI>) {
+    pub fn nom_relatiu(&self, path: &Path) -> Option<OsString> {
+        // Ruta relativa a la carpeta del videojoc, sempre separada per "/" (es el identificador de la partida)
+        let relatiu = path.strip_prefix(&self.local_folder).ok()?;
+        let parts: Vec<&str> = relatiu.iter().map(|p| p.to_str()).collect::<Option<_>>()?;
+        if parts.is_empty() {
+            return None;
+        }
+        Some(OsString::from(parts.join("/")))
+    }
+    fn nom_partida_guardada(&self, path: &str) -> Option<String> {
+        self.nom_relatiu(&PathBuf::from(path))?.into_string().ok()
+    }
+    pub fn fetch_partides_remotes(&mut self, api: &Box<dyn PartidesGuardadesAPI>) -> Result<(), PgApiError> {
         self.partides_remotes.clear();
-        for partida_remota in api.get_partides_guardades(&self) {
-            self.partides_remotes.push(partida_remota)
+        self.partides_no_valides.retain(|p| p.local);
+        for partida_remota in api.get_partides_guardades(&self)? {
+            let nom = partida_remota.nom.to_string_lossy().to_string();
+            if !partida_guardada::nom_valid(&nom) {
+                self.partides_no_valides.push(PartidaNoValida {
+                    nom,
+                    error: "El nom de la partida del servidor no es una ruta valida dins la carpeta del joc".to_string(),
+                    local: false,
+                });
+                continue;
+            }
+            // El mateix filtre que a local: el que no es veu a cap costat no es toca
+            if self.filtre.sincronitza(&partida_remota.nom.to_string_lossy()) {
+                self.partides_remotes.push(partida_remota)
+            }
         }
+        Ok(())
     }
-    pub fn sync(&mut self, api: &Box<dyn PartidesGuardadesAPI>, test_mode: bool) -> String {
+    pub fn sync(&mut self, api: &Box<dyn PartidesGuardadesAPI>, test_mode: bool) -> SyncJoc {
+        let inici = Instant::now();
+        let mut informe = SyncJoc::new(self.nom.to_str().unwrap());
         self.load_partides_locals();
-        self.fetch_partides_remotes(&api);
+        if let Err(e) = self.fetch_partides_remotes(&api) {
+            // Sense la llista del servidor no es toca res, ni tan sols les partides_guardades
+            informe.error = Some(e.to_string());
+            informe.durada = inici.elapsed();
+            return informe;
+        }
         let locals: HashMap<_, _> = self.partides_locals.iter().map(|p| (p.nom.to_str().unwrap().to_string(), p)).collect();
         let remotes: HashMap<_, _> = self.partides_remotes.iter().map(|p| (p.nom.to_str().unwrap().to_string(), p)).collect();
-        let guardades: HashMap<_, _> = self
-            .partides_guardades
-            .iter()
-            .map(|(k, v)| (PathBuf::from(k).file_name().unwrap().to_str().unwrap().to_string(), v))
-            .collect();
+        let guardades: HashMap<_, _> = self.partides_guardades.iter().filter_map(|(k, v)| Some((self.nom_partida_guardada(k)?, v))).collect();
         let mut noms: Vec<_> = locals.keys().chain(remotes.keys()).cloned().collect();
         noms.sort();
         noms.dedup();
-        let mut resultat = String::new();
+        let mut fallides = HashSet::new();
+        let mut pendents = HashSet::new();
+        // Una partida local que no s'ha pogut llegir tampoc es toca al servidor: sense ella semblaria eliminada a local
+        noms.retain(|nom| !self.partides_no_valides.iter().any(|p| p.nom == *nom));
+        for p in &self.partides_no_valides {
+            fallides.insert(p.nom.clone());
+            informe.partides.push(SyncPartida {
+                nom: p.nom.clone(),
+                accio: SyncAction::Error { error: p.error.clone() },
+                bytes: 0,
+                durada: Duration::ZERO,
+            });
+        }
         for nom in noms {
+            let inici_partida = Instant::now();
             let local = locals.get(&nom);
             let remote = remotes.get(&nom);
             let last_sync_hash = guardades.get(&nom).map(|p| p.hash.as_str()).unwrap_or("");
-            let msg = match (local, remote) {
+            let mut res = Ok(());
+            // Accio i bytes que es transfereixen
+            let (accio, bytes) = match (local, remote) {
                 // només local
                 (Some(local), None) => {
                     if last_sync_hash == local.hash {
                         if !test_mode {
-                            local.eliminar_partida_guardada();
+                            res = local.eliminar_partida_guardada().map_err(PgApiError::from);
                         }
-                        format!("    ❌ Eliminar local: {}\n", nom)
+                        (SyncAction::DeleteLocal, 0)
                     } else {
                         if !test_mode {
-                            local.pujar_partida_guardada(&api);
+                            res = local.pujar_partida_guardada(&api);
                         }
-                        format!("    ⬆ Pujar partida local: {}\n", nom)
+                        (SyncAction::Upload { nova: true }, local.mida.unwrap_or(0))
                     }
                 }
                 // només remot
                 (None, Some(remote)) => {
                     if last_sync_hash == remote.hash {
                         if !test_mode {
-                            api.delete_partida_guardada(&remote);
+                            res = api.delete_partida_guardada(&remote);
                         }
-                        format!("    ❌ Eliminar remot: {}\n", nom)
+                        (SyncAction::DeleteRemote, 0)
                     } else {
                         if !test_mode {
-                            remote.descarregar_partida_guardada(&api);
+                            res = remote.descarregar_partida_guardada(&api);
                         }
-                        format!("    ⬇ Descarregar partida remota: {}\n", nom)
+                        (SyncAction::Download { nova: true }, remote.mida.unwrap_or(0))
                     }
                 }
                 // existeixen tots dos
                 (Some(local), Some(remote)) => {
                     if local.hash == remote.hash {
-                        format!("    ✔ Partida OK: {}\n", nom)
+                        (SyncAction::Unchanged, 0)
                     } else if local.hash == last_sync_hash {
                         if !test_mode {
-                            remote.descarregar_partida_guardada(&api);
+                            res = remote.descarregar_partida_guardada(&api);
                         }
-                        format!("    ⬇ Descarregar (remot modificat): {}\n", nom)
+                        (SyncAction::Download { nova: false }, remote.mida.unwrap_or(0))
                     } else if remote.hash == last_sync_hash {
                         if !test_mode {
-                            local.pujar_partida_guardada(&api);
+                            res = local.pujar_partida_guardada(&api);
                         }
-                        format!("    ⬆ Pujar partida local (local modificat): {}\n", nom)
+                        (SyncAction::Upload { nova: false }, local.mida.unwrap_or(0))
                     } else {
-                        if !test_mode {
-                            self.resoldre_conflicte(local, remote, &api);
+                        let resolucio = self.resolucio(local, remote);
+                        match resolucio {
+                            Some(resolucio) => {
+                                if !test_mode {
+                                    res = self.resoldre_conflicte(local, remote, resolucio, &api);
+                                }
+                            }
+                            None => {
+                                pendents.insert(nom.clone());
+                            }
                         }
-                        format!("    ⚠ Conflicte: {}\n", nom)
+                        (SyncAction::Conflict { resolucio }, 0)
                     }
                 }
                 _ => continue,
             };
-            //print!("{}", msg);
-            resultat.push_str(&msg);
+            let (accio, bytes) = match res {
+                Ok(()) => (accio, bytes),
+                Err(e) => {
+                    fallides.insert(nom.clone());
+                    (SyncAction::Error { error: e.to_string() }, 0)
+                }
+            };
+            informe.partides.push(SyncPartida {
+                nom,
+                accio,
+                bytes,
+                durada: inici_partida.elapsed(),
+            });
         }
+        // De les partides que han fallat o tenen un conflicte pendent mantenim el que hi havia a
+        // partides_guardades: si guardessim el hash actual, el proxim sync podria interpretar que
+        // s'han eliminat o modificat nomes a l'altre costat.
+        fallides.extend(pendents);
+        let anteriors: Vec<PartidaGuardadaConfig> = self
+            .partides_guardades
+            .values()
+            .filter(|p| self.nom_partida_guardada(&p.path).is_some_and(|n| fallides.contains(&n)))
+            .cloned()
+            .collect();
         self.actualitzar_partides_guardades();
-        resultat
+        let noves: Vec<String> = self
+            .partides_guardades
+            .keys()
+            .filter(|k| self.nom_partida_guardada(k).is_some_and(|n| fallides.contains(&n)))
+            .cloned()
+            .collect();
+        for k in noves {
+            self.partides_guardades.remove(&k);
+        }
+        for p in anteriors {
+            self.partides_guardades.insert(p.path.clone(), p);
+        }
+        informe.durada = inici.elapsed();
+        informe
     }
     pub fn actualitzar_partides_guardades(&mut self) {
         // Actualitzem les partides que han quedat al local per obtenir els seus hash i actualitzar el partides_guardades
@@ -158,31 +307,96 @@ impl Videojoc {
             );
         }
     }
-    pub fn resoldre_conflicte(&self, local: &PartidaGuardada, remot: &PartidaGuardada, api: &Box<dyn PartidesGuardadesAPI>) {
-        // Donarem prioritat al que tingui el timestamp mes recent. El que tingui el timestamp
-        // mes antic es renombara posant a davant del nom "bck_yyyymmddhhss_"
-        let nou_nom = format!(
+    fn resolucio(&self, local: &PartidaGuardada, remot: &PartidaGuardada) -> Option<Resolucio> {
+        // None si el conflicte s'ha de resoldre a ma
+        match self.politica_conflictes {
+            PoliticaConflicte::MesRecentAmbCopia if remot.es_mes_recent(local) => Some(Resolucio::RemotAmbCopia),
+            PoliticaConflicte::MesRecentAmbCopia => Some(Resolucio::LocalAmbCopia),
+            PoliticaConflicte::PreferirLocal => Some(Resolucio::Local),
+            PoliticaConflicte::PreferirRemot => Some(Resolucio::Remot),
+            PoliticaConflicte::MantenirAmbdues => Some(Resolucio::Ambdues),
+            PoliticaConflicte::Manual => None,
+        }
+    }
+    pub fn conflictes_pendents(&mut self, api: &Box<dyn PartidesGuardadesAPI>) -> Result<Vec<(PartidaGuardada, PartidaGuardada)>, PgApiError> {
+        // Parelles (local, remota) modificades totes dues des de l'ultima sincronitzacio
+        self.load_partides_locals();
+        self.fetch_partides_remotes(api)?;
+        let mut conflictes = Vec::new();
+        for local in self.partides_locals.iter() {
+            let Some(remot) = self.partides_remotes.iter().find(|r| r.nom == local.nom) else {
+                continue;
+            };
+            let last_sync_hash = self.partides_guardades.get(local.path.to_str().unwrap()).map(|p| p.hash.as_str()).unwrap_or("");
+            if local.hash != remot.hash && local.hash != last_sync_hash && remot.hash != last_sync_hash {
+                // La local es d'aquest dispositiu; la remota porta el que l'ha pujada
+                let local = PartidaGuardada::from_partida_guardada(local).with_dispositiu(Some(self.dispositiu.clone()));
+                conflictes.push((local, PartidaGuardada::from_partida_guardada(remot)));
+            }
+        }
+        Ok(conflictes)
+    }
+    fn nom_relatiu_germana(nom: &OsString, nom_fitxer: &str) -> OsString {
+        // Partida amb un altre nom de fitxer al mateix subdirectori que `nom`
+        match PathBuf::from(nom).parent().and_then(|p| p.to_str()).filter(|p| !p.is_empty()) {
+            Some(subdir) => OsString::from(format!("{subdir}/{nom_fitxer}")),
+            None => OsString::from(nom_fitxer),
+        }
+    }
+    fn guardar_copia(&self, partida: &PartidaGuardada, nom_fitxer: &str, api: &Box<dyn PartidesGuardadesAPI>, des_del_servidor: bool) -> Result<(), PgApiError> {
+        // Guarda una copia de `partida` amb el nom `nom_fitxer` a local i al servidor. La copia es
+        // puja de seguida: si nomes fos local, el proxim sync la veuria com eliminada al servidor.
+        let mut copia = PartidaGuardada::from_partida_guardada(partida);
+        copia.path.set_file_name(nom_fitxer);
+        if des_del_servidor {
+            // El nom encara es el de la partida remota: es descarrega el seu contingut a la copia
+            copia.descarregar_partida_guardada(api)?;
+        } else {
+            partida.duplicar_fitxer(nom_fitxer.to_string())?;
+        }
+        copia.nom = Self::nom_relatiu_germana(&partida.nom, nom_fitxer);
+        api.post_partida_guardada(&copia)
+    }
+    fn nom_copia_dispositiu(&self, local: &PartidaGuardada) -> String {
+        // "{nom}_{dispositiu}", amb un numero si ja hi ha una partida amb aquest nom
+        let nom_fitxer = local.path.file_name().unwrap().to_str().unwrap();
+        let mut n = 1;
+        loop {
+            let dispositiu = if n == 1 { self.dispositiu.clone() } else { format!("{}_{n}", self.dispositiu) };
+            let nou_nom = conflicte::nom_amb_dispositiu(nom_fitxer, &dispositiu);
+            let nom_relatiu = Self::nom_relatiu_germana(&local.nom, &nou_nom);
+            if !local.path.with_file_name(&nou_nom).exists() && !self.partides_remotes.iter().any(|r| r.nom == nom_relatiu) {
+                return nou_nom;
+            }
+            n += 1;
+        }
+    }
+    pub fn resoldre_conflicte(&self, local: &PartidaGuardada, remot: &PartidaGuardada, resolucio: Resolucio, api: &Box<dyn PartidesGuardadesAPI>) -> Result<(), PgApiError> {
+        // Les copies "bck_yyyymmddhhmmss_" nomes canvien el nom del fitxer: la partida es queda al mateix subdirectori
+        let nom_bck = format!(
             "bck_{0}_{1}",
             Local::now()
                 .format("%Y%m%d%H%M%S%9f") // nanosegons
                 .to_string(),
-            remot.nom.to_str().unwrap()
+            remot.path.file_name().unwrap().to_str().unwrap()
         );
-        if local.timestamp >= remot.timestamp {
-            // Pujem la partida remot pero renombrada al servidor;
-            let mut remot = PartidaGuardada::from_partida_guardada(remot);
-            remot.nom = OsString::from(nou_nom.clone());
-            api.post_partida_guardada(&remot);
-            remot.path.set_file_name(nou_nom);
-            // La guardem en local tambe
-            remot.descarregar_partida_guardada(api);
-            // Pujem la partida local al servidor (aixo sobreescriu la que hi havia)
-            api.post_partida_guardada(&local);
-        } else {
-            // Creem una nova partida local amb el nom nou
-            local.duplicar_fitxer(nou_nom);
-            // Descarreguem la remota per actualitzar la original
-            remot.descarregar_partida_guardada(api);
+        match resolucio {
+            Resolucio::LocalAmbCopia => {
+                self.guardar_copia(remot, &nom_bck, api, true)?;
+                // Pujem la partida local al servidor (aixo sobreescriu la que hi havia)
+                api.post_partida_guardada(local)
+            }
+            Resolucio::RemotAmbCopia => {
+                self.guardar_copia(local, &nom_bck, api, false)?;
+                // Descarreguem la remota per actualitzar la original
+                remot.descarregar_partida_guardada(api)
+            }
+            Resolucio::Local => api.post_partida_guardada(local),
+            Resolucio::Remot => remot.descarregar_partida_guardada(api),
+            Resolucio::Ambdues => {
+                self.guardar_copia(local, &self.nom_copia_dispositiu(local), api, false)?;
+                remot.descarregar_partida_guardada(api)
+            }
         }
     }
     pub fn get_partides_guardades_list(&self) -> Vec<PartidaGuardadaConfig> {
@@ -194,21 +408,28 @@ impl Videojoc {
 pub mod tests {
     use super::*;
     use crate::videojoc::partida_guardada::tests::get_partida_ntw_s1;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
     pub struct FakeAPI;
     impl PartidesGuardadesAPI for FakeAPI {
-        fn probar_connexio(&self) -> bool {
-            true
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
         }
-        fn get_videojocs(&self) -> Vec<String> {
-            Vec::new()
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
         }
-        fn get_partides_guardades(&self, _: &Videojoc) -> Vec<PartidaGuardada> {
+        fn get_partides_guardades(&self, _: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
             let mut v = Vec::new();
             let p1 = PartidaGuardada {
                 videojoc: "".to_string(),
                 nom: OsString::from("save1.txt"),
                 path: PathBuf::new(),
                 timestamp: 245528886,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "72489639c9286a5eb52d3cdd74fab92bce8467fe44d33e919dc2663c781e5536".to_string(),
             };
             let p2 = PartidaGuardada {
@@ -216,6 +437,10 @@ pub mod tests {
                 nom: OsString::from("save_test_2"),
                 path: PathBuf::new(),
                 timestamp: 0,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "1".to_string(),
             };
             let p3 = PartidaGuardada {
@@ -223,6 +448,10 @@ pub mod tests {
                 nom: OsString::from("save3.txt"),
                 path: PathBuf::new(),
                 timestamp: 0,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "2".to_string(),
             };
             let p4 = PartidaGuardada {
@@ -230,6 +459,10 @@ pub mod tests {
                 nom: OsString::from("save4.txt"),
                 path: PathBuf::new(),
                 timestamp: 0,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "patata".to_string(),
             };
             let p5 = PartidaGuardada {
@@ -237,6 +470,10 @@ pub mod tests {
                 nom: OsString::from("save_remote_modified.txt"),
                 path: PathBuf::new(),
                 timestamp: 0,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "nou_hash".to_string(),
             };
             let p6 = PartidaGuardada {
@@ -244,6 +481,10 @@ pub mod tests {
                 nom: OsString::from("save_deleted_local.txt"),
                 path: PathBuf::new(),
                 timestamp: 0,
+                timestamp_nanos: 0,
+                mode: None,
+                mida: None,
+                dispositiu: None,
                 hash: "xyz".to_string(),
             };
             v.push(p1);
@@ -252,16 +493,51 @@ pub mod tests {
             v.push(p4);
             v.push(p5);
             v.push(p6);
-            v
+            Ok(v)
         }
-        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) {}
-        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Vec<u8> {
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn delete_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
             if partida_guardada.nom == "save_remot.txt" {
-                "Pastanaga Bullida\nPartida remota\n@#áçñÑ%".as_bytes().to_vec()
+                desti.write_all("Pastanaga Bullida\nPartida remota\n@#áçñÑ%".as_bytes())?
             } else {
-                "Contingut @ctualitzat!".as_bytes().to_vec()
+                desti.write_all("Contingut @ctualitzat!".as_bytes())?
+            }
+            Ok(())
+        }
+    }
+    // Servidor que respon la llista de partides pero falla en pujar i descarregar "save_error.txt"
+    struct FakeAPIErrors;
+    impl PartidesGuardadesAPI for FakeAPIErrors {
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
+        }
+        fn get_partides_guardades(&self, videojoc: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
+            let path = videojoc.local_folder.join("save_remot.txt");
+            Ok(vec![PartidaGuardada::new(path.to_str().unwrap().to_string()).with_hash("remot".to_string()).with_videojoc(videojoc)])
+        }
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            if partida_guardada.nom == "save_error.txt" {
+                return Err(PgApiError::Xarxa("connexio tallada".to_string()));
             }
+            Ok(())
+        }
+        fn delete_partida_guardada(&self, _: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, partida_guardada: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("Contingut a mitges".as_bytes())?;
+            if partida_guardada.nom == "save_error.txt" {
+                return Err(PgApiError::Xarxa("connexio tallada".to_string()));
+            }
+            Ok(())
         }
     }
     fn get_videojoc_path_w40k() -> String {
@@ -310,10 +586,31 @@ pub mod tests {
         assert_eq!(v.partides_locals[0].nom, "save2.txt");
     }
     #[test]
+    fn test_load_partides_locals_subdirectoris() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_videojoc/path a videojocs/Stellaris");
+        let mut v = Videojoc::new(path.to_str().unwrap().to_string());
+        v.load_partides_locals();
+        let mut noms: Vec<_> = v.partides_locals.iter().map(|p| p.nom.to_str().unwrap().to_string()).collect();
+        noms.sort();
+        assert_eq!(noms, vec!["perfil 1/save1.sav", "perfil 1/slot 2/save2.sav", "save0.sav"]);
+        for p in v.partides_locals.iter() {
+            assert_eq!(p.videojoc, "Stellaris");
+            assert_eq!(p.path, path.join(&p.nom));
+        }
+    }
+    #[test]
+    fn test_nom_relatiu() {
+        let v = get_videojoc_w40k();
+        assert_eq!(v.nom_relatiu(&v.local_folder.join("save1.txt")).unwrap(), "save1.txt");
+        assert_eq!(v.nom_relatiu(&v.local_folder.join("perfil/slot 1/save1.txt")).unwrap(), "perfil/slot 1/save1.txt");
+        assert!(v.nom_relatiu(&v.local_folder).is_none());
+        assert!(v.nom_relatiu(&PathBuf::from("/una/altra/carpeta/save1.txt")).is_none());
+    }
+    #[test]
     fn test_fetch_partides_remotes() {
         let mut v = get_videojoc_w40k();
         let s = get_fake_api();
-        v.fetch_partides_remotes(&s);
+        v.fetch_partides_remotes(&s).unwrap();
         assert_eq!(v.partides_remotes.len(), 6);
         assert_eq!(v.partides_remotes[0].nom, "save1.txt");
         assert_eq!(v.partides_remotes[1].nom, "save_test_2");
@@ -321,6 +618,22 @@ pub mod tests {
         assert_eq!(v.partides_remotes[3].nom, "save4.txt");
     }
     #[test]
+    fn test_filtre_partides() {
+        // El filtre s'aplica igual a les partides locals i a les del servidor
+        let exclude = vec!["save_*".to_string(), "perfil 1/**".to_string()];
+        let mut v = get_videojoc_w40k().with_filtre(Filtre::new(&[], &exclude).unwrap());
+        v.fetch_partides_remotes(&get_fake_api()).unwrap();
+        let noms: Vec<_> = v.partides_remotes.iter().map(|p| p.nom.to_str().unwrap()).collect();
+        assert!(noms.iter().all(|n| !n.starts_with("save_")));
+        assert!(noms.contains(&"save1.txt"));
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures_videojoc/path a videojocs/Stellaris");
+        let mut v = Videojoc::new(path.to_str().unwrap().to_string()).with_filtre(Filtre::new(&[], &exclude).unwrap());
+        v.load_partides_locals();
+        let noms: Vec<_> = v.partides_locals.iter().map(|p| p.nom.to_str().unwrap()).collect();
+        assert_eq!(noms, vec!["save0.sav"]);
+        assert!(!Videojoc::from(&v).filtre.sincronitza("perfil 1/save1.sav"));
+    }
+    #[test]
     fn test_resoldre_conflicte() {
         let mut local = get_partida_ntw_s1();
         let mut remot = get_partida_ntw_s1();
@@ -332,7 +645,9 @@ pub mod tests {
         remot.timestamp = 0;
         let nfitxers_abans = fs::read_dir(local.path.parent().unwrap()).iter().count();
         assert_eq!(nfitxers_abans, 1);
-        videojoc.resoldre_conflicte(&local, &remot, &api);
+        let resolucio = videojoc.resolucio(&local, &remot).unwrap();
+        assert_eq!(resolucio, Resolucio::LocalAmbCopia);
+        videojoc.resoldre_conflicte(&local, &remot, resolucio, &api).unwrap();
         let dir = local.path.parent().unwrap();
         let dir_fd = fs::File::open(dir).unwrap();
         dir_fd.sync_all().unwrap();
@@ -345,12 +660,16 @@ pub mod tests {
                 fs::remove_file(path).unwrap();
             }
         }
-        // Cas en que el remot es mes recent. Es farà una copia
-        local.timestamp = 0;
+        // Cas en que el remot es mes recent (dins el mateix segon). Es farà una copia
+        local.timestamp = 1;
+        local.timestamp_nanos = 500;
         remot.timestamp = 1;
+        remot.timestamp_nanos = 501;
         let nfitxers_abans = fs::read_dir(local.path.parent().unwrap()).iter().count();
         assert_eq!(nfitxers_abans, 1);
-        videojoc.resoldre_conflicte(&local, &remot, &api);
+        let resolucio = videojoc.resolucio(&local, &remot).unwrap();
+        assert_eq!(resolucio, Resolucio::RemotAmbCopia);
+        videojoc.resoldre_conflicte(&local, &remot, resolucio, &api).unwrap();
         let mut nfitxers_despres = 0;
         for entry in fs::read_dir(local.path.parent().unwrap()).unwrap().flatten() {
             nfitxers_despres += 1;
@@ -402,6 +721,162 @@ pub mod tests {
     ⬇ Descarregar (remot modificat): save_remote_modified.txt
     ⬇ Descarregar partida remota: save_test_2
 ";
-        assert_eq!(resultat_esperat, resultat);
+        assert_eq!(resultat_esperat, resultat.resum_partides());
+    }
+    #[test]
+    fn test_sync_amb_errors() {
+        let dir = std::env::temp_dir().join("clipg_test_sync_errors/Joc");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path_error = dir.join("save_error.txt");
+        fs::write(&path_error, "Partida local").unwrap();
+        let mut v = Videojoc::new(dir.to_str().unwrap().to_string()).with_partides_guardades_list(&vec![PartidaGuardadaConfig {
+            path: path_error.to_str().unwrap().to_string(),
+            hash: "hash anterior".to_string(),
+        }]);
+        let api: Box<dyn PartidesGuardadesAPI> = Box::new(FakeAPIErrors);
+        // La pujada de "save_error.txt" falla pero es continua amb la descarrega de "save_remot.txt"
+        let resultat = v.sync(&api, false);
+        assert_eq!(
+            resultat.resum_partides(),
+            "    ⛔ Error sincronitzant save_error.txt: No s'ha pogut connectar amb el servidor: connexio tallada\n    ⬇ Descarregar partida remota: save_remot.txt\n"
+        );
+        assert_eq!(fs::read_to_string(dir.join("save_remot.txt")).unwrap(), "Contingut a mitges");
+        // La partida que ha fallat conserva el hash de l'ultim sync correcte
+        let guardada = v.partides_guardades.get(path_error.to_str().unwrap()).unwrap();
+        assert_eq!(guardada.hash, "hash anterior");
+        // Una descarrega que falla no toca la partida local ni deixa el fitxer temporal
+        let local = PartidaGuardada::new(path_error.to_str().unwrap().to_string()).with_videojoc(&v);
+        assert!(local.descarregar_partida_guardada(&api).is_err());
+        assert_eq!(fs::read_to_string(&path_error).unwrap(), "Partida local");
+        assert!(fs::read_dir(&dir).unwrap().flatten().all(|e| !e.file_name().to_string_lossy().starts_with(PREFIX_TEMPORAL)));
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sync_partida_local_illegible() {
+        // /proc/self/mem es un fitxer normal que dona error en llegir-lo des del principi (tambe com a root)
+        let dir = std::env::temp_dir().join("clipg_test_sync_illegible/Joc");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("perfil")).unwrap();
+        std::os::unix::fs::symlink("/proc/self/mem", dir.join("perfil/save.txt")).unwrap();
+        let path = dir.join("perfil/save.txt").to_str().unwrap().to_string();
+        let mut v = Videojoc::new(dir.to_str().unwrap().to_string()).with_partides_guardades_list(&vec![PartidaGuardadaConfig {
+            path: path.clone(),
+            hash: "hash anterior".to_string(),
+        }]);
+        let pujades = Rc::new(RefCell::new(Vec::new()));
+        let api: Box<dyn PartidesGuardadesAPI> = Box::new(FakeAPIConflicte { pujades: pujades.clone() });
+        // No es descarrega la remota per sobre ni es tracta com a eliminada a local
+        let resultat = v.sync(&api, false);
+        assert_eq!(resultat.partides.len(), 1);
+        assert!(resultat.partides[0].accio.es_error());
+        assert_eq!(v.partides_no_valides[0].nom, "perfil/save.txt");
+        assert!(v.partides_no_valides[0].local);
+        assert!(fs::symlink_metadata(&path).unwrap().file_type().is_symlink());
+        assert!(pujades.borrow().is_empty());
+        assert_eq!(v.partides_guardades.get(&path).unwrap().hash, "hash anterior");
+        let _ = fs::remove_dir_all(&dir);
+    }
+    // Servidor amb una sola partida "perfil/save.txt" que apunta el que se li puja
+    struct FakeAPIConflicte {
+        pujades: Rc<RefCell<Vec<(String, String)>>>,
+    }
+    impl PartidesGuardadesAPI for FakeAPIConflicte {
+        fn probar_connexio(&self) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_videojocs(&self) -> Result<Vec<String>, PgApiError> {
+            Ok(Vec::new())
+        }
+        fn get_partides_guardades(&self, videojoc: &Videojoc) -> Result<Vec<PartidaGuardada>, PgApiError> {
+            let path = videojoc.local_folder.join("perfil/save.txt");
+            Ok(vec![
+                PartidaGuardada::new(path.to_str().unwrap().to_string())
+                    .with_hash("remot".to_string())
+                    .with_timestamp(1, 0)
+                    .with_mida(Some(14))
+                    .with_dispositiu(Some("sobretaula".to_string()))
+                    .with_videojoc(videojoc),
+            ])
+        }
+        fn post_partida_guardada(&self, partida_guardada: &PartidaGuardada) -> Result<(), PgApiError> {
+            let contingut = fs::read_to_string(&partida_guardada.path)?;
+            self.pujades.borrow_mut().push((partida_guardada.nom.to_str().unwrap().to_string(), contingut));
+            Ok(())
+        }
+        fn delete_partida_guardada(&self, _: &PartidaGuardada) -> Result<(), PgApiError> {
+            Ok(())
+        }
+        fn get_partida_guardada(&self, _: &PartidaGuardada, desti: &mut dyn Write) -> Result<(), PgApiError> {
+            desti.write_all("Partida remota".as_bytes())?;
+            Ok(())
+        }
+    }
+    #[test]
+    fn test_politiques_conflicte() {
+        let dir = std::env::temp_dir().join("clipg_test_politiques/Joc");
+        let save = dir.join("perfil/save.txt");
+        let pujades = Rc::new(RefCell::new(Vec::new()));
+        let api: Box<dyn PartidesGuardadesAPI> = Box::new(FakeAPIConflicte { pujades: pujades.clone() });
+        // Partida modificada a local (mes recent) i al servidor des de l'ultim sync
+        let preparar = |politica| {
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("perfil")).unwrap();
+            fs::write(&save, "Partida local").unwrap();
+            pujades.borrow_mut().clear();
+            Videojoc::new(dir.to_str().unwrap().to_string())
+                .with_politica_conflictes(politica)
+                .with_dispositiu("portatil".to_string())
+                .with_partides_guardades_list(&vec![PartidaGuardadaConfig {
+                    path: save.to_str().unwrap().to_string(),
+                    hash: "hash anterior".to_string(),
+                }])
+        };
+        let fitxers = || {
+            let mut noms: Vec<String> = fs::read_dir(dir.join("perfil")).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+            noms.sort();
+            noms
+        };
+        // Es queda la local i la remota passa a ser una copia bck_, a local i al servidor
+        let mut v = preparar(PoliticaConflicte::MesRecentAmbCopia);
+        assert_eq!(v.sync(&api, false).resum_partides(), "    ⚠ Conflicte: perfil/save.txt\n");
+        let noms = fitxers();
+        let bck = noms.iter().find(|n| n.starts_with("bck_")).unwrap();
+        assert_eq!(noms.len(), 2);
+        assert_eq!(fs::read_to_string(&save).unwrap(), "Partida local");
+        assert_eq!(
+            *pujades.borrow(),
+            vec![(format!("perfil/{bck}"), "Partida remota".to_string()), ("perfil/save.txt".to_string(), "Partida local".to_string())]
+        );
+        let mut v = preparar(PoliticaConflicte::PreferirLocal);
+        v.sync(&api, false);
+        assert_eq!(fitxers(), vec!["save.txt"]);
+        assert_eq!(*pujades.borrow(), vec![("perfil/save.txt".to_string(), "Partida local".to_string())]);
+        let mut v = preparar(PoliticaConflicte::PreferirRemot);
+        v.sync(&api, false);
+        assert_eq!(fitxers(), vec!["save.txt"]);
+        assert_eq!(fs::read_to_string(&save).unwrap(), "Partida remota");
+        assert!(pujades.borrow().is_empty());
+        // Totes dues: la local es queda amb el nom del dispositiu
+        let mut v = preparar(PoliticaConflicte::MantenirAmbdues);
+        v.sync(&api, false);
+        assert_eq!(fitxers(), vec!["save.txt", "save_portatil.txt"]);
+        assert_eq!(fs::read_to_string(&save).unwrap(), "Partida remota");
+        assert_eq!(*pujades.borrow(), vec![("perfil/save_portatil.txt".to_string(), "Partida local".to_string())]);
+        // Manual: no es toca res i es conserva el hash de l'ultim sync perque el conflicte continui pendent
+        let mut v = preparar(PoliticaConflicte::Manual);
+        assert_eq!(v.sync(&api, false).resum_partides(), "    ⚠ Conflicte pendent de resoldre: perfil/save.txt\n");
+        assert_eq!(fitxers(), vec!["save.txt"]);
+        assert!(pujades.borrow().is_empty());
+        assert_eq!(v.partides_guardades.get(save.to_str().unwrap()).unwrap().hash, "hash anterior");
+        let pendents = v.conflictes_pendents(&api).unwrap();
+        assert_eq!(pendents.len(), 1);
+        assert_eq!(pendents[0].1.hash, "remot");
+        assert_eq!((pendents[0].0.mida, pendents[0].0.dispositiu.as_deref()), (Some(13), Some("portatil")));
+        assert_eq!((pendents[0].1.mida, pendents[0].1.dispositiu.as_deref()), (Some(14), Some("sobretaula")));
+        v.resoldre_conflicte(&pendents[0].0, &pendents[0].1, Resolucio::Ambdues, &api).unwrap();
+        assert_eq!(fitxers(), vec!["save.txt", "save_portatil.txt"]);
+        let _ = fs::remove_dir_all(dir.parent().unwrap());
     }
 }
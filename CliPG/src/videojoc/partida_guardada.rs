@@ -1,40 +1,108 @@
-use crate::pg_api::PartidesGuardadesAPI;
+use crate::escriptura;
+use crate::pg_api::{PartidesGuardadesAPI, PgApiError};
 use crate::videojoc::Videojoc;
+use crate::videojoc::cache_hash;
 use filetime::FileTime;
-use sha2::{Digest, Sha256};
 use std::ffi::OsString;
 use std::fs;
 use std::fs::File;
-use std::io::{self, Read, Write};
+use std::io;
 use std::path::PathBuf;
 
 pub struct PartidaGuardada {
     pub videojoc: String,
     pub nom: OsString,
     pub path: PathBuf,
-    pub timestamp: u32,
+    // Data de modificacio: segons des de 1970 i nanosegons. Les remotes porten la del servidor.
+    pub timestamp: i64,
+    pub timestamp_nanos: u32,
+    // Permisos del fitxer (unix). None si no es coneixen.
+    pub mode: Option<u32>,
     pub hash: String,
+    // Mida en bytes. None si no es coneix (servidors antics).
+    pub mida: Option<u64>,
+    // Dispositiu que ha pujat la partida al servidor. Nomes a les remotes.
+    pub dispositiu: Option<String>,
+}
+
+pub fn mtime(metadata: &fs::Metadata) -> (i64, u32) {
+    let mtime = FileTime::from_last_modification_time(metadata);
+    (mtime.unix_seconds(), mtime.nanoseconds())
+}
+
+#[cfg(unix)]
+pub fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+#[cfg(not(unix))]
+pub fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+// Noms de dispositiu de Windows. Tambe amb els digits en superindex, que Windows tracta igual.
+const NOMS_RESERVATS_WINDOWS: [&str; 30] = [
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7",
+    "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+const CARACTERS_INVALIDS_WINDOWS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+pub fn nom_valid(nom: &str) -> bool {
+    // El nom d'una partida del servidor es una ruta relativa separada per "/" que s'afegeix a la carpeta del joc.
+    // Son les mateixes regles que al servidor (SerPG/src/rutes.rs): els tests de tots dos comproven la taula
+    // SerPG/tests/noms_partides.txt. Sense "..", rutes absolutes, trossos buits ni noms que Windows no accepta.
+    nom.split('/').all(|tros| {
+        if tros.is_empty() || tros == "." || tros == ".." || tros.len() > 255 {
+            return false;
+        }
+        if tros.chars().any(|c| c == '\\' || c.is_control() || CARACTERS_INVALIDS_WINDOWS.contains(&c)) || tros.starts_with(".serpg-") {
+            return false;
+        }
+        if tros.ends_with('.') || tros.ends_with(' ') {
+            return false;
+        }
+        let base = tros.split('.').next().unwrap_or_default().trim_end();
+        !NOMS_RESERVATS_WINDOWS.iter().any(|r| r.eq_ignore_ascii_case(base))
+    })
+}
+
+#[cfg(unix)]
+fn aplicar_mode(f: &File, mode: u32) -> io::Result<()> {
+    // Sense bits especials (setuid...) encara que els envii el servidor, i el joc ha de poder tornar a escriure la partida
+    use std::os::unix::fs::PermissionsExt;
+    f.set_permissions(fs::Permissions::from_mode((mode & 0o777) | 0o600))
+}
+#[cfg(not(unix))]
+fn aplicar_mode(_f: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
 }
 
 impl PartidaGuardada {
     pub fn new(path: String) -> Self {
-        let full_path = PathBuf::from(path.clone());
-        let hash: String;
-        let timestamp: u32;
-        if full_path.exists() {
-            hash = PartidaGuardada::hash_file(&full_path);
-            timestamp = FileTime::from_last_modification_time(&fs::metadata(path.clone()).unwrap()).nanoseconds();
-        } else {
-            hash = "".to_string();
-            timestamp = 0;
+        // Si el fitxer existeix pero no es pot llegir queda sense hash: per sincronitzar s'ha de fer servir `llegir`
+        Self::llegir(path.clone()).unwrap_or_else(|_| Self::sense_fitxer(path))
+    }
+    pub fn llegir(path: String) -> io::Result<Self> {
+        // Partida local amb el hash i les metadades del fitxer
+        let mut partida = Self::sense_fitxer(path);
+        if partida.path.exists() {
+            partida.update_metadata()?;
         }
+        Ok(partida)
+    }
+    fn sense_fitxer(path: String) -> Self {
+        let full_path = PathBuf::from(path.clone());
         let videojoc = full_path.parent().unwrap().file_name().unwrap().to_str().unwrap().to_string();
         PartidaGuardada {
             videojoc: videojoc,
             nom: full_path.file_name().unwrap_or_else(|| panic!("La ruta {path} no és correcte!")).to_os_string(),
-            hash: hash,
+            hash: "".to_string(),
             path: full_path,
-            timestamp: timestamp,
+            timestamp: 0,
+            timestamp_nanos: 0,
+            mode: None,
+            mida: None,
+            dispositiu: None,
         }
     }
     pub fn from_partida_guardada(partida_guardada: &PartidaGuardada) -> Self {
@@ -44,51 +112,86 @@ impl PartidaGuardada {
             hash: partida_guardada.hash.clone(),
             path: PathBuf::from(partida_guardada.path.to_str().unwrap()),
             timestamp: partida_guardada.timestamp,
+            timestamp_nanos: partida_guardada.timestamp_nanos,
+            mode: partida_guardada.mode,
+            mida: partida_guardada.mida,
+            dispositiu: partida_guardada.dispositiu.clone(),
         }
     }
     pub fn with_hash(mut self, hash: String) -> Self {
         self.hash = hash;
         self
     }
+    pub fn with_timestamp(mut self, timestamp: i64, timestamp_nanos: u32) -> Self {
+        self.timestamp = timestamp;
+        self.timestamp_nanos = timestamp_nanos;
+        self
+    }
+    pub fn with_mode(mut self, mode: Option<u32>) -> Self {
+        self.mode = mode;
+        self
+    }
+    pub fn with_mida(mut self, mida: Option<u64>) -> Self {
+        self.mida = mida;
+        self
+    }
+    pub fn with_dispositiu(mut self, dispositiu: Option<String>) -> Self {
+        self.dispositiu = dispositiu;
+        self
+    }
+    pub fn es_mes_recent(&self, altra: &PartidaGuardada) -> bool {
+        (self.timestamp, self.timestamp_nanos) > (altra.timestamp, altra.timestamp_nanos)
+    }
     pub fn with_videojoc(mut self, videojoc: &Videojoc) -> Self {
         self.videojoc = videojoc.nom.to_str().unwrap().to_string();
+        // El nom de la partida es la ruta relativa a la carpeta del videojoc (pot incloure subdirectoris)
+        if let Some(nom) = videojoc.nom_relatiu(&self.path) {
+            self.nom = nom;
+        }
         self
     }
-    pub fn update_metadata(&mut self) {
-        if self.path.exists() {
-            self.hash = PartidaGuardada::hash_file(&self.path);
-            self.timestamp = FileTime::from_last_modification_time(&fs::metadata(&self.path).unwrap()).nanoseconds();
-        }
+    pub fn update_metadata(&mut self) -> io::Result<()> {
+        // Si no es pot llegir (permisos, eliminada a mitges...) es retorna l'error: un hash buit es
+        // sincronitzaria com si la partida hagues canviat
+        let metadata = fs::metadata(&self.path)?;
+        self.hash = PartidaGuardada::hash_file(&self.path)?;
+        (self.timestamp, self.timestamp_nanos) = mtime(&metadata);
+        self.mode = mode(&metadata);
+        self.mida = Some(metadata.len());
+        Ok(())
     }
-    fn hash_file(path: &PathBuf) -> String {
-        let data = std::fs::read(path).unwrap_or_default();
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let hash_bytes = hasher.finalize();
-        hex::encode(hash_bytes)
+    fn hash_file(path: &PathBuf) -> io::Result<String> {
+        // Les partides que no han canviat des de l'ultim cop no es tornen a llegir
+        cache_hash::hash(path)
     }
-    pub fn pujar_partida_guardada(&self, api: &Box<dyn PartidesGuardadesAPI>) {
-        api.post_partida_guardada(&self);
+    pub fn pujar_partida_guardada(&self, api: &Box<dyn PartidesGuardadesAPI>) -> Result<(), PgApiError> {
+        api.post_partida_guardada(&self)
     }
-    pub fn descarregar_partida_guardada(&self, api: &Box<dyn PartidesGuardadesAPI>) {
-        let contingut = api.get_partida_guardada(&self);
-        self.write_file_sync(&contingut);
+    pub fn descarregar_partida_guardada(&self, api: &Box<dyn PartidesGuardadesAPI>) -> Result<(), PgApiError> {
+        // Es descarrega a un fitxer temporal al costat de la partida i nomes es substitueix
+        // quan la descarrega acaba be. Si falla, la partida local queda com estava.
+        escriptura::escriure_amb(&self.path, |f| {
+            api.get_partida_guardada(self, f)?;
+            // La partida descarregada conserva els permisos i la data de modificacio del servidor
+            // (els servidors antics no els envien). La data s'aplica al final: els permisos no la canvien.
+            if let Some(mode) = self.mode {
+                aplicar_mode(f, mode)?;
+            }
+            if self.timestamp != 0 || self.timestamp_nanos != 0 {
+                filetime::set_file_handle_times(f, None, Some(FileTime::from_unix_time(self.timestamp, self.timestamp_nanos)))?;
+            }
+            Ok(())
+        })
     }
-    pub fn duplicar_fitxer(&self, nou_nom: String) {
+    pub fn duplicar_fitxer(&self, nou_nom: String) -> io::Result<()> {
         let dir = self.path.parent().unwrap();
-        let nou_path = dir.join(nou_nom);
-        let mut origen = fs::File::open(&self.path).unwrap();
-        let mut desti = fs::File::create(&nou_path).unwrap();
-        io::copy(&mut origen, &mut desti).unwrap();
-        desti.sync_all().unwrap();
+        escriptura::copiar(&self.path, &dir.join(nou_nom))
     }
-    pub fn eliminar_partida_guardada(&self) {
-        fs::remove_file(&self.path).unwrap();
+    pub fn eliminar_partida_guardada(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)
     }
     pub fn write_file_sync(&self, content: &[u8]) {
-        let mut f = fs::File::create(&self.path).unwrap();
-        f.write_all(content).unwrap();
-        f.sync_all().unwrap();
+        escriptura::escriure(&self.path, content).unwrap();
     }
     pub fn read_file_sync(&self) -> Vec<u8> {
         fs::read(&self.path).unwrap()
@@ -144,12 +247,33 @@ pub mod tests {
         PartidaGuardada::new(get_partida_path_w40k_sremota())
     }
     #[test]
+    fn test_nom_valid() {
+        assert!(nom_valid("save1.sav"));
+        assert!(nom_valid("perfil 1/slot 2/save2.sav"));
+        assert!(nom_valid(".config/opcions.ini"));
+        assert!(!nom_valid(""));
+        assert!(!nom_valid("../../.bashrc"));
+        assert!(!nom_valid("perfil/.."));
+        assert!(!nom_valid("/etc/passwd"));
+        assert!(!nom_valid("perfil//save.sav"));
+        assert!(!nom_valid("perfil\\..\\save.sav"));
+        assert!(!nom_valid("nul.txt"));
+        assert!(!nom_valid("save "));
+        assert!(!nom_valid("CON .txt"));
+        // Els mateixos noms que comprova el servidor (SerPG/src/rutes.rs)
+        let taula = fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../SerPG/tests/noms_partides.txt")).unwrap();
+        for linia in taula.lines().filter(|l| !l.starts_with('#')) {
+            let (esperat, nom) = linia.split_once(' ').unwrap();
+            assert_eq!(nom_valid(nom), esperat == "ok", "{nom:?}");
+        }
+    }
+    #[test]
     fn test_new() {
         let test_file_path = get_partida_path_w40k_s1();
         let pg = get_partida_w40k_s1();
         assert_eq!(pg.videojoc, "Total War 40k");
         assert_eq!(pg.nom, "save1.txt");
-        assert_eq!(pg.timestamp, 288718000);
+        assert_eq!(pg.timestamp_nanos, 288718000);
         assert_eq!(pg.hash, "72489639c9286a5eb52d3cdd74fab92bce8467fe44d33e919dc2663c781e5536");
         assert_eq!(pg.path.to_str().unwrap(), test_file_path);
     }
@@ -158,18 +282,24 @@ pub mod tests {
         let pg = get_partida_w40k_s1();
         let copia = PartidaGuardada::from_partida_guardada(&pg);
         assert_eq!(copia.nom, "save1.txt");
-        assert_eq!(copia.timestamp, 288718000);
+        assert_eq!(copia.timestamp_nanos, 288718000);
         assert_eq!(copia.hash, "72489639c9286a5eb52d3cdd74fab92bce8467fe44d33e919dc2663c781e5536");
         assert_eq!(copia.path.to_str().unwrap(), pg.path.to_str().unwrap());
     }
     #[test]
     fn test_update_metadata() {
         let mut pg = get_partida_w40k_s1();
-        let orig_timestamp = pg.timestamp;
+        let orig_timestamp = (pg.timestamp, pg.timestamp_nanos);
         pg.timestamp = 0;
+        pg.timestamp_nanos = 0;
         assert_eq!(pg.timestamp, 0);
-        pg.update_metadata();
-        assert_eq!(pg.timestamp, orig_timestamp);
+        pg.update_metadata().unwrap();
+        assert_eq!((pg.timestamp, pg.timestamp_nanos), orig_timestamp);
+        // Una partida que ja no es pot llegir dona error i no canvia el hash
+        let mut eliminada = PartidaGuardada::new("/tmp/clipg_no_existeix/Joc/save1.sav".to_string()).with_hash("aa".to_string());
+        assert!(eliminada.update_metadata().is_err());
+        assert_eq!(eliminada.hash, "aa");
+        assert!(PartidaGuardada::llegir("/tmp/clipg_no_existeix/Joc/save1.sav".to_string()).is_ok());
     }
     #[test]
     fn test_pujar_partida_guardada() {
@@ -183,16 +313,22 @@ pub mod tests {
         if partida_remota.path.exists() {
             fs::remove_file(partida_remota.path).unwrap();
         }
-        let partida_remota = get_partida_w40k_servidor_sremota();
+        let partida_remota = get_partida_w40k_servidor_sremota().with_timestamp(1700000000, 5).with_mode(Some(0o4440));
         assert!(!partida_remota.path.exists());
         // Descarraguem la partida
         let api = get_fake_api();
-        partida_remota.descarregar_partida_guardada(&api);
+        partida_remota.descarregar_partida_guardada(&api).unwrap();
         // Ara ja hauria de existir
         assert!(partida_remota.path.exists());
         // Verifiquem el contingut
         let content = partida_remota.read_file_sync();
         assert_eq!(content, "Pastanaga Bullida\nPartida remota\n@#áçñÑ%".as_bytes());
+        // I te la data de modificacio i els permisos del servidor, sense setuid i amb escriptura pel propietari
+        let metadata = fs::metadata(&partida_remota.path).unwrap();
+        assert_eq!(mtime(&metadata), (1700000000, 5));
+        if cfg!(unix) {
+            assert_eq!(mode(&metadata), Some(0o640));
+        }
         // Tornem a eliminar per deixarho com abans
         fs::remove_file(partida_remota.path).unwrap();
     }
@@ -205,7 +341,7 @@ pub mod tests {
         assert_eq!(content, "Soc una partida guardada del Napoleon".as_bytes());
         // Descarreguem la nova verZio que hi ha al servidor
         let api = get_fake_api();
-        partida_ja_existent.descarregar_partida_guardada(&api);
+        partida_ja_existent.descarregar_partida_guardada(&api).unwrap();
         assert!(partida_ja_existent.path.exists());
         let content_nou = partida_ja_existent.read_file_sync();
         assert_eq!(content_nou, "Contingut @ctualitzat!".as_bytes());
@@ -221,7 +357,7 @@ pub mod tests {
         let nou_nom = "pastanaga";
         let nou_path = format!("{}/{}", partida_ja_existent.path.parent().unwrap().to_str().unwrap(), nou_nom);
         assert!(!PathBuf::from(&nou_path).exists());
-        partida_ja_existent.duplicar_fitxer(nou_nom.to_string());
+        partida_ja_existent.duplicar_fitxer(nou_nom.to_string()).unwrap();
         assert!(PathBuf::from(&nou_path).exists());
         fs::remove_file(nou_path).unwrap();
     }
@@ -0,0 +1,258 @@
+use crate::cli_pg::{CliPG, CliPgConfig};
+use crate::escriptura::PREFIX_TEMPORAL;
+use crate::informe::SyncReport;
+use crate::videojoc::Videojoc;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// Sincronitzacio quan canvia una partida: s'observen les carpetes dels jocs habilitats (inotify a linux)
+// i un joc es sincronitza quan fa `espera` que no s'hi escriu res. Els jocs escriuen les partides
+// en varies tandes: aixi no es puja una partida a mitges.
+
+pub const ESPERA_PER_DEFECTE: Duration = Duration::from_secs(5);
+// Quan fa que no arriba cap avis es considera que ja han arribat tots els d'una sincronitzacio
+const SILENCI_DESPRES_DE_SINCRONITZAR: Duration = Duration::from_millis(200);
+
+// Agrupa els canvis seguits d'un mateix joc
+pub struct Rebot {
+    espera: Duration,
+    pendents: HashMap<String, Instant>,
+}
+impl Rebot {
+    pub fn new(espera: Duration) -> Self {
+        Rebot { espera, pendents: HashMap::new() }
+    }
+    pub fn apuntar(&mut self, joc: &str, ara: Instant) {
+        // Cada canvi torna a comencar l'espera del joc
+        self.pendents.insert(joc.to_string(), ara);
+    }
+    pub fn llestos(&mut self, ara: Instant) -> Vec<String> {
+        // Jocs que fa `espera` que no canvien. Es treuen dels pendents.
+        let mut llestos: Vec<String> = self
+            .pendents
+            .iter()
+            .filter(|(_, ultim)| ara.duration_since(**ultim) >= self.espera)
+            .map(|(joc, _)| joc.clone())
+            .collect();
+        llestos.sort();
+        for joc in llestos.iter() {
+            self.pendents.remove(joc);
+        }
+        llestos
+    }
+    pub fn oblidar(&mut self, joc: &str) {
+        self.pendents.remove(joc);
+    }
+    pub fn temps_restant(&self, ara: Instant) -> Option<Duration> {
+        // Quan falta perque el primer joc pendent estigui llest
+        self.pendents.values().map(|ultim| self.espera.saturating_sub(ara.duration_since(*ultim))).min()
+    }
+}
+
+pub struct Vigilant {
+    // Si es deixa anar el watcher es deixa de vigilar
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+    // Nomes amb el nom, la carpeta i el filtre: els canvis que el filtre no sincronitza no es tenen en compte
+    jocs: Vec<Videojoc>,
+    // Jocs que no es poden vigilar i per que: la resta es continuen vigilant
+    no_vigilats: Vec<(String, String)>,
+    rebot: Rebot,
+}
+impl Vigilant {
+    pub fn new(config: &CliPgConfig, espera: Duration) -> Result<Self, String> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|e| format!("No s'ha pogut iniciar la vigilancia de carpetes: {e}"))?;
+        let mut jocs = Vec::new();
+        let mut no_vigilats = Vec::new();
+        for vc in config.videojocs_habilitats.list.iter() {
+            let path = PathBuf::from(&vc.path);
+            // Com a sync_all, els jocs amb la carpeta que no existeix no es tenen en compte
+            if !path.is_dir() {
+                no_vigilats.push((vc.nom.clone(), format!("la carpeta \"{}\" no existeix", vc.path)));
+                continue;
+            }
+            // Si no es pot vigilar una carpeta (permisos, limit de inotify...) es continua amb la resta
+            if let Err(e) = watcher.watch(&path, RecursiveMode::Recursive) {
+                eprintln!("No s'ha pogut vigilar \"{}\": {e}", vc.path);
+                no_vigilats.push((vc.nom.clone(), e.to_string()));
+                continue;
+            }
+            jocs.push(Videojoc::new(vc.path.clone()).with_nom(vc.nom.clone()).with_filtre(vc.filtre()));
+        }
+        Ok(Vigilant {
+            _watcher: watcher,
+            rx,
+            jocs,
+            no_vigilats,
+            rebot: Rebot::new(espera),
+        })
+    }
+    pub fn jocs(&self) -> Vec<&str> {
+        self.jocs.iter().map(|joc| joc.nom.to_str().unwrap()).collect()
+    }
+    pub fn no_vigilats(&self) -> &[(String, String)] {
+        &self.no_vigilats
+    }
+    pub fn joc_de_path(&self, path: &Path) -> Option<&str> {
+        // Els fitxers temporals de les escriptures atomiques no son partides
+        if path.file_name().is_some_and(|nom| nom.to_string_lossy().starts_with(PREFIX_TEMPORAL)) {
+            return None;
+        }
+        let joc = self.jocs.iter().find(|joc| path.starts_with(&joc.local_folder))?;
+        let nom = joc.nom_relatiu(path)?;
+        joc.filtre.sincronitza(&nom.to_string_lossy()).then(|| joc.nom.to_str().unwrap())
+    }
+    pub fn esperar(&mut self, maxim: Duration) -> Vec<String> {
+        // Espera canvis com a molt `maxim` i retorna els jocs que ja es poden sincronitzar (pot ser buit)
+        let limit = Instant::now() + maxim;
+        loop {
+            let ara = Instant::now();
+            let llestos = self.rebot.llestos(ara);
+            if !llestos.is_empty() || ara >= limit {
+                return llestos;
+            }
+            let timeout = self.rebot.temps_restant(ara).map_or(limit - ara, |restant| restant.min(limit - ara));
+            match self.rx.recv_timeout(timeout) {
+                Ok(Ok(event)) => self.apuntar(event),
+                Ok(Err(e)) => eprintln!("Error vigilant les carpetes: {e}"),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return llestos,
+            }
+        }
+    }
+    pub fn ignorar_canvis(&mut self, jocs: &[String]) {
+        // Despres de sincronitzar: els canvis d'aquests jocs son les escriptures de la propia sincronitzacio
+        // (descarregues, copies...) i no s'han de tornar a sincronitzar. Els dels altres jocs es mantenen.
+        let mut altres = Vec::new();
+        while let Ok(res) = self.rx.recv_timeout(SILENCI_DESPRES_DE_SINCRONITZAR) {
+            if let Ok(event) = res {
+                altres.push(event);
+            }
+        }
+        for joc in jocs.iter() {
+            self.rebot.oblidar(joc);
+        }
+        for mut event in altres {
+            event.paths.retain(|path| self.joc_de_path(path).is_none_or(|joc| !jocs.iter().any(|j| j == joc)));
+            self.apuntar(event);
+        }
+    }
+    fn apuntar(&mut self, event: Event) {
+        // Nomes interessen els canvis, no les lectures
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+            return;
+        }
+        let ara = Instant::now();
+        for path in event.paths.iter() {
+            if let Some(joc) = self.joc_de_path(path).map(str::to_string) {
+                self.rebot.apuntar(&joc, ara);
+            }
+        }
+    }
+}
+
+pub fn vigilar(config_path: Option<PathBuf>, espera: Duration, continuar: impl Fn() -> bool, mut en_sincronitzar: impl FnMut(SyncReport)) -> Result<(), String> {
+    // Bucle de vigilancia: s'atura quan `continuar` retorna false (es comprova cada segon)
+    let clipg = CliPG::default(config_path.clone());
+    let mut vigilant = Vigilant::new(&clipg.config, espera)?;
+    while continuar() {
+        let jocs = vigilant.esperar(Duration::from_secs(1));
+        if jocs.is_empty() {
+            continue;
+        }
+        // Es torna a llegir la configuracio: la poden haver canviat altres sincronitzacions
+        let mut clipg = CliPG::default(config_path.clone());
+        match clipg.sync_jocs(&jocs, false) {
+            Ok(res) => en_sincronitzar(res),
+            Err(err) => eprintln!("{err}"),
+        }
+        vigilant.ignorar_canvis(&jocs);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::cli_pg::{ServerConfig, VideojocConfig, VideojocConfigList};
+    use crate::videojoc::conflicte::PoliticaConflicte;
+    use crate::videojoc::filtre;
+    use std::fs;
+    #[test]
+    fn test_rebot() {
+        let inici = Instant::now();
+        let mut rebot = Rebot::new(Duration::from_secs(5));
+        assert_eq!(rebot.temps_restant(inici), None);
+        rebot.apuntar("Joc", inici);
+        rebot.apuntar("Altre", inici + Duration::from_secs(1));
+        // Un canvi nou torna a comencar l'espera
+        rebot.apuntar("Joc", inici + Duration::from_secs(3));
+        assert_eq!(rebot.temps_restant(inici + Duration::from_secs(4)), Some(Duration::from_secs(2)));
+        assert!(rebot.llestos(inici + Duration::from_secs(5)).is_empty());
+        assert_eq!(rebot.llestos(inici + Duration::from_secs(6)), vec!["Altre"]);
+        assert_eq!(rebot.llestos(inici + Duration::from_secs(8)), vec!["Joc"]);
+        rebot.apuntar("Joc", inici + Duration::from_secs(9));
+        rebot.oblidar("Joc");
+        assert!(rebot.llestos(inici + Duration::from_secs(20)).is_empty());
+    }
+    #[test]
+    fn test_vigilant() {
+        let test_path = std::env::temp_dir().join("clipg_test_vigilant");
+        fs::remove_dir_all(&test_path).ok();
+        fs::create_dir_all(test_path.join("Joc/perfil 1")).unwrap();
+        let videojoc = |nom: &str| VideojocConfig {
+            nom: nom.to_string(),
+            path: test_path.join(nom).to_str().unwrap().to_string(),
+            partides_guardades: Vec::new(),
+            conflict_policy: PoliticaConflicte::default(),
+            include: filtre::include_per_defecte(),
+            exclude: filtre::exclude_per_defecte(),
+        };
+        let config = CliPgConfig {
+            server: ServerConfig {
+                url: "http://localhost:8000".to_string(),
+                usuari: "admin".to_string(),
+                contrasenya: "admin".to_string(),
+                ca_bundle: None,
+                empremta: None,
+            },
+            dispositiu: None,
+            videojocs_habilitats: VideojocConfigList {
+                list: vec![videojoc("Joc"), videojoc("Sense carpeta")],
+            },
+        };
+        let mut vigilant = Vigilant::new(&config, Duration::from_millis(200)).unwrap();
+        assert_eq!(vigilant.jocs(), vec!["Joc"]);
+        assert_eq!(vigilant.no_vigilats().len(), 1);
+        assert_eq!(vigilant.no_vigilats()[0].0, "Sense carpeta");
+        assert_eq!(vigilant.joc_de_path(&test_path.join("Joc/perfil 1/save1.sav")), Some("Joc"));
+        assert_eq!(vigilant.joc_de_path(&test_path.join(format!("Joc/{PREFIX_TEMPORAL}1-save1.sav"))), None);
+        assert_eq!(vigilant.joc_de_path(&test_path.join("Altre/save1.sav")), None);
+        // Els fitxers que el filtre del joc no sincronitza no s'apunten
+        assert_eq!(vigilant.joc_de_path(&test_path.join("Joc/perfil 1/joc.log")), None);
+        assert_eq!(vigilant.joc_de_path(&test_path.join("Joc/bck_20240329182000000000000_save1.sav")), None);
+        // Sense canvis no hi ha res a sincronitzar
+        assert!(vigilant.esperar(Duration::from_millis(300)).is_empty());
+        // Una rafega d'escriptures es un sol avis, un cop s'acaba
+        for i in 0..3 {
+            fs::write(test_path.join("Joc/perfil 1/save1.sav"), format!("partida {i}")).unwrap();
+        }
+        assert_eq!(vigilant.esperar(Duration::from_secs(5)), vec!["Joc"]);
+        assert!(vigilant.esperar(Duration::from_millis(300)).is_empty());
+        fs::write(test_path.join("Joc/perfil 1/joc.log"), "log").unwrap();
+        assert!(vigilant.esperar(Duration::from_millis(500)).is_empty());
+        // Les escriptures de la sincronitzacio (una descarrega) no fan tornar a sincronitzar el joc
+        fs::write(test_path.join("Joc/perfil 1/save2.sav"), "descarregada").unwrap();
+        fs::rename(test_path.join("Joc/perfil 1/save2.sav"), test_path.join("Joc/perfil 1/save3.sav")).unwrap();
+        vigilant.ignorar_canvis(&["Joc".to_string()]);
+        assert!(vigilant.esperar(Duration::from_millis(500)).is_empty());
+        // Pero els canvis que arriben despres si
+        fs::write(test_path.join("Joc/perfil 1/save1.sav"), "partida nova").unwrap();
+        assert_eq!(vigilant.esperar(Duration::from_secs(5)), vec!["Joc"]);
+        fs::remove_dir_all(&test_path).unwrap();
+    }
+}
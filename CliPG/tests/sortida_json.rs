@@ -0,0 +1,91 @@
+// Amb `--output json` totes les comandes escriuen un sol document JSON a stdout
+use mockito::Server;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const VERSIO: &str = "1700000000_000000000_aa";
+
+fn clipg(conf_path: &Path, args: &[&str]) -> Value {
+    let sortida = Command::new(env!("CARGO_BIN_EXE_CliPG"))
+        .arg("--config")
+        .arg(conf_path)
+        .args(["--output", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(sortida.stdout).unwrap();
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{args:?} no ha escrit JSON ({e}): {stdout}"))
+}
+
+#[test]
+fn test_sortida_json() {
+    let test_path = std::env::temp_dir().join("clipg_test_sortida_json");
+    fs::remove_dir_all(&test_path).ok();
+    let joc_path = test_path.join("Joc");
+    fs::create_dir_all(&joc_path).unwrap();
+    fs::write(joc_path.join("save1.sav"), "partida local").unwrap();
+    // La partida ha canviat a local i al servidor des de l'ultima sincronitzacio: es un conflicte pendent
+    let mut server = Server::new();
+    let conf_path = test_path.join("conf.toml");
+    fs::write(
+        &conf_path,
+        format!(
+            r#"[server]
+url = "{url}"
+usuari = "admin"
+contrasenya = "admin"
+
+[[videojocs_habilitats.list]]
+nom = "Joc"
+path = "{joc}"
+conflict_policy = "manual"
+
+[[videojocs_habilitats.list.partides_guardades]]
+path = "{joc}/save1.sav"
+hash = "00"
+"#,
+            url = server.url(),
+            joc = joc_path.display()
+        ),
+    )
+    .unwrap();
+    server
+        .mock("GET", "/api/v1/videojocs/Joc/partides")
+        .with_header("content-type", "application/json")
+        .with_body(r#"[{"nom": "save1.sav", "hash": "bb", "mtime": 1700000000}]"#)
+        .create();
+    server
+        .mock("GET", "/api/v1/videojocs/Joc/partides/save1.sav/versions")
+        .with_header("content-type", "application/json")
+        .with_body(format!(r#"[{{"id": "{VERSIO}", "timestamp": 1700000000, "hash": "aa", "mida": 7}}]"#))
+        .create();
+    server.mock("POST", format!("/api/v1/videojocs/Joc/partides/save1.sav/versions/{VERSIO}/restaurar").as_str()).create();
+    server.mock("PUT", "/api/v1/videojocs/Joc/partides/save1.sav/binari").create();
+
+    let res = clipg(&conf_path, &["games", "policy", "Joc", "preferir_local"]);
+    assert_eq!(res["ok"], true);
+    assert!(res["missatge"].as_str().unwrap().contains("preferir_local"));
+    assert_eq!(clipg(&conf_path, &["games", "policy", "Joc", "patata"])["ok"], false);
+    clipg(&conf_path, &["games", "policy", "Joc", "manual"]);
+
+    let res = clipg(&conf_path, &["server", "set", "--user", "admin"]);
+    assert_eq!(res["ok"], true);
+    assert!(res["missatge"].as_str().unwrap().contains(&server.url()));
+
+    let res = clipg(&conf_path, &["saves", "history", "Joc", "save1.sav"]);
+    assert_eq!(res["versions"][0]["id"], VERSIO);
+    assert_eq!(res["versions"][0]["mida"], 7);
+
+    assert_eq!(clipg(&conf_path, &["saves", "restore", "Joc", "save1.sav", VERSIO])["ok"], true);
+    assert_eq!(clipg(&conf_path, &["saves", "restore", "Joc", "save1.sav", "patata"])["ok"], false);
+
+    let res = clipg(&conf_path, &["conflicts", "list"]);
+    assert_eq!(res["conflictes"][0]["joc"], "Joc");
+    assert_eq!(res["conflictes"][0]["partida"], "save1.sav");
+    assert_eq!(res["conflictes"][0]["remot"]["hash"], "bb");
+
+    assert_eq!(clipg(&conf_path, &["conflicts", "resolve", "Joc", "save1.sav", "local"])["ok"], true);
+    fs::remove_dir_all(&test_path).unwrap();
+}
@@ -0,0 +1,219 @@
+use crate::error::ErrorSerPG;
+use axum::http::HeaderMap;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Metadades de les partides que viatgen com a capcaleres HTTP amb el contingut binari.
+
+// Data de modificacio de la partida: "{segons}.{nanosegons}" des de 1970 (p.ex. "1700000000.123456789")
+pub const MTIME: &str = "x-pg-mtime";
+
+// Permisos del fitxer en octal (p.ex. "644"). Nomes en sistemes unix.
+pub const MODE: &str = "x-pg-mode";
+
+// SHA-256 del contingut en hexadecimal. Qui rep la partida el comprova abans de guardar-la.
+pub const HASH: &str = "x-pg-hash";
+
+// Nom del dispositiu que puja la partida (p.ex. "portatil"). Es mostra als conflictes.
+pub const DISPOSITIU: &str = "x-pg-dispositiu";
+
+pub fn format_mtime(mtime: i64, mtime_nanos: u32) -> String {
+    format!("{mtime}.{mtime_nanos:09}")
+}
+
+pub fn parse_mtime(valor: &str) -> Option<(i64, u32)> {
+    // Els nanosegons sempre amb 9 xifres: "1.5" no vol dir mig segon
+    let (segons, nanos) = valor.split_once('.').unwrap_or((valor, "000000000"));
+    if nanos.len() != 9 || !nanos.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((segons.parse().ok()?, nanos.parse().ok()?))
+}
+
+pub fn mtime_de_peticio(headers: &HeaderMap) -> Result<Option<SystemTime>, ErrorSerPG> {
+    // Els clients antics no l'envien: el fitxer es queda amb la data en que s'ha escrit
+    let Some(valor) = headers.get(MTIME) else {
+        return Ok(None);
+    };
+    let (segons, nanos) = valor.to_str().ok().and_then(parse_mtime).ok_or_else(|| {
+        ErrorSerPG::PeticioIncorrecta(format!("Capcalera {MTIME} no valida: {valor:?}"))
+    })?;
+    let temps = if segons >= 0 {
+        UNIX_EPOCH + Duration::new(segons as u64, nanos)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(segons.unsigned_abs()) + Duration::from_nanos(nanos as u64)
+    };
+    Ok(Some(temps))
+}
+
+pub fn parse_mode(valor: &str) -> Option<u32> {
+    u32::from_str_radix(valor, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+}
+
+pub fn mode_de_peticio(headers: &HeaderMap) -> Result<Option<u32>, ErrorSerPG> {
+    let Some(valor) = headers.get(MODE) else {
+        return Ok(None);
+    };
+    valor
+        .to_str()
+        .ok()
+        .and_then(parse_mode)
+        .map(Some)
+        .ok_or_else(|| {
+            ErrorSerPG::PeticioIncorrecta(format!("Capcalera {MODE} no valida: {valor:?}"))
+        })
+}
+
+pub fn parse_hash(valor: &str) -> Option<String> {
+    (valor.len() == 64 && valor.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| valor.to_ascii_lowercase())
+}
+
+pub fn hash_de_peticio(headers: &HeaderMap) -> Result<Option<String>, ErrorSerPG> {
+    // Els clients antics no l'envien: la partida es guarda sense comprovar
+    let Some(valor) = headers.get(HASH) else {
+        return Ok(None);
+    };
+    valor
+        .to_str()
+        .ok()
+        .and_then(parse_hash)
+        .map(Some)
+        .ok_or_else(|| {
+            ErrorSerPG::PeticioIncorrecta(format!("Capcalera {HASH} no valida: {valor:?}"))
+        })
+}
+
+pub fn comprovar_hash(esperat: Option<&str>, rebut: &str) -> Result<(), ErrorSerPG> {
+    match esperat {
+        Some(esperat) if !esperat.eq_ignore_ascii_case(rebut) => Err(ErrorSerPG::Integritat(
+            format!("El contingut rebut te el hash {rebut} pero s'esperava {esperat}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn parse_dispositiu(valor: &str) -> Option<String> {
+    // Nomes caracters que es poden posar a un nom de fitxer: el client el fa servir de sufix
+    (!valor.is_empty()
+        && valor.len() <= 64
+        && valor
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'))
+    .then(|| valor.to_string())
+}
+
+pub fn dispositiu_de_peticio(headers: &HeaderMap) -> Result<Option<String>, ErrorSerPG> {
+    // Els clients antics no l'envien: la partida queda sense dispositiu
+    let Some(valor) = headers.get(DISPOSITIU) else {
+        return Ok(None);
+    };
+    valor
+        .to_str()
+        .ok()
+        .and_then(parse_dispositiu)
+        .map(Some)
+        .ok_or_else(|| {
+            ErrorSerPG::PeticioIncorrecta(format!("Capcalera {DISPOSITIU} no valida: {valor:?}"))
+        })
+}
+
+#[cfg(unix)]
+pub fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+#[cfg(not(unix))]
+pub fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+pub fn aplicar_mode(f: &fs::File, mode: u32) -> std::io::Result<()> {
+    // Sense bits especials (setuid...) i el servidor sempre ha de poder llegir la partida
+    use std::os::unix::fs::PermissionsExt;
+    f.set_permissions(fs::Permissions::from_mode((mode & 0o777) | 0o400))
+}
+#[cfg(not(unix))]
+pub fn aplicar_mode(_f: &fs::File, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_mtime() {
+        assert_eq!(format_mtime(1700000000, 5), "1700000000.000000005");
+        assert_eq!(parse_mtime("1700000000.000000005"), Some((1700000000, 5)));
+        assert_eq!(parse_mtime("1700000000"), Some((1700000000, 0)));
+        assert_eq!(parse_mtime("-10.000000005"), Some((-10, 5)));
+        assert_eq!(parse_mtime("1700000000.5"), None);
+        assert_eq!(parse_mtime("1700000000.1000000000"), None);
+        assert_eq!(parse_mtime("ahir"), None);
+        let mut headers = HeaderMap::new();
+        assert_eq!(mtime_de_peticio(&headers), Ok(None));
+        headers.insert(MTIME, "1700000000.000000005".parse().unwrap());
+        assert_eq!(
+            mtime_de_peticio(&headers),
+            Ok(Some(UNIX_EPOCH + Duration::new(1700000000, 5)))
+        );
+        headers.insert(MTIME, "ahir".parse().unwrap());
+        assert!(mtime_de_peticio(&headers).is_err());
+    }
+    #[test]
+    fn test_mode() {
+        assert_eq!(parse_mode("644"), Some(0o644));
+        assert_eq!(parse_mode("4755"), Some(0o4755));
+        assert_eq!(parse_mode("888"), None);
+        assert_eq!(parse_mode("77777"), None);
+        let mut headers = HeaderMap::new();
+        assert_eq!(mode_de_peticio(&headers), Ok(None));
+        headers.insert(MODE, "600".parse().unwrap());
+        assert_eq!(mode_de_peticio(&headers), Ok(Some(0o600)));
+        headers.insert(MODE, "rw-r--r--".parse().unwrap());
+        assert!(mode_de_peticio(&headers).is_err());
+    }
+    #[test]
+    fn test_hash() {
+        let hash = "AB".repeat(32);
+        assert_eq!(parse_hash(&hash), Some("ab".repeat(32)));
+        assert_eq!(parse_hash("abab"), None);
+        assert_eq!(parse_hash(&"zz".repeat(32)), None);
+        let mut headers = HeaderMap::new();
+        assert_eq!(hash_de_peticio(&headers), Ok(None));
+        headers.insert(HASH, hash.parse().unwrap());
+        assert_eq!(hash_de_peticio(&headers), Ok(Some("ab".repeat(32))));
+        headers.insert(HASH, "patata".parse().unwrap());
+        assert!(hash_de_peticio(&headers).is_err());
+        assert_eq!(comprovar_hash(None, "cd"), Ok(()));
+        assert_eq!(comprovar_hash(Some(&hash), &"ab".repeat(32)), Ok(()));
+        assert_eq!(
+            comprovar_hash(Some(&hash), &"cd".repeat(32))
+                .unwrap_err()
+                .codi(),
+            "integritat"
+        );
+    }
+    #[test]
+    fn test_dispositiu() {
+        assert_eq!(
+            parse_dispositiu("portatil_2"),
+            Some("portatil_2".to_string())
+        );
+        assert_eq!(parse_dispositiu(""), None);
+        assert_eq!(parse_dispositiu("el meu pc"), None);
+        assert_eq!(parse_dispositiu(&"a".repeat(65)), None);
+        let mut headers = HeaderMap::new();
+        assert_eq!(dispositiu_de_peticio(&headers), Ok(None));
+        headers.insert(DISPOSITIU, "steam-deck".parse().unwrap());
+        assert_eq!(
+            dispositiu_de_peticio(&headers),
+            Ok(Some("steam-deck".to_string()))
+        );
+        headers.insert(DISPOSITIU, "../pc".parse().unwrap());
+        assert!(dispositiu_de_peticio(&headers).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use axum::{
+    extract::Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io};
+
+// Errors que poden retornar els handlers. Es responen amb l'estat HTTP corresponent i un cos JSON:
+// {"codi": "no_trobat", "missatge": "..."}
+#[derive(Debug, PartialEq)]
+pub enum ErrorSerPG {
+    PeticioIncorrecta(String),
+    NoAutoritzat,
+    Prohibit(String),
+    NoTrobat(String),
+    Conflicte(String),
+    SenseEspai(String),
+    // El contingut rebut no coincideix amb el hash que l'acompanya
+    Integritat(String),
+    Intern(String),
+}
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ErrorAPI {
+    pub codi: String,
+    pub missatge: String,
+}
+impl ErrorSerPG {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorSerPG::PeticioIncorrecta(_) => StatusCode::BAD_REQUEST,
+            ErrorSerPG::NoAutoritzat => StatusCode::UNAUTHORIZED,
+            ErrorSerPG::Prohibit(_) => StatusCode::FORBIDDEN,
+            ErrorSerPG::NoTrobat(_) => StatusCode::NOT_FOUND,
+            ErrorSerPG::Conflicte(_) => StatusCode::CONFLICT,
+            ErrorSerPG::SenseEspai(_) => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorSerPG::Integritat(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorSerPG::Intern(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    pub fn codi(&self) -> &'static str {
+        match self {
+            ErrorSerPG::PeticioIncorrecta(_) => "peticio_incorrecta",
+            ErrorSerPG::NoAutoritzat => "no_autoritzat",
+            ErrorSerPG::Prohibit(_) => "prohibit",
+            ErrorSerPG::NoTrobat(_) => "no_trobat",
+            ErrorSerPG::Conflicte(_) => "conflicte",
+            ErrorSerPG::SenseEspai(_) => "sense_espai",
+            ErrorSerPG::Integritat(_) => "integritat",
+            ErrorSerPG::Intern(_) => "intern",
+        }
+    }
+}
+impl fmt::Display for ErrorSerPG {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorSerPG::NoAutoritzat => write!(f, "Usuari o contrasenya incorrectes"),
+            ErrorSerPG::PeticioIncorrecta(m)
+            | ErrorSerPG::Prohibit(m)
+            | ErrorSerPG::NoTrobat(m)
+            | ErrorSerPG::Conflicte(m)
+            | ErrorSerPG::SenseEspai(m)
+            | ErrorSerPG::Integritat(m)
+            | ErrorSerPG::Intern(m) => write!(f, "{m}"),
+        }
+    }
+}
+impl From<io::Error> for ErrorSerPG {
+    fn from(error: io::Error) -> Self {
+        let missatge = error.to_string();
+        match error.kind() {
+            io::ErrorKind::NotFound => ErrorSerPG::NoTrobat(missatge),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                ErrorSerPG::Prohibit(missatge)
+            }
+            // Un fitxer on hi hauria d'anar un directori o al reves
+            io::ErrorKind::AlreadyExists
+            | io::ErrorKind::IsADirectory
+            | io::ErrorKind::NotADirectory
+            | io::ErrorKind::DirectoryNotEmpty => ErrorSerPG::Conflicte(missatge),
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => {
+                ErrorSerPG::SenseEspai(missatge)
+            }
+            io::ErrorKind::InvalidInput | io::ErrorKind::InvalidFilename => {
+                ErrorSerPG::PeticioIncorrecta(missatge)
+            }
+            _ => ErrorSerPG::Intern(missatge),
+        }
+    }
+}
+impl IntoResponse for ErrorSerPG {
+    fn into_response(self) -> Response {
+        println!("ERROR {} {}: {}", self.status().as_u16(), self.codi(), self);
+        let body = ErrorAPI {
+            codi: self.codi().to_string(),
+            missatge: self.to_string(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_from_io_error() {
+        let error = |kind| ErrorSerPG::from(io::Error::new(kind, "patata"));
+        assert_eq!(
+            error(io::ErrorKind::NotFound),
+            ErrorSerPG::NoTrobat("patata".to_string())
+        );
+        assert_eq!(
+            error(io::ErrorKind::StorageFull).status(),
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+        assert_eq!(
+            error(io::ErrorKind::IsADirectory).status(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            error(io::ErrorKind::PermissionDenied).status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            error(io::ErrorKind::Other).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
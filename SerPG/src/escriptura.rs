@@ -0,0 +1,105 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+// Escriptures atomiques: el contingut nou s'escriu a un fitxer temporal al mateix directori, es fa
+// fsync, es renombra sobre el desti i es fa fsync del directori. Si el proces s'atura a mig camí
+// el desti es o la versio anterior o la nova, mai una barreja.
+
+// Els temporals fan servir el prefix reservat (rutes::PREFIX_RESERVAT): cap id de partida valid hi pot coincidir
+pub const PREFIX_TEMPORAL: &str = ".serpg-tmp-";
+
+pub fn path_temporal(desti: &Path) -> PathBuf {
+    // Un nom diferent per cada escriptura: dues peticions a la mateixa partida no comparteixen temporal
+    static COMPTADOR: AtomicU64 = AtomicU64::new(0);
+    let n = COMPTADOR.fetch_add(1, Ordering::Relaxed);
+    let nom = desti.file_name().unwrap_or_default().to_string_lossy();
+    desti.with_file_name(format!("{PREFIX_TEMPORAL}{}-{n}-{nom}", std::process::id()))
+}
+
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Perque el rename sobrevisqui a una aturada cal persistir tambe l'entrada del directori
+    fs::File::open(dir)?.sync_all()
+}
+#[cfg(not(unix))]
+pub fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn finalitzar(temporal: &Path, desti: &Path) -> io::Result<()> {
+    // El temporal ja ha d'estar escrit i amb fsync fet
+    if let Err(e) = fs::rename(temporal, desti) {
+        let _ = fs::remove_file(temporal);
+        return Err(e);
+    }
+    sync_dir(desti.parent().unwrap_or(Path::new(".")))
+}
+
+pub fn escriure(desti: &Path, contingut: &[u8]) -> io::Result<()> {
+    let temporal = path_temporal(desti);
+    let resultat = (|| {
+        let mut f = fs::File::create(&temporal)?;
+        f.write_all(contingut)?;
+        f.sync_all()
+    })();
+    if let Err(e) = resultat {
+        let _ = fs::remove_file(&temporal);
+        return Err(e);
+    }
+    finalitzar(&temporal, desti)
+}
+
+pub fn copiar_a_temporal(origen: &Path, desti: &Path) -> io::Result<PathBuf> {
+    // Deixa una copia d'origen al costat de desti, a punt per finalitzar()
+    let temporal = path_temporal(desti);
+    let resultat = fs::copy(origen, &temporal).and_then(|_| fs::File::open(&temporal)?.sync_all());
+    if let Err(e) = resultat {
+        let _ = fs::remove_file(&temporal);
+        return Err(e);
+    }
+    Ok(temporal)
+}
+
+pub fn copiar(origen: &Path, desti: &Path) -> io::Result<()> {
+    let temporal = copiar_a_temporal(origen, desti)?;
+    finalitzar(&temporal, desti)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+    #[test]
+    fn test_escriure() {
+        let dir = std::env::temp_dir().join("serpg_test_escriptura");
+        let _ = remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let desti = dir.join("save.sav");
+        escriure(&desti, b"v1").unwrap();
+        escriure(&desti, b"v2").unwrap();
+        assert_eq!(fs::read(&desti).unwrap(), b"v2");
+        let copia = dir.join("copia.sav");
+        copiar(&desti, &copia).unwrap();
+        assert_eq!(fs::read(&copia).unwrap(), b"v2");
+        // No queda cap temporal
+        let noms: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(noms.len(), 2);
+        assert!(noms.iter().all(|n| !n.starts_with(PREFIX_TEMPORAL)));
+        // Si no es pot escriure el desti no canvia i no queda el temporal
+        assert!(escriure(&dir.join("no/existeix.sav"), b"v3").is_err());
+        assert!(path_temporal(&desti)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with(PREFIX_TEMPORAL));
+        assert_ne!(path_temporal(&desti), path_temporal(&desti));
+        let _ = remove_dir_all(&dir);
+    }
+}
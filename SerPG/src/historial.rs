@@ -0,0 +1,193 @@
+use crate::escriptura;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Directori (dins de videojocs_path) on es guarden les versions anteriors de les partides:
+// .historial/{videojoc_id}/{partida_id}/{versio_id}
+pub const DIR_HISTORIAL: &str = ".historial";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct VersioPartidaGuardadaAPI {
+    pub id: String,
+    pub timestamp: i64,
+    pub hash: String,
+    pub mida: u64,
+}
+
+pub fn dir_versions(arrel: &Path, videojoc_id: &str, partida_id: &str) -> PathBuf {
+    arrel.join(DIR_HISTORIAL).join(videojoc_id).join(partida_id)
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    hash_reader(&mut fs::File::open(path)?)
+}
+
+pub fn hash_reader(f: &mut impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn parse_versio_id(id: &str) -> Option<(i64, u32, &str)> {
+    // El id de la versio es "{segons}_{nanosegons}_{hash}" del moment en que es va arxivar. No es la data de
+    // modificacio de la partida: els clients la conserven i una partida antiga es pot arxivar despres d'una de nova.
+    // (Les versions arxivades abans d'aquest canvi porten la data de modificacio, sempre anterior a l'arxivat.)
+    let mut parts = id.splitn(3, '_');
+    let segons = parts.next()?.parse::<i64>().ok()?;
+    let nanos = parts.next()?;
+    let hash = parts.next()?;
+    if nanos.len() != 9 || !nanos.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if hash.is_empty() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((segons, nanos.parse().ok()?, hash))
+}
+
+pub fn hash_de_versio(versio_id: &str) -> Option<&str> {
+    Some(parse_versio_id(versio_id)?.2)
+}
+
+pub fn path_versio(dir: &Path, versio_id: &str) -> Option<PathBuf> {
+    parse_versio_id(versio_id)?;
+    Some(dir.join(versio_id))
+}
+
+pub fn llistar_versions(dir: &Path) -> io::Result<Vec<VersioPartidaGuardadaAPI>> {
+    // Retorna les versions de la arxivada mes tard a la mes antiga
+    let mut versions = Vec::new();
+    if !dir.exists() {
+        return Ok(versions);
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let id = entry.file_name().to_string_lossy().to_string();
+        if let Some((timestamp, _, hash)) = parse_versio_id(&id) {
+            versions.push(VersioPartidaGuardadaAPI {
+                timestamp,
+                hash: hash.to_string(),
+                mida: entry.metadata()?.len(),
+                id,
+            });
+        }
+    }
+    // Per la data del id com a numeros: com a text "999_..." aniria davant de "1000_..."
+    versions.sort_by_cached_key(|v| {
+        let (segons, nanos, _) = parse_versio_id(&v.id).unwrap();
+        std::cmp::Reverse((segons, nanos, v.id.clone()))
+    });
+    Ok(versions)
+}
+
+pub fn arxivar(partida_path: &Path, dir: &Path, max_versions: usize) -> io::Result<()> {
+    // Guarda una copia de la partida actual a l'historial abans de sobreescriure-la o eliminar-la
+    if max_versions == 0 || !partida_path.is_file() {
+        return Ok(());
+    }
+    let hash = hash_file(partida_path)?;
+    let versions = llistar_versions(dir)?;
+    // Si la versio mes recent ja te aquest contingut no cal duplicar-la
+    if versions.first().map(|v| v.hash == hash).unwrap_or(false) {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    let ara = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let id = format!("{}_{:09}_{}", ara.as_secs(), ara.subsec_nanos(), hash);
+    escriptura::copiar(partida_path, &dir.join(id))?;
+    podar(dir, max_versions)
+}
+
+pub fn podar(dir: &Path, max_versions: usize) -> io::Result<()> {
+    // Elimina les versions arxivades fa mes temps fins a quedar-nos amb `max_versions`
+    for versio in llistar_versions(dir)?.iter().skip(max_versions) {
+        fs::remove_file(dir.join(&versio.id))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+    fn get_test_dir(nom: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(nom);
+        let _ = remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    #[test]
+    fn test_parse_versio_id() {
+        assert_eq!(
+            parse_versio_id("1700000000_000000123_ab12"),
+            Some((1700000000, 123, "ab12"))
+        );
+        assert_eq!(parse_versio_id("1700000000_123_ab12"), None);
+        assert_eq!(parse_versio_id("../../etc/passwd"), None);
+        assert_eq!(parse_versio_id("1700000000_000000123_../x"), None);
+    }
+    #[test]
+    fn test_arxivar_i_podar() {
+        let dir = get_test_dir("serpg_test_historial");
+        let partida = dir.join("save.sav");
+        let versions_dir = dir.join("versions");
+        // Sense fitxer no es guarda res
+        arxivar(&partida, &versions_dir, 2).unwrap();
+        assert_eq!(llistar_versions(&versions_dir).unwrap().len(), 0);
+        for contingut in ["v1", "v2", "v3"] {
+            fs::write(&partida, contingut).unwrap();
+            arxivar(&partida, &versions_dir, 2).unwrap();
+            // El mateix contingut no es duplica
+            arxivar(&partida, &versions_dir, 2).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let versions = llistar_versions(&versions_dir).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].hash, hash_file(&partida).unwrap());
+        assert_eq!(
+            fs::read(versions_dir.join(&versions[1].id)).unwrap(),
+            b"v2".to_vec()
+        );
+        // L'ordre es el d'arxivat, encara que la partida arxivada tingui una data de modificacio anterior
+        fs::write(&partida, "v_antiga").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&partida)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000))
+            .unwrap();
+        arxivar(&partida, &versions_dir, 2).unwrap();
+        let versions = llistar_versions(&versions_dir).unwrap();
+        assert_eq!(
+            fs::read(versions_dir.join(&versions[0].id)).unwrap(),
+            b"v_antiga".to_vec()
+        );
+        assert_eq!(
+            fs::read(versions_dir.join(&versions[1].id)).unwrap(),
+            b"v3".to_vec()
+        );
+        // i la comparacio amb la versio mes recent es fa amb la que s'acaba d'arxivar
+        arxivar(&partida, &versions_dir, 2).unwrap();
+        assert_eq!(llistar_versions(&versions_dir).unwrap(), versions);
+        // Amb max_versions a 0 no es guarda historial
+        fs::write(&partida, "v4").unwrap();
+        arxivar(&partida, &versions_dir, 0).unwrap();
+        assert_eq!(llistar_versions(&versions_dir).unwrap().len(), 2);
+        let _ = remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::capcaleres;
+use crate::escriptura;
+use crate::historial;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+
+// Index persistent dels hash de les partides de cada videojoc: .index/{videojoc_id}.json
+// Una entrada es valida mentre la mida i la data de modificacio del fitxer no canviin, aixi
+// llistar un videojoc nomes fa un stat de cada partida i nomes es torna a llegir el que ha canviat.
+pub const DIR_INDEX: &str = ".index";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EntradaIndex {
+    pub mida: u64,
+    // Data de modificacio: segons des de 1970 i nanosegons
+    pub mtime: i64,
+    pub mtime_nanos: u32,
+    pub hash: String,
+    // Permisos del fitxer: no es guarden a l'index, es llegeixen cada cop amb la resta de metadades
+    #[serde(skip)]
+    pub mode: Option<u32>,
+    // Dispositiu que ha pujat la partida. Si el fitxer canvia per fora del servidor es perd.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispositiu: Option<String>,
+}
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct IndexVideojoc {
+    // Clau: id de la partida (ruta relativa al directori del videojoc)
+    partides: HashMap<String, EntradaIndex>,
+    #[serde(skip)]
+    modificat: bool,
+}
+
+pub fn fitxer_index(arrel: &Path, videojoc_id: &str) -> PathBuf {
+    arrel.join(DIR_INDEX).join(format!("{videojoc_id}.json"))
+}
+
+fn entrada(
+    path: &Path,
+    hash: Option<String>,
+    dispositiu: Option<String>,
+) -> io::Result<EntradaIndex> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let hash = match hash {
+        Some(hash) => hash,
+        None => historial::hash_file(path)?,
+    };
+    Ok(EntradaIndex {
+        mida: metadata.len(),
+        mtime: mtime.as_secs() as i64,
+        mtime_nanos: mtime.subsec_nanos(),
+        hash,
+        mode: capcaleres::mode(&metadata),
+        dispositiu,
+    })
+}
+
+impl IndexVideojoc {
+    fn load(fitxer: &Path) -> Self {
+        // Si l'index no existeix o no es pot llegir es reconstrueix: nomes es una cache
+        fs::read_to_string(fitxer)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+    fn save(&mut self, fitxer: &Path) -> io::Result<()> {
+        if let Some(dir) = fitxer.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        escriptura::escriure(fitxer, serde_json::to_string(&self)?.as_bytes())?;
+        self.modificat = false;
+        Ok(())
+    }
+    fn consultar(&self, nom: &str, path: &Path) -> io::Result<Option<EntradaIndex>> {
+        // L'entrada de l'index si la partida no ha canviat; None si cal tornar-ne a calcular el hash
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        match self.partides.get(nom) {
+            Some(e)
+                if e.mida == metadata.len()
+                    && e.mtime == mtime.as_secs() as i64
+                    && e.mtime_nanos == mtime.subsec_nanos() =>
+            {
+                let mut e = e.clone();
+                e.mode = capcaleres::mode(&metadata);
+                Ok(Some(e))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// Cada fitxer d'index te el seu lock: les peticions d'un videojoc no esperen les dels altres
+type IndexCompartit = Arc<Mutex<Option<IndexVideojoc>>>;
+
+#[derive(Default)]
+pub struct Indexos {
+    // Indexos ja llegits de disc, per fitxer d'index
+    carregats: Mutex<HashMap<PathBuf, IndexCompartit>>,
+}
+impl Indexos {
+    fn amb_index<T>(&self, fitxer: &Path, f: impl FnOnce(&mut IndexVideojoc) -> T) -> T {
+        let index = self
+            .carregats
+            .lock()
+            .unwrap()
+            .entry(fitxer.to_path_buf())
+            .or_default()
+            .clone();
+        let mut index = index.lock().unwrap();
+        let index = index.get_or_insert_with(|| IndexVideojoc::load(fitxer));
+        let resultat = f(index);
+        if index.modificat {
+            // Si no es pot guardar es torna a intentar al seguent canvi; l'index en memoria es correcte
+            if let Err(e) = index.save(fitxer) {
+                println!("No s'ha pogut guardar l'index {}: {e}", fitxer.display());
+            }
+        }
+        resultat
+    }
+    fn refrescar(
+        &self,
+        fitxer: &Path,
+        partides: &[(String, PathBuf)],
+        podar: bool,
+    ) -> io::Result<Vec<EntradaIndex>> {
+        // Amb el lock nomes es consulta l'index. El hash de les partides que han canviat es calcula
+        // sense el lock, i despres es guarda si mentrestant no s'ha escrit la partida.
+        let (mut entrades, anteriors) = self.amb_index(fitxer, |index| -> io::Result<_> {
+            if podar {
+                let noms: HashSet<&str> = partides.iter().map(|(nom, _)| nom.as_str()).collect();
+                let abans = index.partides.len();
+                index.partides.retain(|nom, _| noms.contains(nom.as_str()));
+                index.modificat |= index.partides.len() != abans;
+            }
+            let entrades = partides
+                .iter()
+                .map(|(nom, path)| index.consultar(nom, path))
+                .collect::<io::Result<Vec<_>>>()?;
+            let anteriors: Vec<Option<EntradaIndex>> = partides
+                .iter()
+                .map(|(nom, _)| index.partides.get(nom).cloned())
+                .collect();
+            Ok((entrades, anteriors))
+        })?;
+        let mut noves = Vec::new();
+        for (i, (nom, path)) in partides.iter().enumerate() {
+            if entrades[i].is_none() {
+                let e = entrada(path, None, None)?;
+                noves.push((nom.clone(), anteriors[i].clone(), e.clone()));
+                entrades[i] = Some(e);
+            }
+        }
+        if !noves.is_empty() {
+            self.amb_index(fitxer, |index| {
+                for (nom, anterior, e) in noves {
+                    // Si s'ha pujat la partida mentre es llegia, l'entrada de la pujada es la bona
+                    if index.partides.get(&nom) == anterior.as_ref() {
+                        index.partides.insert(nom, e);
+                        index.modificat = true;
+                    }
+                }
+            });
+        }
+        Ok(entrades.into_iter().flatten().collect())
+    }
+    pub fn entrades(
+        &self,
+        fitxer: &Path,
+        partides: &[(String, PathBuf)],
+    ) -> io::Result<Vec<EntradaIndex>> {
+        // Retorna el hash i la data de modificacio de cada partida, en el mateix ordre
+        self.refrescar(fitxer, partides, true)
+    }
+    pub fn entrada(&self, fitxer: &Path, nom: &str, path: &Path) -> io::Result<EntradaIndex> {
+        let mut entrades =
+            self.refrescar(fitxer, &[(nom.to_string(), path.to_path_buf())], false)?;
+        Ok(entrades.remove(0))
+    }
+    pub async fn entrades_async(
+        self: &Arc<Self>,
+        fitxer: &Path,
+        partides: &[(String, PathBuf)],
+    ) -> io::Result<Vec<EntradaIndex>> {
+        // Per les peticions: llegir les partides que han canviat bloquejaria el fil de tokio
+        let (indexos, fitxer, partides) =
+            (Arc::clone(self), fitxer.to_path_buf(), partides.to_vec());
+        tokio::task::spawn_blocking(move || indexos.entrades(&fitxer, &partides))
+            .await
+            .map_err(io::Error::other)?
+    }
+    pub async fn entrada_async(
+        self: &Arc<Self>,
+        fitxer: &Path,
+        nom: &str,
+        path: &Path,
+    ) -> io::Result<EntradaIndex> {
+        let (indexos, fitxer) = (Arc::clone(self), fitxer.to_path_buf());
+        let (nom, path) = (nom.to_string(), path.to_path_buf());
+        tokio::task::spawn_blocking(move || indexos.entrada(&fitxer, &nom, &path))
+            .await
+            .map_err(io::Error::other)?
+    }
+    pub fn actualitzar(
+        &self,
+        fitxer: &Path,
+        nom: &str,
+        path: &Path,
+        hash: Option<String>,
+        dispositiu: Option<String>,
+    ) -> io::Result<()> {
+        // S'ha escrit la partida: si ja es coneix el hash no cal tornar-la a llegir
+        self.amb_index(fitxer, |index| {
+            index
+                .partides
+                .insert(nom.to_string(), entrada(path, hash, dispositiu)?);
+            index.modificat = true;
+            Ok(())
+        })
+    }
+    pub fn eliminar(&self, fitxer: &Path, nom: &str) {
+        self.amb_index(fitxer, |index| {
+            index.modificat |= index.partides.remove(nom).is_some();
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+    #[test]
+    fn test_hashes() {
+        let dir = std::env::temp_dir().join("serpg_test_index");
+        let _ = remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let fitxer = fitxer_index(&dir, "Stellaris");
+        let save1 = dir.join("save1.sav");
+        let save2 = dir.join("save2.sav");
+        fs::write(&save1, "v1").unwrap();
+        fs::write(&save2, "v2").unwrap();
+        let partides = vec![
+            ("save1.sav".to_string(), save1.clone()),
+            ("save2.sav".to_string(), save2.clone()),
+        ];
+        let indexos = Indexos::default();
+        let entrades = indexos.entrades(&fitxer, &partides).unwrap();
+        assert_eq!(entrades[0].hash, historial::hash_file(&save1).unwrap());
+        assert_eq!(entrades[1].mida, 2);
+        let mtime = fs::metadata(&save1)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(entrades[0].mtime, mtime.as_secs() as i64);
+        assert_eq!(entrades[0].mtime_nanos, mtime.subsec_nanos());
+        assert!(fitxer.exists());
+        // Si la mida i la data no canvien es fa servir el hash de l'index sense llegir el fitxer
+        indexos
+            .actualitzar(
+                &fitxer,
+                "save1.sav",
+                &save1,
+                Some("fals".to_string()),
+                Some("portatil".to_string()),
+            )
+            .unwrap();
+        let indexos = Indexos::default();
+        assert_eq!(
+            indexos.entrades(&fitxer, &partides).unwrap()[0].hash,
+            "fals"
+        );
+        // Si el fitxer canvia es torna a calcular
+        fs::write(&save1, "v1 modificada").unwrap();
+        let entrades = indexos.entrades(&fitxer, &partides).unwrap();
+        assert_eq!(entrades[0].hash, historial::hash_file(&save1).unwrap());
+        // Les partides que ja no hi son surten de l'index
+        indexos.eliminar(&fitxer, "save1.sav");
+        let entrades = indexos.entrades(&fitxer, &partides[1..]).unwrap();
+        assert_eq!(entrades.len(), 1);
+        assert_eq!(entrades[0].hash, historial::hash_file(&save2).unwrap());
+        let index = IndexVideojoc::load(&fitxer);
+        assert_eq!(index.partides.len(), 1);
+        assert!(index.partides.contains_key("save2.sav"));
+        // Consultar una sola partida no treu les altres de l'index
+        fs::write(&save1, "v1").unwrap();
+        let e = indexos.entrada(&fitxer, "save1.sav", &save1).unwrap();
+        assert_eq!(e.hash, historial::hash_file(&save1).unwrap());
+        assert_eq!(IndexVideojoc::load(&fitxer).partides.len(), 2);
+        let _ = remove_dir_all(&dir);
+    }
+}
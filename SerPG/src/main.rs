@@ -1,25 +1,49 @@
+mod capcaleres;
+mod error;
+mod escriptura;
+mod historial;
+mod index;
+mod rutes;
+mod tls;
+mod usuaris;
+
 use axum::{
+    body::Body,
     extract::{DefaultBodyLimit, Json, Path, State},
-    http::StatusCode,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
     routing::{delete, get, post},
     Router,
 };
 use axum_auth::AuthBasic;
 use clap::{Arg, Command};
+use error::ErrorSerPG;
+use futures_util::StreamExt;
+use historial::VersioPartidaGuardadaAPI;
+use index::Indexos;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
     fs,
-    fs::File,
-    io::{Read, Write},
-    path::PathBuf,
+    io::{self, Seek, Write},
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
 };
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
+use tokio_util::io::ReaderStream;
+use usuaris::{Usuaris, UsuarisConfig};
 #[derive(Clone)]
 struct SerPGState {
     videojocs_path: String,
-    user: String,
-    password: String,
+    // Usuari donat per linia de comandes: les seves partides son directament a videojocs_path
+    usuari: Option<(String, String)>,
+    // Usuaris del fitxer d'usuaris: cadascun te les partides a videojocs_path/.usuaris/{nom}
+    usuaris: Arc<Usuaris>,
+    max_versions: usize,
+    // Hash de les partides per no haver de llegir-les totes cada cop que es llisten
+    indexos: Arc<Indexos>,
 }
 #[derive(Debug, Serialize, Deserialize)]
 struct VideojocAPI {
@@ -30,23 +54,59 @@ struct VideojocAPI {
 struct PartidaGuardadaAPI {
     nom: String,
     hash: String,
+    // Data de modificacio del fitxer: segons des de 1970 i nanosegons
+    mtime: i64,
+    mtime_nanos: u32,
+    // Permisos del fitxer (unix)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    mida: u64,
+    // Dispositiu que l'ha pujada, si el client l'ha enviat
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dispositiu: Option<String>,
 }
 #[derive(Debug, Deserialize, Serialize)]
 struct PartidaGuardadaContingutAPI {
     nom: String,
     contingut: Vec<u8>,
+    // SHA-256 del contingut. Si el client l'envia es comprova abans de guardar la partida.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
 }
 struct SerPG {
-    pub router: Router,
+    state: SerPGState,
+    // Certificat i clau (PEM) per servir per HTTPS
+    tls: Option<(String, String)>,
 }
 impl SerPG {
-    fn new(path: String, user: String, password: String) -> Self {
+    fn new(path: String) -> Self {
         let state = SerPGState {
             videojocs_path: path,
-            user,
-            password,
+            usuari: None,
+            usuaris: Arc::new(Usuaris::default()),
+            max_versions: 10,
+            indexos: Arc::new(Indexos::default()),
         };
-        let r = Router::new()
+        SerPG { state, tls: None }
+    }
+    fn with_usuari(mut self, user: String, password: String) -> Self {
+        self.state.usuari = Some((user, password));
+        self
+    }
+    fn with_usuaris(mut self, usuaris: UsuarisConfig) -> Self {
+        self.state.usuaris = Arc::new(Usuaris::new(usuaris));
+        self
+    }
+    fn with_max_versions(mut self, max_versions: usize) -> Self {
+        self.state.max_versions = max_versions;
+        self
+    }
+    fn with_tls(mut self, cert: String, key: String) -> Self {
+        self.tls = Some((cert, key));
+        self
+    }
+    fn router(&self) -> Router {
+        Router::new()
             .route("/api/v1/test", get(Self::test))
             .route("/api/v1/videojocs", get(Self::get_videojocs))
             .route(
@@ -61,48 +121,161 @@ impl SerPG {
                 "/api/v1/videojocs/{videojoc_id}/partides/{partida_id}/contingut",
                 get(Self::get_partida_guardada),
             )
+            .route(
+                "/api/v1/videojocs/{videojoc_id}/partides/{partida_id}/binari",
+                get(Self::get_partida_guardada_binari).put(Self::put_partida_guardada_binari),
+            )
+            .route(
+                "/api/v1/videojocs/{videojoc_id}/partides/{partida_id}/versions",
+                get(Self::get_versions_partida_guardada),
+            )
+            .route(
+                "/api/v1/videojocs/{videojoc_id}/partides/{partida_id}/versions/{versio_id}/binari",
+                get(Self::get_versio_partida_guardada),
+            )
+            .route(
+                "/api/v1/videojocs/{videojoc_id}/partides/{partida_id}/versions/{versio_id}/restaurar",
+                post(Self::restaurar_versio_partida_guardada),
+            )
+            // Limit per les peticions JSON (compatibilitat). Els endpoints binaris no carreguen el cos a memoria.
             .layer(DefaultBodyLimit::max(2 * 1024 * 1024 * 1024)) // 2 GiB
-            .with_state(state);
-        SerPG { router: r }
+            .with_state(self.state.clone())
     }
-    fn hash_file(path: &PathBuf) -> String {
-        let data = std::fs::read(path).unwrap_or_default();
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let hash_bytes = hasher.finalize();
-        hex::encode(hash_bytes)
+    fn llistar_partides(
+        dir: &FsPath,
+        prefix: &str,
+        partides: &mut Vec<(String, PathBuf)>,
+    ) -> io::Result<()> {
+        // Recorre el directori del videojoc i tots els seus subdirectoris. El nom de cada partida
+        // es la seva ruta relativa al directori del videojoc separada per "/".
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let nom = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(rutes::PREFIX_RESERVAT)
+            {
+                // Fitxers interns del servidor, com els temporals d'escriptures en curs
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                Self::llistar_partides(&entry.path(), &format!("{nom}/"), partides)?;
+            } else {
+                partides.push((nom, entry.path()));
+            }
+        }
+        Ok(())
+    }
+    fn eliminar_directoris_buits(dir: &FsPath, arrel: &FsPath) {
+        // Elimina els subdirectoris que han quedat buits fins arribar al directori del videojoc
+        let mut actual = dir.to_path_buf();
+        while actual.starts_with(arrel) && actual != arrel {
+            if fs::remove_dir(&actual).is_err() {
+                break;
+            }
+            actual.pop();
+        }
+    }
+    fn partida_no_trobada(videojoc_id: &str, partida_id: &str) -> ErrorSerPG {
+        ErrorSerPG::NoTrobat(format!(
+            "La partida \"{partida_id}\" del videojoc \"{videojoc_id}\" no existeix"
+        ))
+    }
+    fn resposta_binari(f: tokio::fs::File, metadata: fs::Metadata, hash: &str) -> Response {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let mut resposta = (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::CONTENT_LENGTH, metadata.len().to_string()),
+                (
+                    header::HeaderName::from_static(capcaleres::MTIME),
+                    capcaleres::format_mtime(mtime.as_secs() as i64, mtime.subsec_nanos()),
+                ),
+                (
+                    header::HeaderName::from_static(capcaleres::HASH),
+                    hash.to_string(),
+                ),
+            ],
+            Body::from_stream(ReaderStream::new(f)),
+        )
+            .into_response();
+        if let Some(mode) = capcaleres::mode(&metadata) {
+            resposta.headers_mut().insert(
+                capcaleres::MODE,
+                header::HeaderValue::from_str(&format!("{mode:o}")).unwrap(),
+            );
+        }
+        resposta
     }
     async fn start(self, port: Option<String>) {
         let port = port.unwrap_or_else(|| String::from("3000"));
         let addr = format!("0.0.0.0:{port}");
-        println!("🚀 SerPG escoltant a http://{}", addr);
-        let listener = TcpListener::bind(addr).await.unwrap();
-        axum::serve(listener, self.router).await.unwrap();
+        let Some((cert, key)) = self.tls.clone() else {
+            println!("🚀 SerPG escoltant a http://{}", addr);
+            let listener = TcpListener::bind(addr).await.unwrap();
+            axum::serve(listener, self.router()).await.unwrap();
+            return;
+        };
+        let config = tls::carregar(&cert, &key)
+            .await
+            .unwrap_or_else(|e| panic!("No s'ha pogut carregar el certificat {cert}: {e}"));
+        println!(
+            "🔒 Empremta SHA-256 del certificat: {}",
+            tls::empremta(&cert).unwrap_or_default()
+        );
+        tls::recarregar_amb_sighup(config.clone(), cert, key);
+        println!("🚀 SerPG escoltant a https://{}", addr);
+        axum_server::bind_rustls(addr.parse::<std::net::SocketAddr>().unwrap(), config)
+            .serve(self.router().into_make_service())
+            .await
+            .unwrap();
     }
-    fn check_auth(
+    async fn check_auth(
         user: String,
         pass: Option<String>,
         spg_state: &SerPGState,
-    ) -> Result<(), StatusCode> {
-        if user == spg_state.user && pass.as_deref() == Some(spg_state.password.as_str()) {
-            Ok(())
-        } else {
-            Err(StatusCode::UNAUTHORIZED)
+    ) -> Result<String, ErrorSerPG> {
+        // Retorna el directori arrel de les partides de l'usuari autenticat
+        let pass = pass.unwrap_or_default();
+        if let Some((usuari, contrasenya)) = &spg_state.usuari {
+            if &user == usuari && &pass == contrasenya {
+                return Ok(spg_state.videojocs_path.clone());
+            }
+        }
+        if spg_state.usuaris.verificar_async(&user, &pass).await {
+            let arrel = usuaris::dir_usuari(FsPath::new(&spg_state.videojocs_path), &user);
+            fs::create_dir_all(&arrel)?;
+            return Ok(arrel.to_string_lossy().to_string());
         }
+        Err(ErrorSerPG::NoAutoritzat)
     }
-    async fn test(AuthBasic((user, pass)): AuthBasic) -> Result<&'static str, StatusCode> {
+    async fn test(
+        AuthBasic((user, pass)): AuthBasic,
+        State(spg_state): State<SerPGState>,
+    ) -> Result<&'static str, ErrorSerPG> {
         println!("GET /api/v1/test");
+        Self::check_auth(user, pass, &spg_state).await?;
         Ok("OK")
     }
     async fn get_videojocs(
         AuthBasic((user, pass)): AuthBasic,
         State(spg_state): State<SerPGState>,
-    ) -> Result<Json<Vec<VideojocAPI>>, StatusCode> {
+    ) -> Result<Json<Vec<VideojocAPI>>, ErrorSerPG> {
         println!("GET /api/v1/videojocs");
-        Self::check_auth(user, pass, &spg_state)?;
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
         let mut videojocs_list = Vec::new();
-        for path in fs::read_dir(spg_state.videojocs_path).unwrap() {
-            let videojoc = path.unwrap().file_name().to_str().unwrap().to_string();
+        for path in fs::read_dir(arrel)? {
+            let path = path?;
+            let videojoc = path.file_name().to_string_lossy().to_string();
+            // Els directoris que comencen per "." son interns del servidor (p.ex. l'historial)
+            if videojoc.starts_with('.') || !path.file_type()?.is_dir() {
+                continue;
+            }
             videojocs_list.push(VideojocAPI {
                 id: videojoc.clone(),
                 nom: videojoc.clone(),
@@ -114,22 +287,32 @@ impl SerPG {
         AuthBasic((user, pass)): AuthBasic,
         State(spg_state): State<SerPGState>,
         Path(videojoc_id): Path<String>,
-    ) -> Result<Json<Vec<PartidaGuardadaAPI>>, StatusCode> {
+    ) -> Result<Json<Vec<PartidaGuardadaAPI>>, ErrorSerPG> {
         println!("GET /api/v1/videojocs/{videojoc_id}/partides");
-        Self::check_auth(user, pass, &spg_state)?;
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let videojoc_path = rutes::path_videojoc(&arrel, &videojoc_id)?;
         let mut partides_list = Vec::new();
-        let videojoc_path = format!("{}/{}", spg_state.videojocs_path, videojoc_id);
-        if !PathBuf::from(&videojoc_path).exists() {
-            fs::create_dir(videojoc_path.clone()).unwrap();
-        }
-        for entry in fs::read_dir(videojoc_path).unwrap() {
-            let entry = entry.unwrap();
-            let full_path = entry.path();
-            let partida_hash = Self::hash_file(&full_path);
-            let partida = entry.file_name().to_str().unwrap().to_string();
+        // Un videojoc que encara no te partides al servidor es crea buit
+        if !videojoc_path.exists() {
+            fs::create_dir(&videojoc_path)?;
+        }
+        let mut partides = Vec::new();
+        Self::llistar_partides(&videojoc_path, "", &mut partides)?;
+        partides.sort();
+        let index_path = rutes::path_index(&arrel, &videojoc_id)?;
+        let entrades = spg_state
+            .indexos
+            .entrades_async(&index_path, &partides)
+            .await?;
+        for ((partida, _), entrada) in partides.into_iter().zip(entrades) {
             partides_list.push(PartidaGuardadaAPI {
                 nom: partida,
-                hash: partida_hash,
+                hash: entrada.hash,
+                mtime: entrada.mtime,
+                mtime_nanos: entrada.mtime_nanos,
+                mode: entrada.mode,
+                mida: entrada.mida,
+                dispositiu: entrada.dispositiu,
             });
         }
         Ok(Json(partides_list))
@@ -138,60 +321,303 @@ impl SerPG {
         AuthBasic((user, pass)): AuthBasic,
         State(spg_state): State<SerPGState>,
         Path((videojoc_id, partida_id)): Path<(String, String)>,
-    ) -> Result<Json<PartidaGuardadaContingutAPI>, StatusCode> {
+    ) -> Result<Json<PartidaGuardadaContingutAPI>, ErrorSerPG> {
         println!("GET /api/v1/videojocs/{videojoc_id}/partides/{partida_id}/contingut");
-        Self::check_auth(user, pass, &spg_state)?;
-        let partida_path = format!(
-            "{}/{}/{}",
-            spg_state.videojocs_path, videojoc_id, partida_id
-        );
-        let contingut = fs::read(&partida_path).unwrap();
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let partida_path = rutes::path_partida(&arrel, &videojoc_id, &partida_id)?;
+        if !partida_path.is_file() {
+            return Err(Self::partida_no_trobada(&videojoc_id, &partida_id));
+        }
+        let contingut = fs::read(&partida_path)?;
+        let hash = hex::encode(Sha256::digest(&contingut));
         Ok(Json(PartidaGuardadaContingutAPI {
             nom: partida_id,
             contingut,
+            hash: Some(hash),
         }))
     }
+    async fn get_partida_guardada_binari(
+        AuthBasic((user, pass)): AuthBasic,
+        State(spg_state): State<SerPGState>,
+        Path((videojoc_id, partida_id)): Path<(String, String)>,
+    ) -> Result<Response, ErrorSerPG> {
+        // Retorna el contingut en cru (application/octet-stream) llegint el fitxer a trossos
+        println!("GET /api/v1/videojocs/{videojoc_id}/partides/{partida_id}/binari");
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let partida_path = rutes::path_partida(&arrel, &videojoc_id, &partida_id)?;
+        if !partida_path.is_file() {
+            return Err(Self::partida_no_trobada(&videojoc_id, &partida_id));
+        }
+        let index_path = rutes::path_index(&arrel, &videojoc_id)?;
+        let mut f = fs::File::open(&partida_path)?;
+        let metadata = f.metadata()?;
+        // El hash ha de ser el del fitxer obert: si la partida s'ha substituit mentrestant
+        // l'index ja correspon a la nova i cal calcular-lo
+        let entrada = spg_state
+            .indexos
+            .entrada_async(&index_path, &partida_id, &partida_path)
+            .await?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let hash = if entrada.mida == metadata.len()
+            && entrada.mtime == mtime.as_secs() as i64
+            && entrada.mtime_nanos == mtime.subsec_nanos()
+        {
+            entrada.hash
+        } else {
+            let hash = historial::hash_reader(&mut f)?;
+            f.rewind()?;
+            hash
+        };
+        Ok(Self::resposta_binari(
+            tokio::fs::File::from_std(f),
+            metadata,
+            &hash,
+        ))
+    }
+    async fn put_partida_guardada_binari(
+        AuthBasic((user, pass)): AuthBasic,
+        State(spg_state): State<SerPGState>,
+        Path((videojoc_id, partida_id)): Path<(String, String)>,
+        headers: HeaderMap,
+        body: Body,
+    ) -> Result<(), ErrorSerPG> {
+        // Escriu el cos de la peticio directament a disc a mesura que arriba, sense carregar-lo a memoria
+        println!("PUT /api/v1/videojocs/{videojoc_id}/partides/{partida_id}/binari");
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        // La partida conserva la data de modificacio i els permisos que tenia al client
+        let mtime = capcaleres::mtime_de_peticio(&headers)?;
+        let mode = capcaleres::mode_de_peticio(&headers)?;
+        let hash_esperat = capcaleres::hash_de_peticio(&headers)?;
+        let dispositiu = capcaleres::dispositiu_de_peticio(&headers)?;
+        let partida_path = rutes::path_partida(&arrel, &videojoc_id, &partida_id)?;
+        let versions_path = rutes::path_versions(&arrel, &videojoc_id, &partida_id)?;
+        let index_path = rutes::path_index(&arrel, &videojoc_id)?;
+        fs::create_dir_all(partida_path.parent().unwrap())?;
+        // El contingut es rep a un temporal i la partida nomes se substitueix quan ha arribat sencer:
+        // si la connexio es talla, el disc s'omple o el contingut no coincideix amb el hash,
+        // la partida anterior queda intacta
+        let temporal = escriptura::path_temporal(&partida_path);
+        let resultat = async {
+            let hash = Self::rebre_binari(&temporal, body, mode, mtime).await?;
+            capcaleres::comprovar_hash(hash_esperat.as_deref(), &hash)?;
+            Self::arxivar_partida(&spg_state, &partida_path, &versions_path)?;
+            escriptura::finalitzar(&temporal, &partida_path)?;
+            Ok::<_, ErrorSerPG>(hash)
+        }
+        .await;
+        if resultat.is_err() {
+            let _ = fs::remove_file(&temporal);
+        }
+        let hash = resultat?;
+        spg_state.indexos.actualitzar(
+            &index_path,
+            &partida_id,
+            &partida_path,
+            Some(hash),
+            dispositiu,
+        )?;
+        Ok(())
+    }
+    async fn rebre_binari(
+        temporal: &FsPath,
+        body: Body,
+        mode: Option<u32>,
+        mtime: Option<std::time::SystemTime>,
+    ) -> Result<String, ErrorSerPG> {
+        // Escriu el cos de la peticio a `temporal` i en retorna el hash
+        let mut f = tokio::fs::File::create(temporal).await?;
+        let mut stream = body.into_data_stream();
+        // El hash es calcula a mesura que arriba el contingut per no haver de tornar a llegir el fitxer
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| ErrorSerPG::PeticioIncorrecta(e.to_string()))?;
+            hasher.update(&chunk);
+            f.write_all(&chunk).await?;
+        }
+        f.flush().await?;
+        let f = f.into_std().await;
+        if let Some(mode) = mode {
+            capcaleres::aplicar_mode(&f, mode)?;
+        }
+        if let Some(mtime) = mtime {
+            f.set_modified(mtime)?;
+        }
+        f.sync_all()?;
+        Ok(hex::encode(hasher.finalize()))
+    }
     async fn delete_partida_guardada(
         AuthBasic((user, pass)): AuthBasic,
         State(spg_state): State<SerPGState>,
         Path((videojoc_id, partida_id)): Path<(String, String)>,
-    ) -> Result<(), StatusCode> {
+    ) -> Result<(), ErrorSerPG> {
         println!("DELETE /api/v1/videojocs/{videojoc_id}/partides/{partida_id}");
-        Self::check_auth(user, pass, &spg_state)?;
-        let partida_path = format!(
-            "{}/{}/{}",
-            spg_state.videojocs_path, videojoc_id, partida_id
-        );
-        if PathBuf::from(&partida_path).exists() {
-            std::fs::remove_file(partida_path).unwrap();
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let videojoc_path = rutes::path_videojoc(&arrel, &videojoc_id)?;
+        let partida_path = rutes::path_partida(&arrel, &videojoc_id, &partida_id)?;
+        let versions_path = rutes::path_versions(&arrel, &videojoc_id, &partida_id)?;
+        let index_path = rutes::path_index(&arrel, &videojoc_id)?;
+        // Eliminar una partida que no existeix no es un error: el resultat es el mateix
+        if partida_path.exists() {
+            // La partida eliminada es queda a l'historial i es pot restaurar
+            Self::arxivar_partida(&spg_state, &partida_path, &versions_path)?;
+            std::fs::remove_file(&partida_path)?;
+            Self::eliminar_directoris_buits(partida_path.parent().unwrap(), &videojoc_path);
         }
+        spg_state.indexos.eliminar(&index_path, &partida_id);
         Ok(())
     }
     async fn post_partida_guardada(
         AuthBasic((user, pass)): AuthBasic,
         State(spg_state): State<SerPGState>,
         Path(videojoc_id): Path<String>,
+        headers: HeaderMap,
         Json(partida_nova): Json<PartidaGuardadaContingutAPI>,
-    ) -> Result<(), StatusCode> {
+    ) -> Result<(), ErrorSerPG> {
         println!("POST /api/v1/videojocs/{videojoc_id}/partides");
-        Self::check_auth(user, pass, &spg_state)?;
-        let partida_path = format!(
-            "{}/{}/{}",
-            spg_state.videojocs_path,
-            videojoc_id,
-            partida_nova.nom.clone()
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let dispositiu = capcaleres::dispositiu_de_peticio(&headers)?;
+        let partida_path = rutes::path_partida(&arrel, &videojoc_id, &partida_nova.nom)?;
+        let versions_path = rutes::path_versions(&arrel, &videojoc_id, &partida_nova.nom)?;
+        let index_path = rutes::path_index(&arrel, &videojoc_id)?;
+        let hash = hex::encode(Sha256::digest(&partida_nova.contingut));
+        capcaleres::comprovar_hash(partida_nova.hash.as_deref(), &hash)?;
+        // La partida pot estar dins de subdirectoris del videojoc
+        fs::create_dir_all(partida_path.parent().unwrap())?;
+        Self::arxivar_partida(&spg_state, &partida_path, &versions_path)?;
+        escriptura::escriure(&partida_path, &partida_nova.contingut)?;
+        spg_state.indexos.actualitzar(
+            &index_path,
+            &partida_nova.nom,
+            &partida_path,
+            Some(hash),
+            dispositiu,
+        )?;
+        Ok(())
+    }
+    fn arxivar_partida(
+        spg_state: &SerPGState,
+        partida_path: &FsPath,
+        versions_path: &FsPath,
+    ) -> io::Result<()> {
+        // Guarda la versio actual de la partida a l'historial abans de modificar-la
+        historial::arxivar(partida_path, versions_path, spg_state.max_versions)
+    }
+    async fn get_versions_partida_guardada(
+        AuthBasic((user, pass)): AuthBasic,
+        State(spg_state): State<SerPGState>,
+        Path((videojoc_id, partida_id)): Path<(String, String)>,
+    ) -> Result<Json<Vec<VersioPartidaGuardadaAPI>>, ErrorSerPG> {
+        println!("GET /api/v1/videojocs/{videojoc_id}/partides/{partida_id}/versions");
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let versions_path = rutes::path_versions(&arrel, &videojoc_id, &partida_id)?;
+        Ok(Json(historial::llistar_versions(&versions_path)?))
+    }
+    fn path_versio(versions_path: &FsPath, versio_id: &str) -> Result<PathBuf, ErrorSerPG> {
+        let versio_path = historial::path_versio(versions_path, versio_id).ok_or_else(|| {
+            ErrorSerPG::PeticioIncorrecta(format!("\"{versio_id}\" no es un id de versio valid"))
+        })?;
+        if !versio_path.is_file() {
+            return Err(ErrorSerPG::NoTrobat(format!(
+                "La versio \"{versio_id}\" no existeix"
+            )));
+        }
+        Ok(versio_path)
+    }
+    async fn get_versio_partida_guardada(
+        AuthBasic((user, pass)): AuthBasic,
+        State(spg_state): State<SerPGState>,
+        Path((videojoc_id, partida_id, versio_id)): Path<(String, String, String)>,
+    ) -> Result<Response, ErrorSerPG> {
+        println!(
+            "GET /api/v1/videojocs/{videojoc_id}/partides/{partida_id}/versions/{versio_id}/binari"
+        );
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let versions_path = rutes::path_versions(&arrel, &videojoc_id, &partida_id)?;
+        let versio_path = Self::path_versio(&versions_path, &versio_id)?;
+        let f = tokio::fs::File::open(&versio_path).await?;
+        let metadata = f.metadata().await?;
+        // Les versions no es modifiquen: el hash es el del seu id
+        let hash = historial::hash_de_versio(&versio_id).unwrap_or_default();
+        Ok(Self::resposta_binari(f, metadata, hash))
+    }
+    async fn restaurar_versio_partida_guardada(
+        AuthBasic((user, pass)): AuthBasic,
+        State(spg_state): State<SerPGState>,
+        Path((videojoc_id, partida_id, versio_id)): Path<(String, String, String)>,
+    ) -> Result<(), ErrorSerPG> {
+        println!(
+            "POST /api/v1/videojocs/{videojoc_id}/partides/{partida_id}/versions/{versio_id}/restaurar"
         );
-        let videojoc_path = format!("{}/{}", spg_state.videojocs_path, videojoc_id);
-        if !PathBuf::from(&videojoc_path).exists() {
-            fs::create_dir(videojoc_path).unwrap();
+        let arrel = Self::check_auth(user, pass, &spg_state).await?;
+        let partida_path = rutes::path_partida(&arrel, &videojoc_id, &partida_id)?;
+        let versions_path = rutes::path_versions(&arrel, &videojoc_id, &partida_id)?;
+        let versio_path = Self::path_versio(&versions_path, &versio_id)?;
+        let index_path = rutes::path_index(&arrel, &videojoc_id)?;
+        fs::create_dir_all(partida_path.parent().unwrap())?;
+        // Copiem la versio abans d'arxivar l'actual, ja que arxivar pot podar la versio a restaurar
+        let temporal = escriptura::copiar_a_temporal(&versio_path, &partida_path)?;
+        // La versio que es substitueix tambe passa a l'historial
+        if let Err(e) = Self::arxivar_partida(&spg_state, &partida_path, &versions_path) {
+            let _ = fs::remove_file(&temporal);
+            return Err(e.into());
         }
-        let mut f = File::create(partida_path).unwrap();
-        f.write_all(&partida_nova.contingut.to_vec()).unwrap();
-        f.sync_all().unwrap();
-        drop(f);
+        escriptura::finalitzar(&temporal, &partida_path)?;
+        // El hash de la versio es part del seu id
+        let hash = historial::hash_de_versio(&versio_id).map(str::to_string);
+        spg_state
+            .indexos
+            .actualitzar(&index_path, &partida_id, &partida_path, hash, None)?;
         Ok(())
     }
 }
+fn gestionar_usuaris(matches: &clap::ArgMatches) {
+    // Subcomanda `usuaris`: modifica el fitxer d'usuaris. El servidor el llegeix en arrencar.
+    let fitxer = FsPath::new(matches.get_one::<String>("fitxer").unwrap());
+    let mut config = UsuarisConfig::load(fitxer)
+        .unwrap_or_else(|e| panic!("No s'ha pogut llegir {}: {e}", fitxer.display()));
+    match matches.subcommand() {
+        Some(("afegir", sub)) => {
+            let nom = sub.get_one::<String>("nom").unwrap();
+            let contrasenya = match sub.get_one::<String>("contrasenya") {
+                Some(c) => c.clone(),
+                None => {
+                    print!("Contrasenya per {nom}: ");
+                    std::io::stdout().flush().unwrap();
+                    let mut c = String::new();
+                    std::io::stdin().read_line(&mut c).unwrap();
+                    c.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            match config.afegir(nom, &contrasenya) {
+                Ok(true) => println!("Usuari {nom} afegit."),
+                Ok(false) => println!("Contrasenya de {nom} actualitzada."),
+                Err(err) => {
+                    println!("{err}");
+                    return;
+                }
+            }
+        }
+        Some(("eliminar", sub)) => {
+            let nom = sub.get_one::<String>("nom").unwrap();
+            if let Err(err) = config.eliminar(nom) {
+                println!("{err}");
+                return;
+            }
+            // Les partides de l'usuari no s'esborren
+            println!("Usuari {nom} eliminat.");
+        }
+        _ => {
+            for u in config.usuaris.iter() {
+                println!("* {}", u.nom);
+            }
+            return;
+        }
+    }
+    config.save(fitxer).unwrap();
+}
 #[tokio::main]
 async fn main() {
     let matches = Command::new("SerPG")
@@ -199,6 +625,7 @@ async fn main() {
         .author("Bcedu")
         .about("Pastanaga Bullida")
         .arg_required_else_help(true) // Mostra ajuda si no hi ha arguments
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("username")
                 .short('u')
@@ -206,7 +633,8 @@ async fn main() {
                 .help("Nom d'usuari")
                 .num_args(1)
                 .value_name("username")
-                .required(true),
+                .requires("password")
+                .required_unless_present("usuaris"),
         )
         .arg(
             Arg::new("password")
@@ -215,7 +643,14 @@ async fn main() {
                 .help("Contrasenya")
                 .num_args(1)
                 .value_name("password")
-                .required(true),
+                .requires("username"),
+        )
+        .arg(
+            Arg::new("usuaris")
+                .long("usuaris")
+                .help("Fitxer d'usuaris (veure la subcomanda usuaris). Cada usuari te les seves propies partides")
+                .num_args(1)
+                .value_name("fitxer"),
         )
         .arg(
             Arg::new("port")
@@ -235,45 +670,149 @@ async fn main() {
                 .value_name("path")
                 .required(true),
         )
+        .arg(
+            Arg::new("cert")
+                .long("cert")
+                .help("Certificat del servidor (PEM). Amb --cert i --key el servidor escolta per HTTPS. Amb SIGHUP es tornen a llegir")
+                .num_args(1)
+                .value_name("fitxer")
+                .requires("key"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .help("Clau privada del certificat (PEM)")
+                .num_args(1)
+                .value_name("fitxer")
+                .requires("cert"),
+        )
+        .arg(
+            Arg::new("versions")
+                .short('n')
+                .long("versions")
+                .help("Nombre de versions anteriors que es guarden de cada partida (0 per desactivar l'historial)")
+                .num_args(1)
+                .value_name("versions")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10"),
+        )
+        .subcommand(
+            Command::new("usuaris")
+                .about("Afegeix, elimina o llista els usuaris del fitxer d'usuaris")
+                .subcommand_required(true)
+                .arg(
+                    Arg::new("fitxer")
+                        .short('f')
+                        .long("fitxer")
+                        .help("Fitxer d'usuaris")
+                        .num_args(1)
+                        .value_name("fitxer")
+                        .required(true),
+                )
+                .subcommand(
+                    Command::new("afegir")
+                        .about("Afegeix un usuari o li canvia la contrasenya")
+                        .arg(Arg::new("nom").required(true))
+                        .arg(
+                            Arg::new("contrasenya")
+                                .short('w')
+                                .long("contrasenya")
+                                .help("Contrasenya (si no es dona es demana per l'entrada estandard)")
+                                .num_args(1),
+                        ),
+                )
+                .subcommand(
+                    Command::new("eliminar")
+                        .about("Elimina un usuari (les seves partides no s'esborren)")
+                        .arg(Arg::new("nom").required(true)),
+                )
+                .subcommand(Command::new("llistar").about("Llista els usuaris")),
+        )
         .get_matches();
+    if let Some(("usuaris", sub)) = matches.subcommand() {
+        gestionar_usuaris(sub);
+        return;
+    }
     let username = matches.get_one::<String>("username");
     let password = matches.get_one::<String>("password");
+    let fitxer_usuaris = matches.get_one::<String>("usuaris");
     let port = matches.get_one::<String>("port");
     let path = matches.get_one::<String>("path");
-    SerPG::new(
-        path.unwrap().to_string(),
-        username.unwrap().to_string(),
-        password.unwrap().to_string(),
-    )
-    .start(Some(port.unwrap().to_string()))
-    .await;
+    let versions = matches.get_one::<usize>("versions");
+    let cert = matches.get_one::<String>("cert");
+    let key = matches.get_one::<String>("key");
+    let mut serpg = SerPG::new(path.unwrap().to_string()).with_max_versions(*versions.unwrap());
+    if let (Some(username), Some(password)) = (username, password) {
+        serpg = serpg.with_usuari(username.to_string(), password.to_string());
+    }
+    if let Some(fitxer) = fitxer_usuaris {
+        let usuaris = UsuarisConfig::load(FsPath::new(fitxer))
+            .unwrap_or_else(|e| panic!("No s'ha pogut llegir {fitxer}: {e}"));
+        serpg = serpg.with_usuaris(usuaris);
+    }
+    if let (Some(cert), Some(key)) = (cert, key) {
+        serpg = serpg.with_tls(cert.to_string(), key.to_string());
+    }
+    serpg.start(Some(port.unwrap().to_string())).await;
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::{PartidaGuardadaContingutAPI, SerPG};
+    use crate::error::ErrorAPI;
+    use crate::{PartidaGuardadaContingutAPI, SerPG, UsuarisConfig, VersioPartidaGuardadaAPI};
+    use sha2::{Digest, Sha256};
     use std::fs::{remove_dir_all, File};
     use std::io::{Read, Write};
     use std::path::{Path, PathBuf};
+    use std::sync::OnceLock;
     use tokio::fs::remove_dir;
     use urlencoding::encode;
+    fn fixtures() -> PathBuf {
+        // Els tests del servidor del port 3001 treballen sobre una copia de tests/fixtures: el servidor hi
+        // escriu (partides, .index, .historial) i el directori del repositori no s'ha de modificar
+        static COPIA: OnceLock<PathBuf> = OnceLock::new();
+        COPIA
+            .get_or_init(|| {
+                let copia = std::env::temp_dir().join("serpg_test_fixtures");
+                let _ = remove_dir_all(&copia);
+                copiar_dir(
+                    &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"),
+                    &copia,
+                );
+                // git no guarda els directoris buits
+                std::fs::create_dir_all(copia.join("Warhammer 50k")).unwrap();
+                copia
+            })
+            .clone()
+    }
+    fn copiar_dir(origen: &Path, desti: &Path) {
+        std::fs::create_dir_all(desti).unwrap();
+        for entry in std::fs::read_dir(origen).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                copiar_dir(&path, &desti.join(entry.file_name()));
+            } else {
+                std::fs::copy(&path, desti.join(entry.file_name())).unwrap();
+            }
+        }
+    }
     async fn setup_server() {
         // Fem neteja decoses que no haurien de existir
-        let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/pastanaga bullida")
+        let test_path = fixtures()
+            .join("pastanaga bullida")
             .to_str()
             .unwrap()
             .to_string();
         remove_dir_all(&test_path);
         // Fem el servidor
-        let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures")
-            .to_str()
-            .unwrap()
-            .to_string();
-        tokio::spawn(async {
-            SerPG::new(test_path, "admin".to_string(), "admin".to_string())
-                .start(Some("3001".to_string()))
+        let test_path = fixtures().to_str().unwrap().to_string();
+        start_server(test_path, "3001").await;
+    }
+    async fn start_server(test_path: String, port: &'static str) {
+        tokio::spawn(async move {
+            SerPG::new(test_path)
+                .with_usuari("admin".to_string(), "admin".to_string())
+                .start(Some(port.to_string()))
                 .await;
         });
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
@@ -295,7 +834,10 @@ pub mod tests {
         body
     }
     async fn make_get_request(endpoint: &str) -> String {
-        let mut request_url = "http://127.0.0.1:3001/api/v1".to_string();
+        make_get_request_port("3001", endpoint).await
+    }
+    async fn make_get_request_port(port: &str, endpoint: &str) -> String {
+        let mut request_url = format!("http://127.0.0.1:{port}/api/v1");
         for endpoint_part in endpoint.split('/') {
             request_url = format!("{}/{}", request_url, encode(endpoint_part));
         }
@@ -342,8 +884,8 @@ pub mod tests {
     async fn test_api_get_partides_guardades() {
         let server = setup_server().await;
         // Joc que no existeix
-        let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/pastanaga bullida")
+        let test_path = fixtures()
+            .join("pastanaga bullida")
             .to_str()
             .unwrap()
             .to_string();
@@ -356,8 +898,27 @@ pub mod tests {
         assert_eq!(res, "[]");
         // Joc amb partides
         let res = make_get_request("videojocs/Napoleón TW HD/partides").await;
-        let expected_res = "[{\"nom\":\"save1.txt\",\"hash\":\"72489639c9286a5eb52d3cdd74fab92bce8467fe44d33e919dc2663c781e5536\"},{\"nom\":\"save3.txt\",\"hash\":\"2d39cbe113285d20a0f9119b56346daaa17fd195685981cb80d799bda2c27a2c\"}]";
-        assert_eq!(res, expected_res);
+        let res: Vec<serde_json::Value> = serde_json::from_str(&res).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0]["nom"], "save1.txt");
+        assert_eq!(
+            res[0]["hash"],
+            "72489639c9286a5eb52d3cdd74fab92bce8467fe44d33e919dc2663c781e5536"
+        );
+        assert_eq!(res[1]["nom"], "save3.txt");
+        assert_eq!(
+            res[1]["hash"],
+            "2d39cbe113285d20a0f9119b56346daaa17fd195685981cb80d799bda2c27a2c"
+        );
+        // La data de modificacio es la del fitxer
+        let mtime = std::fs::metadata(path.parent().unwrap().join("Napoleón TW HD/save1.txt"))
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(res[0]["mtime"], mtime.as_secs());
+        assert_eq!(res[0]["mtime_nanos"], mtime.subsec_nanos());
     }
     #[tokio::test]
     async fn test_api_get_partida_guardada() {
@@ -371,26 +932,29 @@ pub mod tests {
             .map(|b| b.to_string())
             .collect::<Vec<_>>()
             .join(",");
-        let expected_res = format!(r#"{{"nom":"save3.txt","contingut":[{}]}}"#, contingut_str);
+        let hash = hex::encode(Sha256::digest(&contingut_bytes));
+        let expected_res =
+            format!(r#"{{"nom":"save3.txt","contingut":[{contingut_str}],"hash":"{hash}"}}"#);
         assert_eq!(res, expected_res);
     }
     #[tokio::test]
     async fn test_post_partida_guardada() {
         let server = setup_server().await;
         // Joc que no existeix
-        let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/pastanaga bullida")
+        let test_path = fixtures()
+            .join("pastanaga bullida")
             .to_str()
             .unwrap()
             .to_string();
-        let test_partida_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/pastanaga bullida/save.txt")
+        let test_partida_path = fixtures()
+            .join("pastanaga bullida/save.txt")
             .to_str()
             .unwrap()
             .to_string();
         let partida_test = PartidaGuardadaContingutAPI {
             nom: "save.txt".to_string(),
             contingut: "@@".as_bytes().to_vec(),
+            hash: None,
         };
         make_post_request("videojocs/pastanaga bullida/partides", partida_test).await;
         let path = Path::new(&test_path);
@@ -403,19 +967,20 @@ pub mod tests {
         drop(f);
         assert_eq!(contingut, "@@");
         // Joc amb partides
-        let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/Napoleón TW HD")
+        let test_path = fixtures()
+            .join("Napoleón TW HD")
             .to_str()
             .unwrap()
             .to_string();
-        let test_partida_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/Napoleón TW HD/save3.txt")
+        let test_partida_path = fixtures()
+            .join("Napoleón TW HD/save3.txt")
             .to_str()
             .unwrap()
             .to_string();
         let partida_test = PartidaGuardadaContingutAPI {
             nom: "save3.txt".to_string(),
             contingut: "Partida4".as_bytes().to_vec(),
+            hash: None,
         };
         let original_content = "Soc una partida guardada del Total War 40k\nPartida 3";
         make_post_request("videojocs/Napoleón TW HD/partides", partida_test).await;
@@ -431,12 +996,16 @@ pub mod tests {
         let mut f = File::create(test_partida_path).unwrap();
         f.write_all(original_content.as_bytes()).unwrap();
         f.sync_all().unwrap();
-        drop(f)
+        drop(f);
+        // La versio sobreescrita s'ha guardat a l'historial
+        let historial_path = fixtures().join(".historial");
+        assert!(historial_path.join("Napoleón TW HD/save3.txt").exists());
+        let _ = std::fs::remove_dir_all(historial_path);
     }
     #[tokio::test]
     async fn test_api_delete_partida_guardada() {
-        let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("tests/fixtures/pastanaga bullida/save.txt")
+        let test_path = fixtures()
+            .join("pastanaga bullida/save.txt")
             .to_str()
             .unwrap()
             .to_string();
@@ -446,4 +1015,697 @@ pub mod tests {
         // revise que test_path ja no existeix
         assert!(!Path::new(&test_path).exists());
     }
+    #[tokio::test]
+    async fn test_api_partides_en_subdirectoris() {
+        // Servidor amb un directori propi per no interferir amb els altres tests
+        let test_path = std::env::temp_dir().join("serpg_test_subdirectoris");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        start_server(test_path.to_str().unwrap().to_string(), "3002").await;
+        let base = "http://127.0.0.1:3002/api/v1/videojocs/Stellaris/partides";
+        let client = reqwest::Client::new();
+        // Pujem partides en subdirectoris
+        for (nom, contingut) in [
+            ("perfil 1/save1.sav", "p1"),
+            ("perfil 1/slot 2/save2.sav", "p2"),
+            ("save0.sav", "p0"),
+        ] {
+            let partida = PartidaGuardadaContingutAPI {
+                nom: nom.to_string(),
+                contingut: contingut.as_bytes().to_vec(),
+                hash: None,
+            };
+            let res = client
+                .post(base)
+                .basic_auth("admin", Some("admin"))
+                .json(&partida)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.status(), 200);
+        }
+        assert!(test_path
+            .join("Stellaris/perfil 1/slot 2/save2.sav")
+            .exists());
+        // El llistat retorna les rutes relatives
+        let res = client
+            .get(base)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        let partides: Vec<serde_json::Value> = res.json().await.unwrap();
+        let noms: Vec<&str> = partides
+            .iter()
+            .map(|p| p["nom"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            noms,
+            vec![
+                "perfil 1/save1.sav",
+                "perfil 1/slot 2/save2.sav",
+                "save0.sav"
+            ]
+        );
+        // Descarreguem una partida d'un subdirectori
+        let url = format!("{}/{}/contingut", base, encode("perfil 1/slot 2/save2.sav"));
+        let res = client
+            .get(url)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        let partida: PartidaGuardadaContingutAPI = res.json().await.unwrap();
+        assert_eq!(partida.contingut, "p2".as_bytes().to_vec());
+        // Eliminar la partida elimina tambe els directoris que queden buits
+        let url = format!("{}/{}", base, encode("perfil 1/slot 2/save2.sav"));
+        let res = client
+            .delete(url)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert!(!test_path.join("Stellaris/perfil 1/slot 2").exists());
+        assert!(test_path.join("Stellaris/perfil 1/save1.sav").exists());
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_partida_guardada_binari() {
+        let test_path = std::env::temp_dir().join("serpg_test_binari");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        start_server(test_path.to_str().unwrap().to_string(), "3003").await;
+        let url = format!(
+            "http://127.0.0.1:3003/api/v1/videojocs/Stellaris/partides/{}/binari",
+            encode("perfil 1/save1.sav")
+        );
+        let client = reqwest::Client::new();
+        // Contingut binari que no es UTF-8
+        let contingut: Vec<u8> = (0..=255u8).cycle().take(100_000).collect();
+        let res = client
+            .put(&url)
+            .basic_auth("admin", Some("admin"))
+            .header("content-type", "application/octet-stream")
+            .body(contingut.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            std::fs::read(test_path.join("Stellaris/perfil 1/save1.sav")).unwrap(),
+            contingut
+        );
+        let res = client
+            .get(&url)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "application/octet-stream");
+        assert_eq!(res.bytes().await.unwrap().to_vec(), contingut);
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_index_hash() {
+        let test_path = std::env::temp_dir().join("serpg_test_index_hash");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        start_server(test_path.to_str().unwrap().to_string(), "3008").await;
+        let url = format!(
+            "http://127.0.0.1:3008/api/v1/videojocs/Stellaris/partides/{}",
+            encode("perfil 1/save1.sav")
+        );
+        let client = reqwest::Client::new();
+        let res = client
+            .put(format!("{url}/binari"))
+            .basic_auth("admin", Some("admin"))
+            .header("x-pg-mtime", "1700000000.000000005")
+            .header("x-pg-mode", "640")
+            .header("x-pg-dispositiu", "portatil")
+            .body("Partida 1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        // El PUT ja deixa el hash a l'index
+        let index_path = test_path.join(".index/Stellaris.json");
+        let index = std::fs::read_to_string(&index_path).unwrap();
+        let hash = hex::encode(Sha256::digest("Partida 1"));
+        assert!(index.contains("perfil 1/save1.sav") && index.contains(&hash));
+        let res = make_get_request_port("3008", "videojocs/Stellaris/partides").await;
+        assert_eq!(
+            res,
+            format!(
+                r#"[{{"nom":"perfil 1/save1.sav","hash":"{hash}","mtime":1700000000,"mtime_nanos":5,"mode":416,"mida":9,"dispositiu":"portatil"}}]"#
+            )
+        );
+        // La descarrega retorna la mateixa data de modificacio i els mateixos permisos
+        let res = client
+            .get(format!("{url}/binari"))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["x-pg-mtime"], "1700000000.000000005");
+        assert_eq!(res.headers()["x-pg-mode"], "640");
+        // Una data mal formada es rebutja
+        let res = client
+            .put(format!("{url}/binari"))
+            .basic_auth("admin", Some("admin"))
+            .header("x-pg-mtime", "ahir")
+            .body("Partida 1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 400);
+        // Una partida modificada directament al disc es torna a calcular
+        std::fs::write(
+            test_path.join("Stellaris/perfil 1/save1.sav"),
+            "Partida 1 modificada",
+        )
+        .unwrap();
+        let res = make_get_request_port("3008", "videojocs/Stellaris/partides").await;
+        let hash = hex::encode(Sha256::digest("Partida 1 modificada"));
+        assert!(res.contains(&hash));
+        // El directori de l'index no es un videojoc
+        let res = make_get_request_port("3008", "videojocs").await;
+        assert_eq!(res, r#"[{"id":"Stellaris","nom":"Stellaris"}]"#);
+        let res = client
+            .delete(&url)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let index = std::fs::read_to_string(&index_path).unwrap();
+        assert!(!index.contains("perfil 1/save1.sav"));
+        let _ = remove_dir_all(&test_path);
+    }
+    fn generar_certificat(dir: &Path) -> (String, String, String) {
+        let rcgen::CertifiedKey { cert, signing_key } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, signing_key.serialize_pem()).unwrap();
+        (
+            cert_path.to_str().unwrap().to_string(),
+            key_path.to_str().unwrap().to_string(),
+            cert.pem(),
+        )
+    }
+    async fn make_tls_request(cert_pem: &str) -> reqwest::Result<reqwest::Response> {
+        // Client que nomes confia en el certificat donat
+        reqwest::Client::builder()
+            .tls_certs_only([reqwest::Certificate::from_pem(cert_pem.as_bytes()).unwrap()])
+            .build()
+            .unwrap()
+            .get("https://localhost:3009/api/v1/test")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+    }
+    #[tokio::test]
+    async fn test_api_tls() {
+        let test_path = std::env::temp_dir().join("serpg_test_tls");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        let (cert, key, cert_pem) = generar_certificat(&test_path);
+        let arrel = test_path.to_str().unwrap().to_string();
+        let (c, k) = (cert.clone(), key.clone());
+        tokio::spawn(async move {
+            SerPG::new(arrel)
+                .with_usuari("admin".to_string(), "admin".to_string())
+                .with_tls(c, k)
+                .start(Some("3009".to_string()))
+                .await;
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let res = make_tls_request(&cert_pem).await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), "OK");
+        // Per HTTP no respon
+        assert!(reqwest::get("http://127.0.0.1:3009/api/v1/test")
+            .await
+            .is_err());
+        assert_eq!(crate::tls::empremta(&cert).unwrap().len(), 32 * 3 - 1);
+        // Amb SIGHUP es torna a llegir el certificat
+        #[cfg(unix)]
+        {
+            let (_, _, cert_pem_nou) = generar_certificat(&test_path);
+            std::process::Command::new("kill")
+                .args(["-HUP", &std::process::id().to_string()])
+                .status()
+                .unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            let res = make_tls_request(&cert_pem_nou).await.unwrap();
+            assert_eq!(res.status(), 200);
+            assert!(make_tls_request(&cert_pem).await.is_err());
+        }
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_escriptura_atomica() {
+        let test_path = std::env::temp_dir().join("serpg_test_escriptura_atomica");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        start_server(test_path.to_str().unwrap().to_string(), "3010").await;
+        let url = "http://127.0.0.1:3010/api/v1/videojocs/Stellaris/partides/save1.sav/binari";
+        let client = reqwest::Client::new();
+        let res = client
+            .put(url)
+            .basic_auth("admin", Some("admin"))
+            .body("Partida 1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        // Una pujada que es talla a mitges no toca la partida que hi havia
+        let trossos: Vec<Result<Vec<u8>, std::io::Error>> = vec![
+            Ok(b"Partida 2 a mit".to_vec()),
+            Err(std::io::Error::other("connexio tallada")),
+        ];
+        let _ = client
+            .put(url)
+            .basic_auth("admin", Some("admin"))
+            .body(reqwest::Body::wrap_stream(futures_util::stream::iter(
+                trossos,
+            )))
+            .send()
+            .await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let joc_path = test_path.join("Stellaris");
+        assert_eq!(
+            std::fs::read_to_string(joc_path.join("save1.sav")).unwrap(),
+            "Partida 1"
+        );
+        // No queden temporals, i si n'hi ha d'alguna aturada no es llisten com a partides
+        let noms: Vec<_> = std::fs::read_dir(&joc_path)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(noms, vec!["save1.sav"]);
+        std::fs::write(joc_path.join(".serpg-tmp-1-0-save2.sav"), "a mitges").unwrap();
+        let res = make_get_request_port("3010", "videojocs/Stellaris/partides").await;
+        assert!(res.contains("save1.sav") && !res.contains("save2.sav"));
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_integritat() {
+        let test_path = std::env::temp_dir().join("serpg_test_integritat");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        start_server(test_path.to_str().unwrap().to_string(), "3011").await;
+        let base = "http://127.0.0.1:3011/api/v1/videojocs/Stellaris";
+        let url = format!("{base}/partides/save1.sav/binari");
+        let client = reqwest::Client::new();
+        let hash = hex::encode(Sha256::digest("Partida 1"));
+        let res = client
+            .put(&url)
+            .basic_auth("admin", Some("admin"))
+            .header("x-pg-hash", hash.to_uppercase())
+            .body("Partida 1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        // Si el contingut no coincideix amb el hash la partida no es toca
+        let res = client
+            .put(&url)
+            .basic_auth("admin", Some("admin"))
+            .header("x-pg-hash", &hash)
+            .body("Partida 2")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 422);
+        assert_eq!(res.json::<ErrorAPI>().await.unwrap().codi, "integritat");
+        let res = client
+            .post(format!("{base}/partides"))
+            .basic_auth("admin", Some("admin"))
+            .json(&PartidaGuardadaContingutAPI {
+                nom: "save1.sav".to_string(),
+                contingut: "Partida 2".as_bytes().to_vec(),
+                hash: Some(hash.clone()),
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 422);
+        let joc_path = test_path.join("Stellaris");
+        assert_eq!(
+            std::fs::read_to_string(joc_path.join("save1.sav")).unwrap(),
+            "Partida 1"
+        );
+        let noms: Vec<_> = std::fs::read_dir(&joc_path)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(noms, vec!["save1.sav"]);
+        let res =
+            make_get_request_port("3011", "videojocs/Stellaris/partides/save1.sav/versions").await;
+        assert_eq!(res, "[]");
+        // Les descarregues porten el hash del contingut
+        let res = client
+            .get(&url)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["x-pg-hash"], hash.as_str());
+        // Encara que la partida canvii per fora del servidor
+        std::fs::write(joc_path.join("save1.sav"), "Partida 1 modificada").unwrap();
+        let res = client
+            .get(&url)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            res.headers()["x-pg-hash"],
+            hex::encode(Sha256::digest("Partida 1 modificada")).as_str()
+        );
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_historial_partida_guardada() {
+        let test_path = std::env::temp_dir().join("serpg_test_historial_api");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        let server_path = test_path.to_str().unwrap().to_string();
+        tokio::spawn(async move {
+            SerPG::new(server_path)
+                .with_usuari("admin".to_string(), "admin".to_string())
+                .with_max_versions(2)
+                .start(Some("3004".to_string()))
+                .await;
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let base = format!(
+            "http://127.0.0.1:3004/api/v1/videojocs/Stellaris/partides/{}",
+            encode("perfil 1/save1.sav")
+        );
+        let client = reqwest::Client::new();
+        for contingut in ["v1", "v2", "v3", "v4"] {
+            let res = client
+                .put(format!("{base}/binari"))
+                .basic_auth("admin", Some("admin"))
+                .body(contingut)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.status(), 200);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        // El llistat de partides no inclou l'historial
+        let res = make_get_request_port("3004", "videojocs").await;
+        assert_eq!(res, r#"[{"id":"Stellaris","nom":"Stellaris"}]"#);
+        // Nomes es guarden les 2 ultimes versions anteriors: v3 i v2
+        let get_versions = || async {
+            let res = client
+                .get(format!("{base}/versions"))
+                .basic_auth("admin", Some("admin"))
+                .send()
+                .await
+                .unwrap();
+            res.json::<Vec<VersioPartidaGuardadaAPI>>().await.unwrap()
+        };
+        let versions = get_versions().await;
+        assert_eq!(versions.len(), 2);
+        let mut continguts = Vec::new();
+        for versio in versions.iter() {
+            let res = client
+                .get(format!("{base}/versions/{}/binari", versio.id))
+                .basic_auth("admin", Some("admin"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.headers()["x-pg-hash"], versio.hash.as_str());
+            continguts.push(res.text().await.unwrap());
+        }
+        assert_eq!(continguts, vec!["v3", "v2"]);
+        // Restaurem la v2: passa a ser la actual i la v4 queda a l'historial
+        let res = client
+            .post(format!("{base}/versions/{}/restaurar", versions[1].id))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            std::fs::read_to_string(test_path.join("Stellaris/perfil 1/save1.sav")).unwrap(),
+            "v2"
+        );
+        let versions = get_versions().await;
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].mida, 2);
+        // Una versio que no existeix
+        let res = client
+            .post(format!("{base}/versions/1_000000000_abcd/restaurar"))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 404);
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_usuaris() {
+        // Cada usuari del fitxer d'usuaris te les seves propies partides
+        let test_path = std::env::temp_dir().join("serpg_test_usuaris_api");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(&test_path).unwrap();
+        let mut usuaris = UsuarisConfig::default();
+        usuaris.afegir("edu", "pastanaga").unwrap();
+        usuaris.afegir("anna", "bullida").unwrap();
+        let server_path = test_path.to_str().unwrap().to_string();
+        tokio::spawn(async move {
+            SerPG::new(server_path)
+                .with_usuari("admin".to_string(), "admin".to_string())
+                .with_usuaris(usuaris)
+                .start(Some("3005".to_string()))
+                .await;
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let client = reqwest::Client::new();
+        let url = "http://127.0.0.1:3005/api/v1";
+        let get = |usuari: &'static str, contrasenya: &'static str, endpoint: &'static str| {
+            client
+                .get(format!("{url}/{endpoint}"))
+                .basic_auth(usuari, Some(contrasenya))
+                .send()
+        };
+        assert_eq!(get("edu", "pastanaga", "test").await.unwrap().status(), 200);
+        assert_eq!(get("edu", "bullida", "test").await.unwrap().status(), 401);
+        assert_eq!(get("pep", "pastanaga", "test").await.unwrap().status(), 401);
+        let res = client
+            .put(format!(
+                "{url}/videojocs/Stellaris/partides/save1.sav/binari"
+            ))
+            .basic_auth("edu", Some("pastanaga"))
+            .body("Partida de l'edu")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert!(test_path.join(".usuaris/edu/Stellaris/save1.sav").exists());
+        let res = get("edu", "pastanaga", "videojocs").await.unwrap();
+        assert_eq!(
+            res.text().await.unwrap(),
+            r#"[{"id":"Stellaris","nom":"Stellaris"}]"#
+        );
+        // Ni l'anna ni l'usuari de linia de comandes veuen les partides de l'edu
+        let res = get("anna", "bullida", "videojocs").await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "[]");
+        let res = make_get_request_port("3005", "videojocs").await;
+        assert_eq!(res, "[]");
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_ids_maliciosos() {
+        // Cap ruta ha de poder llegir, escriure o eliminar fitxers fora de l'arrel
+        let test_path = std::env::temp_dir().join("serpg_test_rutes");
+        let _ = remove_dir_all(&test_path);
+        let arrel = test_path.join("arrel");
+        std::fs::create_dir_all(arrel.join("Stellaris")).unwrap();
+        std::fs::write(test_path.join("secret.txt"), "secret").unwrap();
+        std::fs::write(arrel.join("secret.txt"), "secret").unwrap();
+        start_server(arrel.to_str().unwrap().to_string(), "3006").await;
+        let client = reqwest::Client::new();
+        let url = "http://127.0.0.1:3006/api/v1/videojocs";
+        let videojocs_maliciosos = [
+            "../arrel",
+            ".historial",
+            ".usuaris",
+            "CON",
+            "a\\..\\..",
+            "/tmp",
+            "a:b",
+            "COM0",
+            "Joc.",
+        ];
+        let partides_malicioses = [
+            "../secret.txt",
+            "../../secret.txt",
+            "perfil/../../../secret.txt",
+            "/etc/passwd",
+            "..\\..\\secret.txt",
+            "perfil/.serpg-tmp-save",
+            "nul",
+            // Noms que no es podrien descarregar a Windows
+            "a:b",
+            "save?.sav",
+            "save.",
+            "perfil /save.sav",
+            "COM0",
+            "lpt¹.sav",
+        ];
+        let mut peticions = Vec::new();
+        for v in videojocs_maliciosos {
+            let v = encode(v);
+            peticions.push(client.get(format!("{url}/{v}/partides")));
+            peticions.push(client.get(format!("{url}/{v}/partides/save.sav/binari")));
+            peticions.push(
+                client
+                    .put(format!("{url}/{v}/partides/save.sav/binari"))
+                    .body("x"),
+            );
+            peticions.push(client.delete(format!("{url}/{v}/partides/save.sav")));
+        }
+        for p in partides_malicioses {
+            let v = "Stellaris";
+            peticions.push(client.get(format!("{url}/{v}/partides/{}/contingut", encode(p))));
+            peticions.push(client.get(format!("{url}/{v}/partides/{}/binari", encode(p))));
+            peticions.push(
+                client
+                    .put(format!("{url}/{v}/partides/{}/binari", encode(p)))
+                    .body("x"),
+            );
+            peticions.push(client.delete(format!("{url}/{v}/partides/{}", encode(p))));
+            peticions.push(client.get(format!("{url}/{v}/partides/{}/versions", encode(p))));
+            peticions.push(client.get(format!(
+                "{url}/{v}/partides/{}/versions/1_000000000_ab/binari",
+                encode(p)
+            )));
+            peticions.push(client.post(format!(
+                "{url}/{v}/partides/{}/versions/1_000000000_ab/restaurar",
+                encode(p)
+            )));
+            peticions.push(client.post(format!("{url}/{v}/partides")).json(
+                &PartidaGuardadaContingutAPI {
+                    nom: p.to_string(),
+                    contingut: "x".as_bytes().to_vec(),
+                    hash: None,
+                },
+            ));
+        }
+        // Ids de versio que intenten sortir del directori de l'historial
+        for versio in [
+            "..%2F..%2Fsecret.txt",
+            "1_000000000_ab%2F..%2F..",
+            "secret.txt",
+        ] {
+            peticions.push(client.get(format!(
+                "{url}/Stellaris/partides/save.sav/versions/{versio}/binari"
+            )));
+        }
+        for peticio in peticions {
+            let res = peticio
+                .basic_auth("admin", Some("admin"))
+                .send()
+                .await
+                .unwrap();
+            let status = res.status();
+            let url = res.url().to_string();
+            assert_eq!(status, 400, "{url}");
+        }
+        assert_eq!(
+            std::fs::read_to_string(test_path.join("secret.txt")).unwrap(),
+            "secret"
+        );
+        assert_eq!(
+            std::fs::read_to_string(arrel.join("secret.txt")).unwrap(),
+            "secret"
+        );
+        // No s'ha creat res fora dels directoris dels videojocs
+        let mut creats: Vec<String> = std::fs::read_dir(&test_path)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_str().unwrap().to_string())
+            .collect();
+        creats.sort();
+        assert_eq!(creats, vec!["arrel", "secret.txt"]);
+        let mut creats: Vec<String> = std::fs::read_dir(&arrel)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_str().unwrap().to_string())
+            .collect();
+        creats.sort();
+        assert_eq!(creats, vec!["Stellaris", "secret.txt"]);
+        assert_eq!(
+            std::fs::read_dir(arrel.join("Stellaris")).unwrap().count(),
+            0
+        );
+        let _ = remove_dir_all(&test_path);
+    }
+    #[tokio::test]
+    async fn test_api_errors() {
+        // Els errors es responen amb l'estat corresponent i un cos JSON amb el codi i el missatge
+        let test_path = std::env::temp_dir().join("serpg_test_errors");
+        let _ = remove_dir_all(&test_path);
+        std::fs::create_dir_all(test_path.join("Stellaris/perfil 1")).unwrap();
+        start_server(test_path.to_str().unwrap().to_string(), "3007").await;
+        let client = reqwest::Client::new();
+        let url = "http://127.0.0.1:3007/api/v1/videojocs/Stellaris/partides";
+        let comprovar = |res: reqwest::Response, status: u16, codi: &'static str| async move {
+            assert_eq!(res.status(), status);
+            let error: ErrorAPI = res.json().await.unwrap();
+            assert_eq!(error.codi, codi);
+            assert!(!error.missatge.is_empty());
+        };
+        // Una partida que no existeix
+        for endpoint in ["no_existeix.sav/binari", "no_existeix.sav/contingut"] {
+            let res = client
+                .get(format!("{url}/{endpoint}"))
+                .basic_auth("admin", Some("admin"))
+                .send()
+                .await
+                .unwrap();
+            comprovar(res, 404, "no_trobat").await;
+        }
+        // Una versio que no existeix
+        let res = client
+            .get(format!("{url}/save.sav/versions/1_000000000_ab/binari"))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        comprovar(res, 404, "no_trobat").await;
+        // Pujar una partida on ja hi ha un directori
+        let res = client
+            .put(format!("{url}/{}/binari", encode("perfil 1")))
+            .basic_auth("admin", Some("admin"))
+            .body("x")
+            .send()
+            .await
+            .unwrap();
+        comprovar(res, 409, "conflicte").await;
+        // Credencials incorrectes
+        let res = client
+            .get(url)
+            .basic_auth("admin", Some("patata"))
+            .send()
+            .await
+            .unwrap();
+        comprovar(res, 401, "no_autoritzat").await;
+        // Un id no valid
+        let res = client
+            .get(format!("{url}/{}/binari", encode("../secret")))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .await
+            .unwrap();
+        comprovar(res, 400, "peticio_incorrecta").await;
+        let _ = remove_dir_all(&test_path);
+    }
 }
@@ -0,0 +1,199 @@
+use crate::error::ErrorSerPG;
+use crate::historial;
+use crate::index;
+use std::path::{Component, Path, PathBuf};
+
+// Totes les rutes del disc que es construeixen a partir dels ids de la URL passen per aqui.
+// Un id que no es valid es respon amb un 400 (peticio_incorrecta) abans de tocar el sistema de fitxers.
+
+// Prefix reservat per fitxers interns del servidor dins els directoris dels videojocs
+pub const PREFIX_RESERVAT: &str = ".serpg-";
+
+// Noms de dispositiu de Windows. Tambe amb els digits en superindex, que Windows tracta igual.
+const NOMS_RESERVATS_WINDOWS: [&str; 30] = [
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6",
+    "LPT7", "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+// Caracters que no poden anar en un nom de fitxer a Windows (a mes dels separadors i de control)
+const CARACTERS_INVALIDS_WINDOWS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+fn component_valid(nom: &str) -> bool {
+    // Un sol nom de fitxer o directori, sense separadors ni noms especials. Ha de ser valid a tots els
+    // sistemes dels clients: una partida pujada des de linux s'ha de poder descarregar a Windows.
+    if nom.is_empty() || nom == "." || nom == ".." || nom.len() > 255 {
+        return false;
+    }
+    if nom
+        .chars()
+        .any(|c| c == '/' || c == '\\' || c.is_control() || CARACTERS_INVALIDS_WINDOWS.contains(&c))
+    {
+        return false;
+    }
+    // Windows treu els punts i espais del final: "save." i "save" serien el mateix fitxer
+    if nom.ends_with('.') || nom.ends_with(' ') {
+        return false;
+    }
+    if nom.starts_with(PREFIX_RESERVAT) {
+        return false;
+    }
+    // Noms de dispositiu de Windows, amb o sense extensio (p.ex. "CON" o "nul.txt")
+    let base = nom.split('.').next().unwrap_or_default().trim_end();
+    !NOMS_RESERVATS_WINDOWS
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(base))
+}
+
+pub fn validar_videojoc_id(videojoc_id: &str) -> Result<(), ErrorSerPG> {
+    // Els directoris que comencen per "." a l'arrel son interns del servidor (historial, usuaris...)
+    if !component_valid(videojoc_id) || videojoc_id.starts_with('.') {
+        return Err(ErrorSerPG::PeticioIncorrecta(format!(
+            "\"{videojoc_id}\" no es un id de videojoc valid"
+        )));
+    }
+    Ok(())
+}
+
+pub fn validar_partida_id(partida_id: &str) -> Result<(), ErrorSerPG> {
+    // El id de la partida es una ruta relativa separada per "/". Cada tros ha de ser un nom valid,
+    // aixi es rebutgen rutes absolutes ("/etc/passwd" te un primer tros buit) i els "..".
+    if !partida_id.split('/').all(component_valid) {
+        return Err(ErrorSerPG::PeticioIncorrecta(format!(
+            "\"{partida_id}\" no es un id de partida valid"
+        )));
+    }
+    Ok(())
+}
+
+fn dins_de(path: PathBuf, arrel: &Path) -> Result<PathBuf, ErrorSerPG> {
+    // Comprovacio extra: el resultat nomes pot tenir components normals per sota de l'arrel
+    let fora =
+        || ErrorSerPG::PeticioIncorrecta("La ruta surt del directori del servidor".to_string());
+    let relatiu = path.strip_prefix(arrel).map_err(|_| fora())?;
+    if !relatiu
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(fora());
+    }
+    Ok(path)
+}
+
+pub fn path_videojoc(arrel: &str, videojoc_id: &str) -> Result<PathBuf, ErrorSerPG> {
+    validar_videojoc_id(videojoc_id)?;
+    let arrel = Path::new(arrel);
+    dins_de(arrel.join(videojoc_id), arrel)
+}
+
+pub fn path_partida(
+    arrel: &str,
+    videojoc_id: &str,
+    partida_id: &str,
+) -> Result<PathBuf, ErrorSerPG> {
+    validar_partida_id(partida_id)?;
+    let videojoc = path_videojoc(arrel, videojoc_id)?;
+    dins_de(videojoc.join(partida_id), Path::new(arrel))
+}
+
+pub fn path_versions(
+    arrel: &str,
+    videojoc_id: &str,
+    partida_id: &str,
+) -> Result<PathBuf, ErrorSerPG> {
+    validar_videojoc_id(videojoc_id)?;
+    validar_partida_id(partida_id)?;
+    let arrel = Path::new(arrel);
+    dins_de(
+        historial::dir_versions(arrel, videojoc_id, partida_id),
+        arrel,
+    )
+}
+
+pub fn path_index(arrel: &str, videojoc_id: &str) -> Result<PathBuf, ErrorSerPG> {
+    validar_videojoc_id(videojoc_id)?;
+    let arrel = Path::new(arrel);
+    dins_de(index::fitxer_index(arrel, videojoc_id), arrel)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_validar_videojoc_id() {
+        assert!(validar_videojoc_id("Napoleón TW HD").is_ok());
+        assert!(validar_videojoc_id("Mount & blade Warband 2").is_ok());
+        for id in [
+            "",
+            ".",
+            "..",
+            "../etc",
+            "/etc",
+            "a/b",
+            "a\\..\\b",
+            ".historial",
+            ".usuaris",
+            "CON",
+            "nul.txt",
+            "joc\0",
+            "joc\n",
+        ] {
+            assert!(validar_videojoc_id(id).is_err(), "{id:?}");
+        }
+    }
+    #[test]
+    fn test_validar_partida_id() {
+        assert!(validar_partida_id("save1.txt").is_ok());
+        assert!(validar_partida_id("perfil 1/slot 2/save2.sav").is_ok());
+        assert!(validar_partida_id(".config/save").is_ok());
+        for id in [
+            "",
+            "..",
+            "../secret.txt",
+            "perfil/../../secret.txt",
+            "perfil/./save",
+            "/etc/passwd",
+            "perfil//save",
+            "perfil/",
+            "..\\secret.txt",
+            "C:\\Windows\\win.ini",
+            "perfil/aux",
+            "perfil/.serpg-tmp-save",
+            "save\0.txt",
+            "save ",
+            "perfil /save.sav",
+            "CON .txt",
+        ] {
+            assert!(validar_partida_id(id).is_err(), "{id:?}");
+        }
+    }
+    #[test]
+    fn test_noms_partides_compartits() {
+        // La mateixa taula la comprova CliPG (videojoc/partida_guardada.rs)
+        let taula = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/noms_partides.txt"),
+        )
+        .unwrap();
+        for linia in taula.lines().filter(|l| !l.starts_with('#')) {
+            let (esperat, nom) = linia.split_once(' ').unwrap();
+            assert_eq!(validar_partida_id(nom).is_ok(), esperat == "ok", "{nom:?}");
+        }
+    }
+    #[test]
+    fn test_path_partida() {
+        assert_eq!(
+            path_partida("/srv/pg", "Stellaris", "perfil 1/save.sav"),
+            Ok(PathBuf::from("/srv/pg/Stellaris/perfil 1/save.sav"))
+        );
+        assert_eq!(
+            path_versions("/srv/pg", "Stellaris", "save.sav"),
+            Ok(PathBuf::from("/srv/pg/.historial/Stellaris/save.sav"))
+        );
+        assert!(path_partida("/srv/pg", "..", "save.sav").is_err());
+        assert!(path_partida("/srv/pg", "Stellaris", "../../../etc/passwd").is_err());
+        assert!(path_versions("/srv/pg", "Stellaris", "../../x").is_err());
+        assert_eq!(
+            path_index("/srv/pg", "Stellaris"),
+            Ok(PathBuf::from("/srv/pg/.index/Stellaris.json"))
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::{pem::PemObject, CertificateDer};
+use sha2::{Digest, Sha256};
+use std::io;
+
+// HTTPS opcional: certificat i clau en format PEM. Amb SIGHUP es tornen a llegir els fitxers
+// (p.ex. despres de renovar el certificat) sense aturar el servidor.
+
+pub async fn carregar(cert: &str, key: &str) -> io::Result<RustlsConfig> {
+    // rustls necessita un proveidor criptografic per defecte
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    RustlsConfig::from_pem_file(cert, key).await
+}
+
+pub fn empremta(cert: &str) -> io::Result<String> {
+    // SHA-256 del certificat del servidor (el primer del fitxer), en el format de
+    // `openssl x509 -noout -fingerprint -sha256`. Es el valor que es pot fixar al client.
+    let der = CertificateDer::from_pem_file(cert).map_err(io::Error::other)?;
+    let hash = Sha256::digest(der.as_ref());
+    Ok(hash
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+#[cfg(unix)]
+pub fn recarregar_amb_sighup(config: RustlsConfig, cert: String, key: String) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut senyal = match signal(SignalKind::hangup()) {
+        Ok(senyal) => senyal,
+        Err(e) => {
+            // El servidor funciona igual, pero per canviar el certificat caldra reiniciar-lo
+            println!("No es podra recarregar el certificat amb SIGHUP: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while senyal.recv().await.is_some() {
+            // Si els fitxers nous no son valids es continua amb el certificat que hi havia
+            match config.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => println!(
+                    "🔒 Certificat recarregat (SHA-256 {})",
+                    empremta(&cert).unwrap_or_default()
+                ),
+                Err(e) => println!("No s'ha pogut recarregar el certificat {cert}: {e}"),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn recarregar_amb_sighup(_config: RustlsConfig, _cert: String, _key: String) {}
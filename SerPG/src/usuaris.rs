@@ -0,0 +1,204 @@
+use crate::escriptura;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+// Directori (dins de videojocs_path) amb les partides de cada usuari del fitxer d'usuaris:
+// .usuaris/{nom}/{videojoc_id}/{partida_id}
+pub const DIR_USUARIS: &str = ".usuaris";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UsuariConfig {
+    pub nom: String,
+    // Hash argon2 en format PHC ("$argon2id$v=19$...")
+    pub contrasenya: String,
+}
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UsuarisConfig {
+    #[serde(default)]
+    pub usuaris: Vec<UsuariConfig>,
+}
+impl UsuarisConfig {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if !path.exists() {
+            return Ok(UsuarisConfig::default());
+        }
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = toml::to_string_pretty(self).map_err(io::Error::other)?;
+        escriptura::escriure(path, content.as_bytes())
+    }
+    pub fn afegir(&mut self, nom: &str, contrasenya: &str) -> Result<bool, String> {
+        // Retorna true si l'usuari es nou i false si nomes se li ha canviat la contrasenya
+        if !nom_valid(nom) {
+            return Err(format!("\"{nom}\" no es un nom d'usuari valid."));
+        }
+        let hash = hash_contrasenya(contrasenya);
+        if let Some(u) = self.usuaris.iter_mut().find(|u| u.nom == nom) {
+            u.contrasenya = hash;
+            return Ok(false);
+        }
+        self.usuaris.push(UsuariConfig {
+            nom: nom.to_string(),
+            contrasenya: hash,
+        });
+        Ok(true)
+    }
+    pub fn eliminar(&mut self, nom: &str) -> Result<(), String> {
+        let len = self.usuaris.len();
+        self.usuaris.retain(|u| u.nom != nom);
+        if self.usuaris.len() == len {
+            Err(format!("\"{nom}\" no es un usuari."))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn nom_valid(nom: &str) -> bool {
+    // El nom es fa servir com a directori de l'usuari
+    !nom.is_empty()
+        && !nom.starts_with('.')
+        && nom
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+pub fn hash_contrasenya(contrasenya: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(contrasenya.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+pub fn dir_usuari(arrel: &Path, nom: &str) -> PathBuf {
+    arrel.join(DIR_USUARIS).join(nom)
+}
+
+#[derive(Default)]
+pub struct Usuaris {
+    config: UsuarisConfig,
+    // Parelles (usuari, sha256 de la contrasenya) ja verificades. Argon2 es lent a proposit
+    // i el client fa moltes peticions seguides amb les mateixes credencials.
+    verificats: Mutex<HashSet<(String, String)>>,
+}
+impl Usuaris {
+    pub fn new(config: UsuarisConfig) -> Self {
+        Usuaris {
+            config,
+            verificats: Mutex::new(HashSet::new()),
+        }
+    }
+    fn clau(nom: &str, contrasenya: &str) -> (String, String) {
+        (
+            nom.to_string(),
+            hex::encode(Sha256::digest(contrasenya.as_bytes())),
+        )
+    }
+    pub async fn verificar_async(self: &Arc<Self>, nom: &str, contrasenya: &str) -> bool {
+        // Per les peticions: argon2 triga i bloquejaria el fil de tokio que atén la peticio.
+        // Les credencials ja verificades no cal enviar-les a un altre fil.
+        if self
+            .verificats
+            .lock()
+            .unwrap()
+            .contains(&Self::clau(nom, contrasenya))
+        {
+            return true;
+        }
+        let usuaris = Arc::clone(self);
+        let (nom, contrasenya) = (nom.to_string(), contrasenya.to_string());
+        tokio::task::spawn_blocking(move || usuaris.verificar(&nom, &contrasenya))
+            .await
+            .unwrap_or(false)
+    }
+    pub fn verificar(&self, nom: &str, contrasenya: &str) -> bool {
+        let Some(usuari) = self.config.usuaris.iter().find(|u| u.nom == nom) else {
+            return false;
+        };
+        let clau = Self::clau(nom, contrasenya);
+        if self.verificats.lock().unwrap().contains(&clau) {
+            return true;
+        }
+        let Ok(hash) = PasswordHash::new(&usuari.contrasenya) else {
+            return false;
+        };
+        let correcte = Argon2::default()
+            .verify_password(contrasenya.as_bytes(), &hash)
+            .is_ok();
+        if correcte {
+            self.verificats.lock().unwrap().insert(clau);
+        }
+        correcte
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[test]
+    fn test_afegir_eliminar_i_verificar() {
+        let mut config = UsuarisConfig::default();
+        assert_eq!(config.afegir("edu", "pastanaga"), Ok(true));
+        assert_eq!(config.afegir("anna", "bullida"), Ok(true));
+        assert!(config.afegir("../edu", "x").is_err());
+        assert!(config.afegir(".historial", "x").is_err());
+        assert!(config.afegir("", "x").is_err());
+        // La contrasenya no es guarda en clar
+        assert!(config.usuaris[0].contrasenya.starts_with("$argon2"));
+        assert!(!config.usuaris[0].contrasenya.contains("pastanaga"));
+        let usuaris = Usuaris::new(config.clone());
+        assert!(usuaris.verificar("edu", "pastanaga"));
+        assert!(usuaris.verificar("edu", "pastanaga"));
+        assert!(!usuaris.verificar("edu", "bullida"));
+        assert!(!usuaris.verificar("pep", "pastanaga"));
+        // Canviar la contrasenya no afegeix cap usuari
+        assert_eq!(config.afegir("edu", "nova"), Ok(false));
+        assert_eq!(config.usuaris.len(), 2);
+        assert!(Usuaris::new(config.clone()).verificar("edu", "nova"));
+        assert!(config.eliminar("pep").is_err());
+        config.eliminar("edu").unwrap();
+        assert_eq!(config.usuaris.len(), 1);
+        assert!(!Usuaris::new(config).verificar("edu", "nova"));
+    }
+    #[tokio::test]
+    async fn test_verificar_async() {
+        let mut config = UsuarisConfig::default();
+        config.afegir("edu", "pastanaga").unwrap();
+        let usuaris = Arc::new(Usuaris::new(config));
+        assert!(usuaris.verificar_async("edu", "pastanaga").await);
+        assert!(usuaris.verificar_async("edu", "pastanaga").await);
+        assert!(!usuaris.verificar_async("edu", "bullida").await);
+        assert!(!usuaris.verificar_async("pep", "pastanaga").await);
+    }
+    #[test]
+    fn test_load_i_save() {
+        let path = std::env::temp_dir().join("serpg_test_usuaris/usuaris.toml");
+        let _ = fs::remove_file(&path);
+        // Si el fitxer no existeix no hi ha usuaris
+        assert_eq!(
+            UsuarisConfig::load(&path).unwrap(),
+            UsuarisConfig::default()
+        );
+        let mut config = UsuarisConfig::default();
+        config.afegir("edu", "pastanaga").unwrap();
+        config.save(&path).unwrap();
+        assert_eq!(UsuarisConfig::load(&path).unwrap(), config);
+        let _ = fs::remove_file(&path);
+    }
+}